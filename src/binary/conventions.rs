@@ -1,8 +1,7 @@
 use crate::binary::decoder::Decoder;
-use crate::errors::RuntimeError;
-use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use crate::errors::DecodeError;
+use crate::leb128;
+use std::io::Read;
 
 // Vectors: https://webassembly.github.io/spec/core/binary/conventions.html#vectors
 #[derive(Debug, Clone, PartialEq)]
@@ -14,21 +13,14 @@ impl<T> Decoder for Vectors<T>
 where
     T: Decoder,
 {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
-        let mut num = [0; 1];
-        let n = reader.read(&mut num)?;
-        if n == 0 {
-            Err(RuntimeError::InvalidWasmError(
-                "invalid vectors".to_string(),
-            ))?;
-        }
+        let (mut size, num) = leb128::decode::u32(reader)?;
 
-        let mut size = 1;
         let mut vecs: Vec<T> = vec![];
-        for _ in 0..num[0] {
+        for _ in 0..num {
             let (n, res) = T::decode(reader)?;
             vecs.push(*res);
             size += n;
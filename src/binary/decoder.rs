@@ -1,9 +1,8 @@
-use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use crate::errors::DecodeError;
+use std::io::Read;
 
 pub trait Decoder {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read;
 }
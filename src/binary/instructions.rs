@@ -1,83 +1,702 @@
+use crate::binary::conventions::Vectors;
 use crate::binary::decoder::Decoder;
-use crate::errors::RuntimeError;
+use crate::errors::DecodeError;
 use crate::leb128;
-use crate::structure::instructions::Instr::{
-    F32Const, F64Const, I32Add, I32Const, I32Mul, I32Sub, I64Const, LocalGet, LocalSet,
+use crate::structure::instructions::Instr::*;
+use crate::structure::instructions::{BlockType, Expr, Instr, MemArg};
+use crate::structure::modules::{
+    DataIdx, ElemIdx, FuncIdx, GlobalIdx, LabelIdx, LocalIdx, TableIdx, TypeIdx,
 };
-use crate::structure::instructions::{Expr, Instr};
-use crate::structure::modules::LocalIdx;
-use std::error::Error;
+use crate::structure::types::{RefType, ValType};
 use std::io::Read;
 
+const END: u8 = 0x0b;
+const ELSE: u8 = 0x05;
+
+// Bounds the nesting of blocks, loops and ifs, which are decoded recursively.
+const MAX_NESTING: usize = 512;
+
 impl Decoder for Expr {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
-        let mut size: usize = 0;
-        let mut expr: Vec<Instr> = vec![];
-        loop {
-            let mut opcode = [0; 1];
-            reader.read_exact(&mut opcode)?;
-
-            let (n, instr) = match opcode[0] {
-                0x0b => break,
-
-                // Variable Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#variable-instructions
-                0x20 => local_get(reader)?,
-                0x21 => local_set(reader)?,
-
-                // Numeric Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#numeric-instructions
-                0x41 => i32_const(reader)?,
-
-                0x6a => i32_add()?,
-                0x6b => i32_sub()?,
-                0x6c => i32_mul()?,
-
-                _ => Err(RuntimeError::InvalidWasmError(
-                    "not implemented".to_string(),
-                ))?,
-            };
-            expr.push(*instr);
-            size += n;
+        let (size, expr) = expr(reader, &mut vec![])?;
+        Ok((size, Box::new(expr)))
+    }
+}
+
+// Decodes an expression, recording the offset of each of its instructions from the
+// start of the expression in the order they appear.
+pub fn expr<R>(reader: &mut R, offsets: &mut Vec<usize>) -> Result<(usize, Expr), DecodeError>
+where
+    R: Read,
+{
+    let (size, expr, terminator) = instrs(reader, 0, 0, offsets)?;
+    if terminator != END {
+        Err(DecodeError::UnexpectedElse)?;
+    }
+
+    Ok((size, Expr(expr)))
+}
+
+// Decodes a sequence of instructions up to the `end` or `else` opcode closing it.
+// The terminating opcode is returned alongside the instructions. `pos` is the offset of
+// the sequence from the start of the expression.
+fn instrs<R>(
+    reader: &mut R,
+    depth: usize,
+    pos: usize,
+    offsets: &mut Vec<usize>,
+) -> Result<(usize, Vec<Instr>, u8), DecodeError>
+where
+    R: Read,
+{
+    if depth > MAX_NESTING {
+        Err(DecodeError::NestingTooDeep)?;
+    }
+
+    let mut size: usize = 0;
+    let mut instrs: Vec<Instr> = vec![];
+    loop {
+        let mut opcode = [0; 1];
+        reader.read_exact(&mut opcode)?;
+        size += 1;
+        if opcode[0] == END || opcode[0] == ELSE {
+            return Ok((size, instrs, opcode[0]));
+        }
+        offsets.push(pos + size - 1);
+
+        // Blocks are decoded here so that the frames of the recursion stay small.
+        let (n, instr) = match opcode[0] {
+            0x02 => block(reader, depth, pos + size, offsets)?,
+            0x03 => loop_(reader, depth, pos + size, offsets)?,
+            0x04 => if_(reader, depth, pos + size, offsets)?,
+            opcode => instr(opcode, reader)?,
+        };
+        instrs.push(*instr);
+        size += n;
+    }
+}
+
+// Decodes the instruction with the given opcode, other than a block, loop or if.
+fn instr<R>(opcode: u8, reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let instr = match opcode {
+        // Control Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#control-instructions
+        0x00 => (0, Box::new(Unreachable)),
+        0x01 => (0, Box::new(Nop)),
+        0x0c => br(reader)?,
+        0x0d => br_if(reader)?,
+        0x0e => br_table(reader)?,
+        0x0f => (0, Box::new(Return)),
+        0x10 => call(reader)?,
+        0x11 => call_indirect(reader)?,
+
+        // Parametric Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#parametric-instructions
+        0x1a => (0, Box::new(Drop)),
+        0x1b => (0, Box::new(Select)),
+        0x1c => select_t(reader)?,
+
+        // Variable Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#variable-instructions
+        0x20 => local_get(reader)?,
+        0x21 => local_set(reader)?,
+        0x22 => local_tee(reader)?,
+        0x23 => global_get(reader)?,
+        0x24 => global_set(reader)?,
+
+        // Table Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#table-instructions
+        0x25 => table(reader, TableGet)?,
+        0x26 => table(reader, TableSet)?,
+
+        // Memory Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#memory-instructions
+        0x28 => memory(reader, I32Load)?,
+        0x29 => memory(reader, I64Load)?,
+        0x2a => memory(reader, F32Load)?,
+        0x2b => memory(reader, F64Load)?,
+        0x2c => memory(reader, I32Load8S)?,
+        0x2d => memory(reader, I32Load8U)?,
+        0x2e => memory(reader, I32Load16S)?,
+        0x2f => memory(reader, I32Load16U)?,
+        0x30 => memory(reader, I64Load8S)?,
+        0x31 => memory(reader, I64Load8U)?,
+        0x32 => memory(reader, I64Load16S)?,
+        0x33 => memory(reader, I64Load16U)?,
+        0x34 => memory(reader, I64Load32S)?,
+        0x35 => memory(reader, I64Load32U)?,
+        0x36 => memory(reader, I32Store)?,
+        0x37 => memory(reader, I64Store)?,
+        0x38 => memory(reader, F32Store)?,
+        0x39 => memory(reader, F64Store)?,
+        0x3a => memory(reader, I32Store8)?,
+        0x3b => memory(reader, I32Store16)?,
+        0x3c => memory(reader, I64Store8)?,
+        0x3d => memory(reader, I64Store16)?,
+        0x3e => memory(reader, I64Store32)?,
+        0x3f => memory_zero(reader, MemorySize)?,
+        0x40 => memory_zero(reader, MemoryGrow)?,
+
+        // Numeric Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#numeric-instructions
+        0x41 => i32_const(reader)?,
+        0x42 => i64_const(reader)?,
+        0x43 => f32_const(reader)?,
+        0x44 => f64_const(reader)?,
+
+        0x45 => (0, Box::new(I32Eqz)),
+        0x46 => (0, Box::new(I32Eq)),
+        0x47 => (0, Box::new(I32Ne)),
+        0x48 => (0, Box::new(I32LtS)),
+        0x49 => (0, Box::new(I32LtU)),
+        0x4a => (0, Box::new(I32GtS)),
+        0x4b => (0, Box::new(I32GtU)),
+        0x4c => (0, Box::new(I32LeS)),
+        0x4d => (0, Box::new(I32LeU)),
+        0x4e => (0, Box::new(I32GeS)),
+        0x4f => (0, Box::new(I32GeU)),
+
+        0x50 => (0, Box::new(I64Eqz)),
+        0x51 => (0, Box::new(I64Eq)),
+        0x52 => (0, Box::new(I64Ne)),
+        0x53 => (0, Box::new(I64LtS)),
+        0x54 => (0, Box::new(I64LtU)),
+        0x55 => (0, Box::new(I64GtS)),
+        0x56 => (0, Box::new(I64GtU)),
+        0x57 => (0, Box::new(I64LeS)),
+        0x58 => (0, Box::new(I64LeU)),
+        0x59 => (0, Box::new(I64GeS)),
+        0x5a => (0, Box::new(I64GeU)),
+
+        0x5b => (0, Box::new(F32Eq)),
+        0x5c => (0, Box::new(F32Ne)),
+        0x5d => (0, Box::new(F32Lt)),
+        0x5e => (0, Box::new(F32Gt)),
+        0x5f => (0, Box::new(F32Le)),
+        0x60 => (0, Box::new(F32Ge)),
+
+        0x61 => (0, Box::new(F64Eq)),
+        0x62 => (0, Box::new(F64Ne)),
+        0x63 => (0, Box::new(F64Lt)),
+        0x64 => (0, Box::new(F64Gt)),
+        0x65 => (0, Box::new(F64Le)),
+        0x66 => (0, Box::new(F64Ge)),
+
+        0x67 => (0, Box::new(I32Clz)),
+        0x68 => (0, Box::new(I32Ctz)),
+        0x69 => (0, Box::new(I32Popcnt)),
+        0x6a => (0, Box::new(I32Add)),
+        0x6b => (0, Box::new(I32Sub)),
+        0x6c => (0, Box::new(I32Mul)),
+        0x6d => (0, Box::new(I32DivS)),
+        0x6e => (0, Box::new(I32DivU)),
+        0x6f => (0, Box::new(I32RemS)),
+        0x70 => (0, Box::new(I32RemU)),
+        0x71 => (0, Box::new(I32And)),
+        0x72 => (0, Box::new(I32Or)),
+        0x73 => (0, Box::new(I32Xor)),
+        0x74 => (0, Box::new(I32Shl)),
+        0x75 => (0, Box::new(I32ShrS)),
+        0x76 => (0, Box::new(I32ShrU)),
+        0x77 => (0, Box::new(I32Rotl)),
+        0x78 => (0, Box::new(I32Rotr)),
+
+        0x79 => (0, Box::new(I64Clz)),
+        0x7a => (0, Box::new(I64Ctz)),
+        0x7b => (0, Box::new(I64Popcnt)),
+        0x7c => (0, Box::new(I64Add)),
+        0x7d => (0, Box::new(I64Sub)),
+        0x7e => (0, Box::new(I64Mul)),
+        0x7f => (0, Box::new(I64DivS)),
+        0x80 => (0, Box::new(I64DivU)),
+        0x81 => (0, Box::new(I64RemS)),
+        0x82 => (0, Box::new(I64RemU)),
+        0x83 => (0, Box::new(I64And)),
+        0x84 => (0, Box::new(I64Or)),
+        0x85 => (0, Box::new(I64Xor)),
+        0x86 => (0, Box::new(I64Shl)),
+        0x87 => (0, Box::new(I64ShrS)),
+        0x88 => (0, Box::new(I64ShrU)),
+        0x89 => (0, Box::new(I64Rotl)),
+        0x8a => (0, Box::new(I64Rotr)),
+
+        0x8b => (0, Box::new(F32Abs)),
+        0x8c => (0, Box::new(F32Neg)),
+        0x8d => (0, Box::new(F32Ceil)),
+        0x8e => (0, Box::new(F32Floor)),
+        0x8f => (0, Box::new(F32Trunc)),
+        0x90 => (0, Box::new(F32Nearest)),
+        0x91 => (0, Box::new(F32Sqrt)),
+        0x92 => (0, Box::new(F32Add)),
+        0x93 => (0, Box::new(F32Sub)),
+        0x94 => (0, Box::new(F32Mul)),
+        0x95 => (0, Box::new(F32Div)),
+        0x96 => (0, Box::new(F32Min)),
+        0x97 => (0, Box::new(F32Max)),
+        0x98 => (0, Box::new(F32Copysign)),
+
+        0x99 => (0, Box::new(F64Abs)),
+        0x9a => (0, Box::new(F64Neg)),
+        0x9b => (0, Box::new(F64Ceil)),
+        0x9c => (0, Box::new(F64Floor)),
+        0x9d => (0, Box::new(F64Trunc)),
+        0x9e => (0, Box::new(F64Nearest)),
+        0x9f => (0, Box::new(F64Sqrt)),
+        0xa0 => (0, Box::new(F64Add)),
+        0xa1 => (0, Box::new(F64Sub)),
+        0xa2 => (0, Box::new(F64Mul)),
+        0xa3 => (0, Box::new(F64Div)),
+        0xa4 => (0, Box::new(F64Min)),
+        0xa5 => (0, Box::new(F64Max)),
+        0xa6 => (0, Box::new(F64Copysign)),
+
+        0xa7 => (0, Box::new(I32WrapI64)),
+        0xa8 => (0, Box::new(I32TruncF32S)),
+        0xa9 => (0, Box::new(I32TruncF32U)),
+        0xaa => (0, Box::new(I32TruncF64S)),
+        0xab => (0, Box::new(I32TruncF64U)),
+        0xac => (0, Box::new(I64ExtendI32S)),
+        0xad => (0, Box::new(I64ExtendI32U)),
+        0xae => (0, Box::new(I64TruncF32S)),
+        0xaf => (0, Box::new(I64TruncF32U)),
+        0xb0 => (0, Box::new(I64TruncF64S)),
+        0xb1 => (0, Box::new(I64TruncF64U)),
+        0xb2 => (0, Box::new(F32ConvertI32S)),
+        0xb3 => (0, Box::new(F32ConvertI32U)),
+        0xb4 => (0, Box::new(F32ConvertI64S)),
+        0xb5 => (0, Box::new(F32ConvertI64U)),
+        0xb6 => (0, Box::new(F32DemoteF64)),
+        0xb7 => (0, Box::new(F64ConvertI32S)),
+        0xb8 => (0, Box::new(F64ConvertI32U)),
+        0xb9 => (0, Box::new(F64ConvertI64S)),
+        0xba => (0, Box::new(F64ConvertI64U)),
+        0xbb => (0, Box::new(F64PromoteF32)),
+        0xbc => (0, Box::new(I32ReinterpretF32)),
+        0xbd => (0, Box::new(I64ReinterpretF64)),
+        0xbe => (0, Box::new(F32ReinterpretI32)),
+        0xbf => (0, Box::new(F64ReinterpretI64)),
+        0xc0 => (0, Box::new(I32Extend8S)),
+        0xc1 => (0, Box::new(I32Extend16S)),
+        0xc2 => (0, Box::new(I64Extend8S)),
+        0xc3 => (0, Box::new(I64Extend16S)),
+        0xc4 => (0, Box::new(I64Extend32S)),
+
+        0xfc => prefixed(reader)?,
+
+        // Reference Instructions: https://webassembly.github.io/spec/core/binary/instructions.html#reference-instructions
+        0xd0 => ref_null(reader)?,
+        0xd1 => (0, Box::new(RefIsNull)),
+        0xd2 => ref_func(reader)?,
+
+        _ => Err(DecodeError::IllegalOpcode(opcode))?,
+    };
+    Ok(instr)
+}
+
+// Instructions with the 0xFC prefix are identified by a u32 following it.
+fn prefixed<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, opcode) = leb128::decode::u32(reader)?;
+    let (n2, instr) = match opcode {
+        0 => (0, Box::new(I32TruncSatF32S)),
+        1 => (0, Box::new(I32TruncSatF32U)),
+        2 => (0, Box::new(I32TruncSatF64S)),
+        3 => (0, Box::new(I32TruncSatF64U)),
+        4 => (0, Box::new(I64TruncSatF32S)),
+        5 => (0, Box::new(I64TruncSatF32U)),
+        6 => (0, Box::new(I64TruncSatF64S)),
+        7 => (0, Box::new(I64TruncSatF64U)),
+        8 => memory_init(reader)?,
+        9 => data_drop(reader)?,
+        10 => memory_copy(reader)?,
+        11 => memory_zero(reader, MemoryFill)?,
+        12 => table_init(reader)?,
+        13 => elem_drop(reader)?,
+        14 => table_copy(reader)?,
+        15 => table(reader, TableGrow)?,
+        16 => table(reader, TableSize)?,
+        17 => table(reader, TableFill)?,
+        _ => Err(DecodeError::IllegalPrefixedOpcode(opcode))?,
+    };
+    Ok((n1 + n2, instr))
+}
+
+fn block_type<R>(reader: &mut R) -> Result<(usize, BlockType), DecodeError>
+where
+    R: Read,
+{
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+
+    // A block type is either 0x40, a single value type or a type index encoded as
+    // a positive signed 33-bit integer, which never collides with the former two.
+    match buf[0] {
+        0x40 => Ok((1, BlockType::Empty)),
+        0x7f | 0x7e | 0x7d | 0x7c | 0x70 | 0x6f => {
+            let (n, valtype) = ValType::decode(&mut buf.as_ref())?;
+            Ok((n, BlockType::ValType(*valtype)))
+        }
+        _ => {
+            let (n, typeidx) = leb128::decode::i64(&mut buf.as_ref().chain(reader))?;
+            if typeidx < 0 || typeidx > u32::MAX as i64 {
+                Err(DecodeError::InvalidBlockType)?;
+            }
+            Ok((n, BlockType::TypeIdx(TypeIdx(typeidx as u32))))
         }
+    }
+}
+
+fn block<R>(
+    reader: &mut R,
+    depth: usize,
+    pos: usize,
+    offsets: &mut Vec<usize>,
+) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, bt) = block_type(reader)?;
+    let (n2, body, terminator) = instrs(reader, depth + 1, pos + n1, offsets)?;
+    if terminator != END {
+        Err(DecodeError::UnexpectedElse)?;
+    }
+    Ok((n1 + n2, Box::new(Block(bt, body))))
+}
+
+fn loop_<R>(
+    reader: &mut R,
+    depth: usize,
+    pos: usize,
+    offsets: &mut Vec<usize>,
+) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, bt) = block_type(reader)?;
+    let (n2, body, terminator) = instrs(reader, depth + 1, pos + n1, offsets)?;
+    if terminator != END {
+        Err(DecodeError::UnexpectedElse)?;
+    }
+    Ok((n1 + n2, Box::new(Loop(bt, body))))
+}
+
+fn if_<R>(
+    reader: &mut R,
+    depth: usize,
+    pos: usize,
+    offsets: &mut Vec<usize>,
+) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, bt) = block_type(reader)?;
+    let (n2, then, terminator) = instrs(reader, depth + 1, pos + n1, offsets)?;
+    let (n3, else_) = if terminator == ELSE {
+        let (n3, else_, terminator) = instrs(reader, depth + 1, pos + n1 + n2, offsets)?;
+        if terminator != END {
+            Err(DecodeError::UnexpectedElse)?;
+        }
+        (n3, else_)
+    } else {
+        (0, vec![])
+    };
+    Ok((n1 + n2 + n3, Box::new(If(bt, then, else_))))
+}
+
+fn br<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, labelidx) = LabelIdx::decode(reader)?;
+    Ok((n, Box::new(Br(*labelidx))))
+}
 
-        Ok((size, Box::new(Expr(expr))))
+fn br_if<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, labelidx) = LabelIdx::decode(reader)?;
+    Ok((n, Box::new(BrIf(*labelidx))))
+}
+
+fn br_table<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, labels) = Vectors::<LabelIdx>::decode(reader)?;
+    let (n2, default) = LabelIdx::decode(reader)?;
+    Ok((n1 + n2, Box::new(BrTable(labels.0, *default))))
+}
+
+fn call<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, funcidx) = FuncIdx::decode(reader)?;
+    Ok((n, Box::new(Call(*funcidx))))
+}
+
+fn call_indirect<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, typeidx) = TypeIdx::decode(reader)?;
+    let (n2, tableidx) = TableIdx::decode(reader)?;
+    Ok((n1 + n2, Box::new(CallIndirect(*typeidx, *tableidx))))
+}
+
+impl Decoder for LabelIdx {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, labelidx) = leb128::decode::u32(reader)?;
+        Ok((n, Box::new(LabelIdx(labelidx))))
     }
 }
 
-fn local_get<R>(reader: &mut R) -> Result<(usize, Box<Instr>), Box<dyn Error>>
+fn local_get<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
 where
     R: Read,
 {
     let (n, localidx) = leb128::decode::u32(reader)?;
-    Ok((0, Box::new(LocalGet(LocalIdx(localidx)))))
+    Ok((n, Box::new(LocalGet(LocalIdx(localidx)))))
 }
 
-fn local_set<R>(reader: &mut R) -> Result<(usize, Box<Instr>), Box<dyn Error>>
+fn local_set<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
 where
     R: Read,
 {
     let (n, localidx) = leb128::decode::u32(reader)?;
-    Ok((0, Box::new(LocalSet(LocalIdx(localidx)))))
+    Ok((n, Box::new(LocalSet(LocalIdx(localidx)))))
+}
+
+fn local_tee<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, localidx) = leb128::decode::u32(reader)?;
+    Ok((n, Box::new(LocalTee(LocalIdx(localidx)))))
+}
+
+fn global_get<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, globalidx) = leb128::decode::u32(reader)?;
+    Ok((n, Box::new(GlobalGet(GlobalIdx(globalidx)))))
+}
+
+fn global_set<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, globalidx) = leb128::decode::u32(reader)?;
+    Ok((n, Box::new(GlobalSet(GlobalIdx(globalidx)))))
+}
+
+fn select_t<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, valtypes) = Vectors::<ValType>::decode(reader)?;
+    Ok((n, Box::new(SelectT(valtypes.0))))
+}
+
+fn table<R>(
+    reader: &mut R,
+    instr: fn(TableIdx) -> Instr,
+) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, tableidx) = TableIdx::decode(reader)?;
+    Ok((n, Box::new(instr(*tableidx))))
+}
+
+fn table_copy<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, dst) = TableIdx::decode(reader)?;
+    let (n2, src) = TableIdx::decode(reader)?;
+    Ok((n1 + n2, Box::new(TableCopy(*dst, *src))))
+}
+
+fn table_init<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, elemidx) = ElemIdx::decode(reader)?;
+    let (n2, tableidx) = TableIdx::decode(reader)?;
+    Ok((n1 + n2, Box::new(TableInit(*tableidx, *elemidx))))
+}
+
+fn elem_drop<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, elemidx) = ElemIdx::decode(reader)?;
+    Ok((n, Box::new(ElemDrop(*elemidx))))
+}
+
+fn memory<R>(reader: &mut R, instr: fn(MemArg) -> Instr) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, align) = leb128::decode::u32(reader)?;
+    let (n2, offset) = leb128::decode::u32(reader)?;
+    Ok((n1 + n2, Box::new(instr(MemArg { offset, align }))))
+}
+
+// Memory instructions without a memarg carry a zero byte reserved for a memory index.
+fn memory_zero<R>(reader: &mut R, instr: Instr) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    if buf[0] != 0x00 {
+        Err(DecodeError::ZeroByteExpected)?;
+    }
+    Ok((1, Box::new(instr)))
 }
 
-fn i32_const<R>(reader: &mut R) -> Result<(usize, Box<Instr>), Box<dyn Error>>
+fn memory_init<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, dataidx) = DataIdx::decode(reader)?;
+    let (n2, instr) = memory_zero(reader, MemoryInit(*dataidx))?;
+    Ok((n1 + n2, instr))
+}
+
+fn data_drop<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, dataidx) = DataIdx::decode(reader)?;
+    Ok((n, Box::new(DataDrop(*dataidx))))
+}
+
+// memory.copy carries a zero byte for each of its destination and source memories.
+fn memory_copy<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n1, _) = memory_zero(reader, MemoryCopy)?;
+    let (n2, instr) = memory_zero(reader, MemoryCopy)?;
+    Ok((n1 + n2, instr))
+}
+
+fn i32_const<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
 where
     R: Read,
 {
     let (n, num) = leb128::decode::i32(reader)?;
-    Ok((0, Box::new(I32Const(num))))
+    Ok((n, Box::new(I32Const(num))))
+}
+
+fn i64_const<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, num) = leb128::decode::i64(reader)?;
+    Ok((n, Box::new(I64Const(num))))
+}
+
+fn f32_const<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok((4, Box::new(F32Const(f32::from_le_bytes(buf)))))
 }
 
-fn i32_add() -> Result<(usize, Box<Instr>), Box<dyn Error>> {
-    Ok((0, Box::new(I32Add)))
+fn f64_const<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok((8, Box::new(F64Const(f64::from_le_bytes(buf)))))
 }
 
-fn i32_sub() -> Result<(usize, Box<Instr>), Box<dyn Error>> {
-    Ok((0, Box::new(I32Sub)))
+fn ref_null<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, reftype) = RefType::decode(reader)?;
+    Ok((n, Box::new(RefNull(*reftype))))
 }
 
-fn i32_mul() -> Result<(usize, Box<Instr>), Box<dyn Error>> {
-    Ok((0, Box::new(I32Mul)))
+fn ref_func<R>(reader: &mut R) -> Result<(usize, Box<Instr>), DecodeError>
+where
+    R: Read,
+{
+    let (n, funcidx) = leb128::decode::u32(reader)?;
+    Ok((n, Box::new(RefFunc(FuncIdx(funcidx)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks() {
+        let bytes = [
+            0x02, 0x7f, // block (result i32)
+            0x20, 0, // local.get 0
+            0x04, 0x7f, 0x41, 1, 0x05, 0x41, 2, 0x0b, // if (result i32) 1 else 2 end
+            0x0c, 0,    // br 0
+            0x0b, // end
+            0x03, 0x01, 0x0b, // loop (type 1) end
+            0x0b,
+        ];
+        let (n, expr) = Expr::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+
+        let i32 = BlockType::ValType(ValType::NumType(crate::structure::types::NumType::I32));
+        let if_ = If(i32.clone(), vec![I32Const(1)], vec![I32Const(2)]);
+        assert_eq!(
+            expr.0,
+            vec![
+                Block(i32, vec![LocalGet(LocalIdx(0)), if_, Br(LabelIdx(0))]),
+                Loop(BlockType::TypeIdx(TypeIdx(1)), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unexpected_else() {
+        let err = Expr::decode(&mut &[0x01, 0x05, 0x0b][..]).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected else");
+    }
+
+    #[test]
+    fn prefixed() {
+        let (_, expr) = Expr::decode(&mut &[0xfc, 0x07, 0x0b][..]).unwrap();
+        assert_eq!(expr.0, vec![I64TruncSatF64U]);
+
+        let err = Expr::decode(&mut &[0xfc, 0x80, 0x01, 0x0b][..]).unwrap_err();
+        assert_eq!(err.to_string(), "illegal opcode 0xfc 128");
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth| {
+            let mut bytes = [0x02, 0x40].repeat(depth);
+            bytes.extend(vec![0x0b; depth + 1]);
+            Expr::decode(&mut &bytes[..])
+        };
+        assert!(nested(MAX_NESTING).is_ok());
+        let err = nested(MAX_NESTING + 1).unwrap_err();
+        assert_eq!(err.to_string(), "nesting too deep");
+        // Nesting is bounded before it can exhaust the native stack.
+        let err = nested(1_000_000).unwrap_err();
+        assert_eq!(err.to_string(), "nesting too deep");
+    }
 }
@@ -1,99 +1,216 @@
 use crate::binary::conventions::Vectors;
 use crate::binary::decoder::Decoder;
-use crate::errors::RuntimeError;
+use crate::errors::DecodeError;
 use crate::leb128;
-use crate::structure::modules::ExportDesc::Mem;
+use crate::structure::instructions::{Expr, Instr};
 use crate::structure::modules::{
-    Export, ExportDesc, Func, FuncIdx, GlobalIdx, MemIdx, Module, TableIdx, TypeIdx,
+    Custom, Data, DataMode, Elem, ElemMode, Export, ExportDesc, Func, FuncIdx, Global, GlobalIdx,
+    Import, ImportDesc, LocalIdx, Mem, MemIdx, Module, Names, Start, Table, TableIdx, TypeIdx,
 };
-use crate::structure::types::{FuncType, ValType};
+use crate::structure::types::{FuncType, GlobalType, MemType, RefType, TableType, ValType};
 use crate::structure::values::{Byte, Name};
-use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::Read;
 
-pub fn magic(reader: &mut BufReader<File>) -> Result<(), Box<dyn Error>> {
+pub fn magic<R: Read>(reader: &mut R) -> Result<(), DecodeError> {
     let mut buf = [0; 4];
     let magic: [u8; 4] = [0x00, 0x61, 0x73, 0x6d];
-    let n = reader.read(&mut buf)?;
-    if n == 0 || buf != magic {
-        Err(RuntimeError::InvalidWasmError("invalid magic".to_string()))?;
+    reader.read_exact(&mut buf)?;
+    if buf != magic {
+        Err(DecodeError::InvalidMagic)?;
     }
 
     Ok(())
 }
 
-pub fn version(reader: &mut BufReader<File>) -> Result<(), Box<dyn Error>> {
+pub fn version<R: Read>(reader: &mut R) -> Result<(), DecodeError> {
     let mut buf = [0; 4];
     let magic: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
-    let n = reader.read(&mut buf)?;
-    if n == 0 || buf != magic {
-        Err(RuntimeError::InvalidWasmError(
-            "invalid version".to_string(),
-        ))?;
+    reader.read_exact(&mut buf)?;
+    if buf != magic {
+        Err(DecodeError::InvalidVersion)?;
     }
 
     Ok(())
 }
 
-pub fn sections(reader: &mut BufReader<File>) -> Result<Module, Box<dyn Error>> {
+pub fn sections<R: Read>(reader: &mut R) -> Result<Module, DecodeError> {
     let mut module: Module = Default::default();
     let mut func_indices = vec![];
+    let mut data_count = None;
+    let mut last_section = 0;
+    // The offset of the next section from the start of the module, past the preamble.
+    let mut offset = 8;
     loop {
         let mut section_id = [0; 1];
         let n = reader.read(&mut section_id)?;
         if n == 0 {
-            return Ok(module);
+            break;
         }
 
-        let (n, section_size) = leb128::decode::u32(reader)?;
+        // Sections other than custom sections occur at most once, in a fixed order.
+        if section_id[0] != 0 {
+            let order =
+                section_order(section_id[0]).ok_or(DecodeError::InvalidSectionId(section_id[0]))?;
+            if order <= last_section {
+                Err(DecodeError::UnorderedSection(section_id[0]))?;
+            }
+            last_section = order;
+        }
 
-        match section_id[0] {
-            1 => {
-                println!("=== Type Section (size: {}) ===", section_size);
-                let (_, types) = Vectors::<FuncType>::decode(reader)?;
-                module.types = (*types).0;
+        // The contents are read up front, so that a section can't extend past its size.
+        // A section cut short by the end of the module is still decoded as far as it goes,
+        // to report what is wrong with its contents first.
+        let (n, section_size) = leb128::decode::u32(reader)?;
+        let start = offset + 1 + n;
+        offset = start + section_size as usize;
+        let mut contents = vec![];
+        reader
+            .take(section_size as u64)
+            .read_to_end(&mut contents)?;
+        let truncated = contents.len() != section_size as usize;
+        let reader = &mut contents.as_slice();
 
-                println!("types: {:?}\n", module.types);
-            }
-            3 => {
-                println!("=== Function Section (size: {}) ===", section_size);
-                let (_, res) = Vectors::<TypeIdx>::decode(reader)?;
-                func_indices = (*res).0;
+        // Running out of contents means the section, or a function body in it, is cut
+        // short by its size.
+        let result = (|| -> Result<(), DecodeError> {
+            match section_id[0] {
+                0 => {
+                    let (_, name) = Name::decode(reader)?;
+                    let bytes = std::mem::take(reader).to_vec();
 
-                println!("function indices: {:?}\n", func_indices);
-            }
-            7 => {
-                println!("=== Export Section (size: {}) ===", section_size);
-                let (_, exports) = Vectors::<Export>::decode(reader)?;
-                module.exports = (*exports).0;
+                    // A malformed name section is ignored rather than failing the whole module.
+                    if name.0 == "name" {
+                        if let Ok((_, names)) = Names::decode(&mut bytes.as_slice()) {
+                            module.names = *names;
+                        }
+                    }
 
-                println!("exports: {:?}\n", module.exports);
-            }
-            10 => {
-                println!("=== Code Section (size: {}) ===", section_size);
-                let (_, funcs) = Vectors::<Func>::decode(reader)?;
-                let mut funcs = (*funcs).0;
-                for (i, mut func) in funcs.iter_mut().enumerate() {
-                    let typeidx = func_indices.get(i).ok_or("unknown index")?;
-                    func.type_ = *typeidx;
+                    let custom = Custom {
+                        name: *name,
+                        bytes: bytes.into_iter().map(Byte).collect(),
+                    };
+                    module.customs.push(custom);
+                }
+                1 => {
+                    let (_, types) = Vectors::<FuncType>::decode(reader)?;
+                    module.types = (*types).0;
+                }
+                2 => {
+                    let (_, imports) = Vectors::<Import>::decode(reader)?;
+                    module.imports = (*imports).0;
+                }
+                3 => {
+                    let (_, res) = Vectors::<TypeIdx>::decode(reader)?;
+                    func_indices = (*res).0;
+                }
+                4 => {
+                    let (_, tables) = Vectors::<Table>::decode(reader)?;
+                    module.tables = (*tables).0;
                 }
-                module.funcs = funcs;
+                5 => {
+                    let (_, mems) = Vectors::<Mem>::decode(reader)?;
+                    module.mems = (*mems).0;
+                }
+                6 => {
+                    let (_, globals) = Vectors::<Global>::decode(reader)?;
+                    module.globals = (*globals).0;
+                }
+                7 => {
+                    let (_, exports) = Vectors::<Export>::decode(reader)?;
+                    module.exports = (*exports).0;
+                }
+                8 => {
+                    let (_, start) = Start::decode(reader)?;
+                    module.start = Some(*start);
+                }
+                9 => {
+                    let (_, elems) = Vectors::<Elem>::decode(reader)?;
+                    module.elems = (*elems).0;
+                }
+                10 => {
+                    let (_, count) = leb128::decode::u32(reader)?;
+                    for _ in 0..count {
+                        // Instruction offsets are made relative to the start of the module.
+                        let entry = start + contents.len() - reader.len();
+                        let (_, mut func) = Func::decode(reader)?;
+                        for offset in &mut func.offsets {
+                            *offset += entry;
+                        }
+                        module.funcs.push(*func);
+                    }
+                }
+                11 => {
+                    let (_, datas) = Vectors::<Data>::decode(reader)?;
+                    module.datas = (*datas).0;
+                }
+                12 => {
+                    let (_, count) = leb128::decode::u32(reader)?;
+                    data_count = Some(count);
+                }
+                _ => unreachable!("unordered section id {}", section_id[0]),
+            };
+            Ok(())
+        })();
+        match result {
+            Err(DecodeError::UnexpectedEnd) => Err(DecodeError::UnexpectedEndOfSection)?,
+            result => result?,
+        }
+        if truncated {
+            Err(DecodeError::UnexpectedEndOfSection)?;
+        }
 
-                println!("funcs: {:?}\n", module.funcs)
-            }
-            _ => Err(RuntimeError::InvalidWasmError(
-                "not implemented".to_string(),
-            ))?,
-        };
+        if !reader.is_empty() {
+            Err(DecodeError::SectionSizeMismatch)?;
+        }
+    }
+
+    // A function section without a code section declares functions without bodies.
+    if func_indices.len() != module.funcs.len() {
+        Err(DecodeError::FuncCodeMismatch)?;
+    }
+    for (func, typeidx) in module.funcs.iter_mut().zip(func_indices) {
+        func.type_ = typeidx;
+    }
+
+    if let Some(count) = data_count {
+        if count as usize != module.datas.len() {
+            Err(DecodeError::DataCountMismatch)?;
+        }
+    } else if module.funcs.iter().any(|f| uses_data_idx(&f.body.0)) {
+        // Data indices in function bodies can only be checked when the data count is
+        // known before the code section.
+        Err(DecodeError::DataCountRequired)?;
+    }
+
+    Ok(module)
+}
+
+// The position of a section in the order sections must occur in, where the data count
+// section comes before the code section despite its id.
+fn section_order(id: u8) -> Option<u8> {
+    match id {
+        1..=9 => Some(id),
+        12 => Some(10),
+        10 => Some(11),
+        11 => Some(12),
+        _ => None,
     }
 }
 
+fn uses_data_idx(instrs: &[Instr]) -> bool {
+    instrs.iter().any(|instr| match instr {
+        Instr::MemoryInit(_) | Instr::DataDrop(_) => true,
+        Instr::Block(_, body) | Instr::Loop(_, body) => uses_data_idx(body),
+        Instr::If(_, then, else_) => uses_data_idx(then) || uses_data_idx(else_),
+        _ => false,
+    })
+}
+
 // Code Section: https://webassembly.github.io/spec/core/binary/modules.html#binary-local
 pub struct Locals(pub u32, pub ValType);
 
 impl Decoder for Locals {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
@@ -107,14 +224,260 @@ impl Decoder for Locals {
     }
 }
 
+// Table Section: https://webassembly.github.io/spec/core/binary/modules.html#table-section
+impl Decoder for Table {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, tabletype) = TableType::decode(reader)?;
+        Ok((n, Box::new(Table { type_: *tabletype })))
+    }
+}
+
+// Memory Section: https://webassembly.github.io/spec/core/binary/modules.html#memory-section
+impl Decoder for Mem {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, memtype) = MemType::decode(reader)?;
+        Ok((n, Box::new(Mem { type_: *memtype })))
+    }
+}
+
+// Global Section: https://webassembly.github.io/spec/core/binary/modules.html#global-section
+impl Decoder for Global {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n1, globaltype) = GlobalType::decode(reader)?;
+        let (n2, init) = Expr::decode(reader)?;
+        Ok((
+            n1 + n2,
+            Box::new(Global {
+                type_: *globaltype,
+                init: *init,
+            }),
+        ))
+    }
+}
+
+// Start Section: https://webassembly.github.io/spec/core/binary/modules.html#start-section
+impl Decoder for Start {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, func) = FuncIdx::decode(reader)?;
+        Ok((n, Box::new(Start { func: *func })))
+    }
+}
+
+// Element Section: https://webassembly.github.io/spec/core/binary/modules.html#element-section
+impl Decoder for Elem {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (mut size, flag) = leb128::decode::u32(reader)?;
+        if flag > 7 {
+            Err(DecodeError::InvalidElemFlag(flag))?;
+        }
+
+        // Bit 0 marks a passive or declarative segment, bit 1 an explicit table index
+        // (or declarative when bit 0 is set), and bit 2 element expressions instead of
+        // function indices.
+        let mode = if flag & 0x01 == 0 {
+            let table = if flag & 0x02 == 0 {
+                TableIdx(0)
+            } else {
+                let (n, tableidx) = leb128::decode::u32(reader)?;
+                size += n;
+                TableIdx(tableidx)
+            };
+            let (n, offset) = Expr::decode(reader)?;
+            size += n;
+            ElemMode::Active {
+                table,
+                offset: *offset,
+            }
+        } else if flag & 0x02 == 0 {
+            ElemMode::Passive
+        } else {
+            ElemMode::Declarative
+        };
+
+        // Segments of the form 0 and 4 leave the element kind implicit.
+        let type_ = if flag & 0x03 == 0 {
+            RefType::FuncRef
+        } else if flag & 0x04 == 0 {
+            let mut elemkind = [0; 1];
+            reader.read_exact(&mut elemkind)?;
+            size += 1;
+            if elemkind[0] != 0x00 {
+                Err(DecodeError::InvalidElemKind(elemkind[0]))?;
+            }
+            RefType::FuncRef
+        } else {
+            let (n, reftype) = RefType::decode(reader).map_err(|e| match e {
+                DecodeError::UnknownRefType(b) => DecodeError::InvalidElemType(b),
+                e => e,
+            })?;
+            size += n;
+            *reftype
+        };
+
+        let init = if flag & 0x04 == 0 {
+            let (n, funcs) = Vectors::<FuncIdx>::decode(reader)?;
+            size += n;
+            funcs
+                .0
+                .into_iter()
+                .map(|f| Expr(vec![Instr::RefFunc(f)]))
+                .collect()
+        } else {
+            let (n, exprs) = Vectors::<Expr>::decode(reader)?;
+            size += n;
+            exprs.0
+        };
+
+        Ok((size, Box::new(Elem { type_, init, mode })))
+    }
+}
+
+// Data Section: https://webassembly.github.io/spec/core/binary/modules.html#data-section
+impl Decoder for Data {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (mut size, flag) = leb128::decode::u32(reader)?;
+
+        let mode = match flag {
+            0 => {
+                let (n, offset) = Expr::decode(reader)?;
+                size += n;
+                DataMode::Active {
+                    memory: MemIdx(0),
+                    offset: *offset,
+                }
+            }
+            1 => DataMode::Passive,
+            2 => {
+                let (n1, memidx) = leb128::decode::u32(reader)?;
+                let (n2, offset) = Expr::decode(reader)?;
+                size += n1 + n2;
+                DataMode::Active {
+                    memory: MemIdx(memidx),
+                    offset: *offset,
+                }
+            }
+            _ => Err(DecodeError::InvalidDataFlag(flag))?,
+        };
+
+        let (n, init) = Vectors::<Byte>::decode(reader)?;
+        size += n;
+
+        Ok((size, Box::new(Data { init: init.0, mode })))
+    }
+}
+
+// Name Section: https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+impl Decoder for Names {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let mut names: Names = Default::default();
+        let mut size = 0;
+        loop {
+            let mut id = [0; 1];
+            if reader.read(&mut id)? == 0 {
+                break;
+            }
+            // The size is not trusted for an allocation before the bytes are read.
+            let (n, subsection_size) = leb128::decode::u32(reader)?;
+            let mut bytes = vec![];
+            reader
+                .by_ref()
+                .take(subsection_size as u64)
+                .read_to_end(&mut bytes)?;
+            if bytes.len() != subsection_size as usize {
+                Err(DecodeError::UnexpectedEnd)?;
+            }
+            size += 1 + n + bytes.len();
+
+            let mut subsection = bytes.as_slice();
+            match id[0] {
+                0 => {
+                    let (_, name) = Name::decode(&mut subsection)?;
+                    names.module = Some(*name);
+                }
+                1 => {
+                    let (_, funcs) = Vectors::<NameAssoc>::decode(&mut subsection)?;
+                    names.funcs = funcs
+                        .0
+                        .into_iter()
+                        .map(|NameAssoc(idx, name)| (FuncIdx(idx), name))
+                        .collect();
+                }
+                2 => {
+                    let (_, locals) = Vectors::<IndirectNameAssoc>::decode(&mut subsection)?;
+                    names.locals = locals
+                        .0
+                        .into_iter()
+                        .map(|IndirectNameAssoc(idx, map)| {
+                            let map = map
+                                .into_iter()
+                                .map(|NameAssoc(idx, name)| (LocalIdx(idx), name))
+                                .collect();
+                            (FuncIdx(idx), map)
+                        })
+                        .collect();
+                }
+                // Subsections from extensions to the name section are skipped.
+                _ => {}
+            }
+        }
+
+        Ok((size, Box::new(names)))
+    }
+}
+
+pub struct NameAssoc(pub u32, pub Name);
+
+impl Decoder for NameAssoc {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n1, idx) = leb128::decode::u32(reader)?;
+        let (n2, name) = Name::decode(reader)?;
+        Ok((n1 + n2, Box::new(NameAssoc(idx, *name))))
+    }
+}
+
+pub struct IndirectNameAssoc(pub u32, pub Vec<NameAssoc>);
+
+impl Decoder for IndirectNameAssoc {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n1, idx) = leb128::decode::u32(reader)?;
+        let (n2, map) = Vectors::<NameAssoc>::decode(reader)?;
+        Ok((n1 + n2, Box::new(IndirectNameAssoc(idx, map.0))))
+    }
+}
+
 impl Decoder for Export {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
-        let (n1, name) = Vectors::<Byte>::decode(reader)?;
-        let name = (*name).0;
-        let name = String::from_utf8(name.iter().map(|b| b.0).collect())?;
+        let (n1, name) = Name::decode(reader)?;
 
         let mut buf = [0; 1];
         reader.read_exact(&mut buf)?;
@@ -135,19 +498,302 @@ impl Decoder for Export {
                 let (n2, globalidx) = leb128::decode::u32(reader)?;
                 (n2, ExportDesc::Global(GlobalIdx(globalidx)))
             }
-            _ => {
-                return Err(Box::new(RuntimeError::InvalidWasmError(
-                    "unknown export descriptor".to_string(),
-                )))
+            b => return Err(DecodeError::UnknownExportDesc(b)),
+        };
+
+        Ok((n1 + n2 + 1, Box::new(Export { name: *name, desc })))
+    }
+}
+
+// Import Section: https://webassembly.github.io/spec/core/binary/modules.html#import-section
+impl Decoder for Import {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n1, module) = Name::decode(reader)?;
+        let (n2, name) = Name::decode(reader)?;
+
+        let mut buf = [0; 1];
+        reader.read_exact(&mut buf)?;
+        let (n3, desc) = match buf[0] {
+            0x00 => {
+                let (n3, typeidx) = TypeIdx::decode(reader)?;
+                (n3, ImportDesc::Func(*typeidx))
+            }
+            0x01 => {
+                let (n3, tabletype) = TableType::decode(reader)?;
+                (n3, ImportDesc::Table(*tabletype))
             }
+            0x02 => {
+                let (n3, memtype) = MemType::decode(reader)?;
+                (n3, ImportDesc::Mem(*memtype))
+            }
+            0x03 => {
+                let (n3, globaltype) = GlobalType::decode(reader)?;
+                (n3, ImportDesc::Global(*globaltype))
+            }
+            b => return Err(DecodeError::UnknownImportDesc(b)),
         };
 
         Ok((
-            n1 + n2 + 1,
-            Box::new(Export {
-                name: Name(name),
+            n1 + n2 + n3 + 1,
+            Box::new(Import {
+                module: *module,
+                name: *name,
                 desc,
             }),
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::types::{Limits, Mut, NumType};
+
+    #[test]
+    fn preamble() {
+        assert!(magic(&mut &b"\0asm"[..]).is_ok());
+        let err = magic(&mut &b"asm\0"[..]).unwrap_err();
+        assert_eq!(err.to_string(), "magic header not detected");
+        // A truncated version is not padded with zeros.
+        let err = version(&mut &[1, 0][..]).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected end");
+        let err = version(&mut &[0, 0, 0, 0][..]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown binary version");
+    }
+
+    #[test]
+    fn imports() {
+        let bytes = [
+            4, // four imports
+            1, b'm', 1, b'f', 0x00, 2, // func of type 2
+            1, b'm', 1, b't', 0x01, 0x70, 0x00, 1, // table funcref {min 1}
+            1, b'm', 1, b'm', 0x02, 0x01, 1, 2, // memory {min 1, max 2}
+            1, b'm', 1, b'g', 0x03, 0x7f, 0x01, // global (mut i32)
+        ];
+        let (n, imports) = Vectors::<Import>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+
+        let import = |name: &str, desc| Import {
+            module: Name("m".to_string()),
+            name: Name(name.to_string()),
+            desc,
+        };
+        assert_eq!(
+            imports.0,
+            vec![
+                import("f", ImportDesc::Func(TypeIdx(2))),
+                import(
+                    "t",
+                    ImportDesc::Table(TableType(Limits { min: 1, max: None }, RefType::FuncRef))
+                ),
+                import(
+                    "m",
+                    ImportDesc::Mem(MemType(Limits {
+                        min: 1,
+                        max: Some(2)
+                    }))
+                ),
+                import(
+                    "g",
+                    ImportDesc::Global(GlobalType(Mut::Var, ValType::NumType(NumType::I32)))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tables_mems_globals() {
+        let bytes = [1, 0x6f, 0x01, 0, 10];
+        let (_, tables) = Vectors::<Table>::decode(&mut &bytes[..]).unwrap();
+        let limits = Limits {
+            min: 0,
+            max: Some(10),
+        };
+        assert_eq!(tables.0[0].type_, TableType(limits, RefType::ExternRef));
+
+        let bytes = [1, 0x00, 17];
+        let (_, mems) = Vectors::<Mem>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(mems.0[0].type_, MemType(Limits { min: 17, max: None }));
+
+        let bytes = [
+            2, // two globals
+            0x7e, 0x00, 0x42, 0x7e, 0x0b, // i64 = i64.const -2
+            0x7f, 0x01, 0x23, 0, 0x0b, // mut i32 = global.get 0
+        ];
+        let (n, globals) = Vectors::<Global>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+        let i64 = ValType::NumType(NumType::I64);
+        assert_eq!(globals.0[0].type_, GlobalType(Mut::Const, i64));
+        assert_eq!(globals.0[0].init.0, vec![Instr::I64Const(-2)]);
+        assert_eq!(globals.0[1].init.0, vec![Instr::GlobalGet(GlobalIdx(0))]);
+    }
+
+    #[test]
+    fn elems() {
+        let bytes = [
+            5, // five segments
+            0, 0x41, 1, 0x0b, 1, 3, // active at 1 in table 0: funcs 3
+            1, 0x00, 2, 0, 1, // passive funcs 0 and 1
+            2, 1, 0x41, 0, 0x0b, 0x00, 0, // active at 0 in table 1, no funcs
+            3, 0x00, 1, 2, // declarative func 2
+            5, 0x6f, 1, 0xd0, 0x6f, 0x0b, // passive externref: ref.null extern
+        ];
+        let (n, elems) = Vectors::<Elem>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+
+        let funcs = |idxs: &[u32]| -> Vec<Expr> {
+            idxs.iter()
+                .map(|&i| Expr(vec![Instr::RefFunc(FuncIdx(i))]))
+                .collect()
+        };
+        let expected = vec![
+            Elem {
+                type_: RefType::FuncRef,
+                init: funcs(&[3]),
+                mode: ElemMode::Active {
+                    table: TableIdx(0),
+                    offset: Expr(vec![Instr::I32Const(1)]),
+                },
+            },
+            Elem {
+                type_: RefType::FuncRef,
+                init: funcs(&[0, 1]),
+                mode: ElemMode::Passive,
+            },
+            Elem {
+                type_: RefType::FuncRef,
+                init: vec![],
+                mode: ElemMode::Active {
+                    table: TableIdx(1),
+                    offset: Expr(vec![Instr::I32Const(0)]),
+                },
+            },
+            Elem {
+                type_: RefType::FuncRef,
+                init: funcs(&[2]),
+                mode: ElemMode::Declarative,
+            },
+            Elem {
+                type_: RefType::ExternRef,
+                init: vec![Expr(vec![Instr::RefNull(RefType::ExternRef)])],
+                mode: ElemMode::Passive,
+            },
+        ];
+        assert_eq!(elems.0, expected);
+
+        let err = Elem::decode(&mut &[8][..]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid element segment flag 8");
+    }
+
+    #[test]
+    fn datas() {
+        let bytes = [
+            3, // three segments
+            0, 0x41, 8, 0x0b, 2, b'h', b'i', // active at 8
+            1, 1, b'!', // passive
+            2, 1, 0x41, 0, 0x0b, 0, // active at 0 in memory 1
+        ];
+        let (n, datas) = Vectors::<Data>::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+        assert_eq!(
+            datas.0,
+            vec![
+                Data {
+                    init: vec![Byte(b'h'), Byte(b'i')],
+                    mode: DataMode::Active {
+                        memory: MemIdx(0),
+                        offset: Expr(vec![Instr::I32Const(8)]),
+                    },
+                },
+                Data {
+                    init: vec![Byte(b'!')],
+                    mode: DataMode::Passive,
+                },
+                Data {
+                    init: vec![],
+                    mode: DataMode::Active {
+                        memory: MemIdx(1),
+                        offset: Expr(vec![Instr::I32Const(0)]),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn name_section() {
+        let bytes = [
+            0, 4, 3, b'l', b'i', b'b', // module name
+            1, 6, 1, 3, 3, b'f', b'i', b'b', // func 3 is $fib
+            9, 1, 0, // an unknown subsection
+            2, 6, 1, 3, 1, 0, 1, b'n', // local 0 of func 3 is n
+        ];
+        let (n, names) = Names::decode(&mut &bytes[..]).unwrap();
+        assert_eq!(n, bytes.len());
+        assert_eq!(names.module, Some(Name("lib".to_string())));
+        assert_eq!(names.func(&FuncIdx(3)), Some(&Name("fib".to_string())));
+        assert_eq!(names.func(&FuncIdx(0)), None);
+        assert_eq!(
+            names.local(&FuncIdx(3), &LocalIdx(0)),
+            Some(&Name("n".to_string()))
+        );
+
+        // A subsection larger than the section is rejected without being allocated.
+        let bytes = [1, 0xff, 0xff, 0xff, 0xff, 0x0f, 0];
+        let err = Names::decode(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected end");
+    }
+
+    #[test]
+    fn unknown_import_desc() {
+        let bytes = [1, b'm', 1, b'f', 0x04, 0];
+        let err = Import::decode(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown import descriptor");
+    }
+
+    fn decode_sections(bytes: &[u8]) -> String {
+        sections(&mut &bytes[..]).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn section_order_and_sizes() {
+        // A function section followed by a type section.
+        let bytes = [3, 2, 1, 0, 1, 4, 1, 0x60, 0, 0];
+        assert_eq!(decode_sections(&bytes), "junk after last section");
+        assert_eq!(decode_sections(&[13, 0]), "malformed section id");
+        // A type section with a byte left over, and one cut short.
+        assert_eq!(decode_sections(&[1, 2, 0, 0]), "section size mismatch");
+        assert_eq!(
+            decode_sections(&[1, 5, 1, 0x60]),
+            "unexpected end of section or function"
+        );
+        // A custom section whose name is longer than the section.
+        assert_eq!(decode_sections(&[0, 2, 5, b'a']), "length out of bounds");
+    }
+
+    #[test]
+    fn function_and_code_counts() {
+        // A function section without a code section.
+        let bytes = [1, 4, 1, 0x60, 0, 0, 3, 2, 1, 0];
+        assert_eq!(
+            decode_sections(&bytes),
+            "function and code section have inconsistent lengths"
+        );
+    }
+
+    #[test]
+    fn instruction_offsets() {
+        // The sections of (module (func (result i32) nop i64.const 0)), which follow the
+        // 8 bytes of the preamble.
+        let bytes = [
+            1, 5, 1, 0x60, 0, 1, 0x7f, // type section
+            3, 2, 1, 0, // function section
+            10, 7, 1, 5, 0, 0x01, 0x42, 0, 0x0b, // code section
+        ];
+        let module = sections(&mut &bytes[..]).unwrap();
+        assert_eq!(module.funcs[0].offsets, vec![24, 25]);
+    }
+}
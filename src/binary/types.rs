@@ -1,37 +1,37 @@
 use crate::binary::conventions::Vectors;
 use crate::binary::decoder::Decoder;
+use crate::binary::instructions;
 use crate::binary::modules::Locals;
-use crate::errors::RuntimeError::InvalidWasmError;
+use crate::errors::DecodeError;
 use crate::leb128;
-use crate::structure::instructions::Expr;
-use crate::structure::modules::{Func, TypeIdx};
-use crate::structure::types::{FuncType, NumType, ResultType, ValType};
-use std::error::Error;
-use std::fs::{read, File};
+use crate::structure::modules::{DataIdx, ElemIdx, Func, FuncIdx, TableIdx, TypeIdx};
+use crate::structure::types::{
+    FuncType, GlobalType, Limits, MemType, Mut, NumType, RefType, ResultType, TableType, ValType,
+};
 use std::io::Read;
 
+// Bounds the number of locals of a function, which are expanded into one value each.
+const MAX_LOCALS: u64 = 50_000;
+
 impl Decoder for FuncType {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
         let mut type_id = [0; 1];
         reader.read_exact(&mut type_id)?;
         if type_id[0] != 0x60 {
-            Err(InvalidWasmError("invalid func type".to_string()))?;
+            Err(DecodeError::InvalidFuncType(type_id[0]))?;
         }
-        println!("type_id: {}", type_id[0]);
 
         let (n1, params) = ResultType::decode(reader)?;
         let (n2, results) = ResultType::decode(reader)?;
-        println!("params: {:?}", params);
-        println!("results: {:?}", results);
         Ok((n1 + n2, Box::new(FuncType(*params, *results))))
     }
 }
 
 impl Decoder for ResultType {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
@@ -41,29 +41,114 @@ impl Decoder for ResultType {
 }
 
 impl Decoder for ValType {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
         let mut valtype = [0; 1];
         reader.read_exact(&mut valtype)?;
 
-        let numtype = match valtype[0] {
-            0x7f => NumType::I32,
-            0x7e => NumType::I64,
-            0x7d => NumType::F32,
-            0x7c => NumType::F64,
-            _ => Err(InvalidWasmError(
-                format!("unknown number type {}", valtype[0]).to_string(),
-            ))?,
+        let valtype = match valtype[0] {
+            0x7f => ValType::NumType(NumType::I32),
+            0x7e => ValType::NumType(NumType::I64),
+            0x7d => ValType::NumType(NumType::F32),
+            0x7c => ValType::NumType(NumType::F64),
+            0x70 => ValType::RefType(RefType::FuncRef),
+            0x6f => ValType::RefType(RefType::ExternRef),
+            _ => Err(DecodeError::UnknownValType(valtype[0]))?,
+        };
+
+        Ok((1, Box::new(valtype)))
+    }
+}
+
+// Reference Types: https://webassembly.github.io/spec/core/binary/types.html#reference-types
+impl Decoder for RefType {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let mut reftype = [0; 1];
+        reader.read_exact(&mut reftype)?;
+
+        let reftype = match reftype[0] {
+            0x70 => RefType::FuncRef,
+            0x6f => RefType::ExternRef,
+            _ => Err(DecodeError::UnknownRefType(reftype[0]))?,
+        };
+
+        Ok((1, Box::new(reftype)))
+    }
+}
+
+// Limits: https://webassembly.github.io/spec/core/binary/types.html#limits
+impl Decoder for Limits {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let mut flag = [0; 1];
+        reader.read_exact(&mut flag)?;
+
+        let (n1, min) = leb128::decode::u32(reader)?;
+        let (n2, max) = match flag[0] {
+            0x00 => (0, None),
+            0x01 => {
+                let (n2, max) = leb128::decode::u32(reader)?;
+                (n2, Some(max))
+            }
+            _ => Err(DecodeError::InvalidLimitsFlag(flag[0]))?,
+        };
+
+        Ok((1 + n1 + n2, Box::new(Limits { min, max })))
+    }
+}
+
+// Memory Types: https://webassembly.github.io/spec/core/binary/types.html#memory-types
+impl Decoder for MemType {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, limits) = Limits::decode(reader)?;
+        Ok((n, Box::new(MemType(*limits))))
+    }
+}
+
+// Table Types: https://webassembly.github.io/spec/core/binary/types.html#table-types
+impl Decoder for TableType {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n1, reftype) = RefType::decode(reader)?;
+        let (n2, limits) = Limits::decode(reader)?;
+        Ok((n1 + n2, Box::new(TableType(*limits, *reftype))))
+    }
+}
+
+// Global Types: https://webassembly.github.io/spec/core/binary/types.html#global-types
+impl Decoder for GlobalType {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, valtype) = ValType::decode(reader)?;
+
+        let mut mut_ = [0; 1];
+        reader.read_exact(&mut mut_)?;
+        let mut_ = match mut_[0] {
+            0x00 => Mut::Const,
+            0x01 => Mut::Var,
+            _ => Err(DecodeError::InvalidMutability(mut_[0]))?,
         };
 
-        Ok((0, Box::new(ValType::NumType(numtype))))
+        Ok((n + 1, Box::new(GlobalType(mut_, *valtype))))
     }
 }
 
 impl Decoder for TypeIdx {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
@@ -72,15 +157,61 @@ impl Decoder for TypeIdx {
     }
 }
 
+impl Decoder for FuncIdx {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, funcidx) = leb128::decode::u32(reader)?;
+        Ok((n, Box::new(FuncIdx(funcidx))))
+    }
+}
+
+impl Decoder for TableIdx {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, tableidx) = leb128::decode::u32(reader)?;
+        Ok((n, Box::new(TableIdx(tableidx))))
+    }
+}
+
+impl Decoder for ElemIdx {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, elemidx) = leb128::decode::u32(reader)?;
+        Ok((n, Box::new(ElemIdx(elemidx))))
+    }
+}
+
+impl Decoder for DataIdx {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        let (n, dataidx) = leb128::decode::u32(reader)?;
+        Ok((n, Box::new(DataIdx(dataidx))))
+    }
+}
+
 impl Decoder for Func {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
-        let (n1, size) = leb128::decode::u32(reader)?;
+        let (n1, _size) = leb128::decode::u32(reader)?;
 
         let (n2, t) = Vectors::<Locals>::decode(reader)?;
 
+        // The total count is bounded before the locals are expanded.
+        let total: u64 = t.0.iter().map(|l| l.0 as u64).sum();
+        if total > MAX_LOCALS {
+            Err(DecodeError::TooManyLocals)?;
+        }
+
         let mut locals = vec![];
         for l in (*t).0 {
             let (n, valtype) = (l.0, l.1);
@@ -88,17 +219,52 @@ impl Decoder for Func {
                 locals.push(valtype.clone())
             }
         }
-        println!("locals: {:?}", locals);
 
-        let (n3, expr) = Expr::decode(reader)?;
-        println!("expr: {:?}", expr);
+        // Instruction offsets are made relative to the start of the entry.
+        let mut offsets = vec![];
+        let (n3, body) = instructions::expr(reader, &mut offsets)?;
+        for offset in &mut offsets {
+            *offset += n1 + n2;
+        }
 
         let func = Func {
             type_: TypeIdx(0),
             locals,
-            body: *expr,
+            body,
+            offsets,
         };
 
         Ok((n1 + n2 + n3, Box::new(func)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locals() {
+        // Two groups of locals and an empty body.
+        let bytes = [6, 2, 2, 0x7f, 1, 0x7e, 0x0b];
+        let (_, func) = Func::decode(&mut &bytes[..]).unwrap();
+        let (i32, i64) = (NumType::I32, NumType::I64);
+        assert_eq!(
+            func.locals,
+            vec![
+                ValType::NumType(i32.clone()),
+                ValType::NumType(i32),
+                ValType::NumType(i64)
+            ]
+        );
+    }
+
+    #[test]
+    fn too_many_locals() {
+        // Two groups of 2^32 - 1 locals each, which must not be expanded.
+        let bytes = [
+            13, 2, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x7f, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x7f, 0x0b,
+        ];
+        let err = Func::decode(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.to_string(), "too many locals");
+    }
+}
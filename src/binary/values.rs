@@ -1,10 +1,11 @@
 use crate::binary::decoder::Decoder;
-use crate::structure::values::Byte;
-use std::error::Error;
+use crate::errors::DecodeError;
+use crate::leb128;
+use crate::structure::values::{Byte, Name};
 use std::io::Read;
 
 impl Decoder for Byte {
-    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), Box<dyn Error>>
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
     where
         R: Read,
     {
@@ -13,3 +14,21 @@ impl Decoder for Byte {
         Ok((1, Box::new(Byte(buf[0]))))
     }
 }
+
+// Names: https://webassembly.github.io/spec/core/binary/values.html#names
+impl Decoder for Name {
+    fn decode<R>(reader: &mut R) -> Result<(usize, Box<Self>), DecodeError>
+    where
+        R: Read,
+    {
+        // The length is not trusted for an allocation before the bytes are read.
+        let (n, len) = leb128::decode::u32(reader)?;
+        let mut bytes = vec![];
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len as usize {
+            Err(DecodeError::LengthOutOfBounds)?;
+        }
+        let name = String::from_utf8(bytes).map_err(|_| DecodeError::InvalidUtf8)?;
+        Ok((n + len as usize, Box::new(Name(name))))
+    }
+}
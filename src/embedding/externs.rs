@@ -0,0 +1,150 @@
+use crate::embedding::funcs::Func;
+use crate::embedding::instances::Store;
+use crate::execution::runtime::{Addr, ExternVal, StoreId};
+use crate::execution::stack::Val;
+use crate::structure::types::ExternType;
+
+/// A function, table, memory or global that can be exported and imported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extern {
+    Func(Func),
+    Table(Table),
+    Memory(Memory),
+    Global(Global),
+}
+
+impl Extern {
+    /// Returns the current type of the value, which reflects any growth of tables and
+    /// memories.
+    pub fn ty(&self, store: &Store) -> ExternType {
+        self.value(store).type_(&store.store)
+    }
+
+    pub(crate) fn new(store: StoreId, v: ExternVal) -> Extern {
+        match v {
+            ExternVal::Func(addr) => Extern::Func(Func { store, addr }),
+            ExternVal::Table(addr) => Extern::Table(Table { store, addr }),
+            ExternVal::Mem(addr) => Extern::Memory(Memory { store, addr }),
+            ExternVal::Global(addr) => Extern::Global(Global { store, addr }),
+        }
+    }
+
+    // Returns the external value in `store` that the handle refers to.
+    pub(crate) fn value(&self, store: &Store) -> ExternVal {
+        let (id, v) = match *self {
+            Extern::Func(Func { store, addr }) => (store, ExternVal::Func(addr)),
+            Extern::Table(Table { store, addr }) => (store, ExternVal::Table(addr)),
+            Extern::Memory(Memory { store, addr }) => (store, ExternVal::Mem(addr)),
+            Extern::Global(Global { store, addr }) => (store, ExternVal::Global(addr)),
+        };
+        store.check(id);
+        v
+    }
+
+    pub fn into_func(self) -> Option<Func> {
+        match self {
+            Extern::Func(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn into_table(self) -> Option<Table> {
+        match self {
+            Extern::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn into_memory(self) -> Option<Memory> {
+        match self {
+            Extern::Memory(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn into_global(self) -> Option<Global> {
+        match self {
+            Extern::Global(g) => Some(g),
+            _ => None,
+        }
+    }
+}
+
+impl From<Func> for Extern {
+    fn from(f: Func) -> Self {
+        Extern::Func(f)
+    }
+}
+
+impl From<Table> for Extern {
+    fn from(t: Table) -> Self {
+        Extern::Table(t)
+    }
+}
+
+impl From<Memory> for Extern {
+    fn from(m: Memory) -> Self {
+        Extern::Memory(m)
+    }
+}
+
+impl From<Global> for Extern {
+    fn from(g: Global) -> Self {
+        Extern::Global(g)
+    }
+}
+
+/// A table in a [`Store`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Table {
+    store: StoreId,
+    addr: Addr,
+}
+
+impl Table {
+    /// Returns the current number of elements.
+    pub fn size(&self, store: &Store) -> u32 {
+        store.check(self.store);
+        store.store.tables[self.addr].size()
+    }
+}
+
+/// A linear memory in a [`Store`]. Every instance that imports it shares its contents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Memory {
+    store: StoreId,
+    addr: Addr,
+}
+
+impl Memory {
+    /// Returns the current size in pages of 64KiB.
+    pub fn size(&self, store: &Store) -> u32 {
+        store.check(self.store);
+        store.store.mems[self.addr].size()
+    }
+
+    pub fn data<'a>(&self, store: &'a Store) -> &'a [u8] {
+        store.check(self.store);
+        &store.store.mems[self.addr].data
+    }
+
+    pub fn data_mut<'a>(&self, store: &'a mut Store) -> &'a mut [u8] {
+        store.check(self.store);
+        &mut store.store.mems[self.addr].data
+    }
+}
+
+/// A global variable in a [`Store`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Global {
+    store: StoreId,
+    addr: Addr,
+}
+
+impl Global {
+    /// Returns the current value.
+    pub fn get(&self, store: &Store) -> Val {
+        store.check(self.store);
+        store.store.globals[self.addr].value
+    }
+}
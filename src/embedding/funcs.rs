@@ -0,0 +1,191 @@
+use crate::embedding::instances::Store;
+use crate::embedding::values::WasmTypeList;
+use crate::errors::Error;
+use crate::execution::modules::invoke;
+use crate::execution::runtime;
+use crate::execution::runtime::{Addr, ModuleInst, StoreId};
+use crate::execution::stack::{FuncRef, Ref, Val};
+use crate::structure::types::{FuncType, ResultType};
+use std::marker::PhantomData;
+
+/// A function in a [`Store`], such as one exported by an [`Instance`](crate::Instance).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Func {
+    pub(crate) store: StoreId,
+    pub(crate) addr: Addr,
+}
+
+impl Func {
+    /// Returns the type of the function.
+    pub fn ty<'a>(&self, store: &'a Store) -> &'a FuncType {
+        store.check(self.store);
+        store.store.funcs[self.addr].type_()
+    }
+
+    /// Calls the function with `args` and returns its results in declaration order.
+    ///
+    /// The arguments must match the parameter types exactly. A trap raised by the
+    /// function is returned as [`Error::Trap`].
+    pub fn call(&self, store: &mut Store, args: &[Val]) -> Result<Vec<Val>, Error> {
+        store.check(self.store);
+        invoke(&mut store.store, self.addr, args)
+    }
+
+    /// Checks that the function takes `Params` and returns `Results`, and wraps it so
+    /// that it can be called with native Rust values.
+    pub fn typed<Params, Results>(&self, store: &Store) -> Result<TypedFunc<Params, Results>, Error>
+    where
+        Params: WasmTypeList,
+        Results: WasmTypeList,
+    {
+        let expected = FuncType(ResultType(Params::types()), ResultType(Results::types()));
+        let actual = self.ty(store);
+        if *actual != expected {
+            return Err(Error::InvalidParameters(format!(
+                "expected function of type {:?}, found {:?}",
+                expected, actual
+            )));
+        }
+
+        Ok(TypedFunc {
+            func: *self,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl From<Func> for Ref {
+    /// Returns a reference to the function, which can be passed to functions of the
+    /// same store as a `funcref`.
+    fn from(func: Func) -> Ref {
+        Ref::Func(FuncRef {
+            store: func.store,
+            addr: func.addr,
+        })
+    }
+}
+
+/// A [`Func`] whose type has been checked against `Params` and `Results`.
+#[derive(Debug)]
+pub struct TypedFunc<Params, Results> {
+    func: Func,
+    _marker: PhantomData<fn(Params) -> Results>,
+}
+
+impl<Params, Results> Clone for TypedFunc<Params, Results> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Params, Results> Copy for TypedFunc<Params, Results> {}
+
+impl<Params, Results> TypedFunc<Params, Results>
+where
+    Params: WasmTypeList,
+    Results: WasmTypeList,
+{
+    /// Calls the function. A trap raised by the function is returned as [`Error::Trap`].
+    pub fn call(&self, store: &mut Store, params: Params) -> Result<Results, Error> {
+        let results = self.func.call(store, &params.into_vals())?;
+        // The function type was checked when it was wrapped.
+        Ok(Results::from_vals(&results).expect("result types match"))
+    }
+
+    /// Returns the untyped function.
+    pub fn func(&self) -> Func {
+        self.func
+    }
+}
+
+/// The state of the instance that called a host function.
+pub struct Caller<'a> {
+    store: &'a mut runtime::Store,
+    inst: Option<&'a ModuleInst>,
+}
+
+impl<'a> Caller<'a> {
+    pub(crate) fn new(store: &'a mut runtime::Store, inst: Option<&'a ModuleInst>) -> Caller<'a> {
+        Caller { store, inst }
+    }
+
+    /// Returns the contents of the caller's memory, or `None` if it has no memory or the
+    /// function was called by the embedder.
+    pub fn memory(&mut self) -> Option<&mut [u8]> {
+        let addr = *self.inst?.memaddrs.first()?;
+        Some(self.store.mems[addr].data.as_mut_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Engine, Instance, Module, Store, Val};
+
+    // (module (func (export "add") (param i32 i32) (result i32)
+    //   local.get 0 local.get 1 i32.add))
+    const ADD: [u8; 41] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f,
+        0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00,
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+    ];
+
+    fn instantiate() -> (Store, Instance) {
+        let engine = Engine::new();
+        let module = Module::new(&engine, ADD).unwrap();
+        let mut store = Store::new(&engine);
+        let instance = Instance::new(&mut store, &module).unwrap();
+        (store, instance)
+    }
+
+    #[test]
+    fn typed_funcs() {
+        let (mut store, instance) = instantiate();
+        let add = instance
+            .get_typed_func::<(i32, i32), i32>(&store, "add")
+            .unwrap();
+        assert_eq!(add.call(&mut store, (i32::MAX, 1)).unwrap(), i32::MIN);
+
+        let err = instance
+            .get_typed_func::<i32, i32>(&store, "add")
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid parameters: expected function"));
+        let err = instance
+            .get_typed_func::<(), ()>(&store, "sub")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid parameters: unknown function sub");
+    }
+
+    #[test]
+    fn argument_types() {
+        let (mut store, instance) = instantiate();
+        let add = instance.get_func(&store, "add").unwrap();
+        let err = add
+            .call(&mut store, &[Val::I32(1), Val::I64(2)])
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid parameters: expected arguments"));
+    }
+
+    #[test]
+    fn func_refs() {
+        let engine = Engine::new();
+        let text = r#"(module (func (export "f") (param funcref)))"#;
+        let module = Module::from_wat(&engine, text).unwrap();
+        let mut store = Store::new(&engine);
+        let instance = Instance::new(&mut store, &module).unwrap();
+        let f = instance.get_func(&store, "f").unwrap();
+        assert!(f.call(&mut store, &[Val::Ref(f.into())]).is_ok());
+
+        // A function of another store is rejected, although its address exists here.
+        let (other_store, other) = instantiate();
+        let add = other.get_func(&other_store, "add").unwrap();
+        let err = f.call(&mut store, &[Val::Ref(add.into())]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid parameters: function reference not in the store"
+        );
+    }
+}
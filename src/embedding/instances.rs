@@ -0,0 +1,169 @@
+use crate::embedding::externs::{Extern, Memory};
+use crate::embedding::funcs::{Func, TypedFunc};
+use crate::embedding::linker::{HostFuncId, Linker};
+use crate::embedding::modules::{Engine, Module};
+use crate::embedding::values::WasmTypeList;
+use crate::errors::Error;
+use crate::execution::runtime;
+use crate::execution::runtime::{Addr, ExternVal, FuncInst, ModuleInst, StoreId};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Owns the runtime state of every instance created in it: functions, tables, memories,
+/// globals and segments. Instances in the same store can share them through imports.
+///
+/// Handles such as [`Instance`] and [`Func`] refer into the store they were created in
+/// and must only be used with that store, or with clones of it. Using them with another
+/// store panics.
+#[derive(Debug, Clone, Default)]
+pub struct Store {
+    pub(crate) store: runtime::Store,
+    instances: Vec<Rc<ModuleInst>>,
+    host_funcs: HashMap<HostFuncId, Addr>,
+}
+
+impl Store {
+    pub fn new(_engine: &Engine) -> Store {
+        Default::default()
+    }
+
+    // Panics unless a handle with the store id `id` belongs to this store.
+    pub(crate) fn check(&self, id: StoreId) {
+        assert!(
+            id == self.store.id,
+            "handle used with a store it does not belong to"
+        );
+    }
+
+    // Returns the address of the host function `id`, allocating `funcinst` the first
+    // time it is imported into this store.
+    pub(crate) fn host_func(&mut self, id: HostFuncId, funcinst: &FuncInst) -> Addr {
+        let funcs = &mut self.store.funcs;
+        *self.host_funcs.entry(id).or_insert_with(|| {
+            funcs.push(funcinst.clone());
+            funcs.len() - 1
+        })
+    }
+}
+
+/// An instantiated module, owned by a [`Store`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    store: StoreId,
+    index: usize,
+}
+
+impl Instance {
+    /// Instantiates a module without imports into `store`: initializes its globals,
+    /// tables and memories, and runs its start function.
+    ///
+    /// Fails with a [`LinkError`](crate::errors::LinkError) if the module has imports, or
+    /// with a trap if initializing its segments goes out of bounds or the start function
+    /// traps. Modules with imports are instantiated through a [`Linker`](crate::Linker).
+    pub fn new(store: &mut Store, module: &Module) -> Result<Instance, Error> {
+        Linker::new(&Engine::new()).instantiate(store, module)
+    }
+
+    // Instantiates `module` with the values its imports resolve to, in import order.
+    pub(crate) fn with_imports(
+        store: &mut Store,
+        module: &Module,
+        externvals: &[ExternVal],
+    ) -> Result<Instance, Error> {
+        let inst = store.store.instantiate(&module.module, externvals)?;
+        store.instances.push(inst);
+
+        Ok(Instance {
+            store: store.store.id,
+            index: store.instances.len() - 1,
+        })
+    }
+
+    /// Returns the exports of the instance by name, in declaration order.
+    pub fn exports<'a>(&self, store: &'a Store) -> impl Iterator<Item = (&'a str, Extern)> + 'a {
+        store.check(self.store);
+        store.instances[self.index]
+            .exports
+            .iter()
+            .map(move |e| (e.name.as_str(), Extern::new(store.store.id, e.value)))
+    }
+
+    /// Looks up the value exported under `name`.
+    pub fn get_export(&self, store: &Store, name: &str) -> Option<Extern> {
+        store.check(self.store);
+        let inst = &store.instances[self.index];
+        let export = inst.exports.iter().find(|e| e.name == name)?;
+        Some(Extern::new(store.store.id, export.value))
+    }
+
+    /// Looks up the function exported under `name`.
+    pub fn get_func(&self, store: &Store, name: &str) -> Option<Func> {
+        self.get_export(store, name)?.into_func()
+    }
+
+    /// Looks up the function at `index` in the function index space of the instance,
+    /// where imported functions come first.
+    pub fn get_func_by_index(&self, store: &Store, index: u32) -> Option<Func> {
+        store.check(self.store);
+        let addr = *store.instances[self.index].funcaddrs.get(index as usize)?;
+        Some(Func {
+            store: store.store.id,
+            addr,
+        })
+    }
+
+    /// Looks up the memory exported under `name`.
+    pub fn get_memory(&self, store: &Store, name: &str) -> Option<Memory> {
+        self.get_export(store, name)?.into_memory()
+    }
+
+    /// Looks up the function exported under `name` and checks that it takes `Params`
+    /// and returns `Results`.
+    ///
+    /// `Params` and `Results` are a single value type such as `i32`, or a tuple of them,
+    /// with `()` meaning none.
+    pub fn get_typed_func<Params, Results>(
+        &self,
+        store: &Store,
+        name: &str,
+    ) -> Result<TypedFunc<Params, Results>, Error>
+    where
+        Params: WasmTypeList,
+        Results: WasmTypeList,
+    {
+        let func = self
+            .get_func(store, name)
+            .ok_or_else(|| Error::InvalidParameters(format!("unknown function {}", name)))?;
+        func.typed(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn funcs_by_index() {
+        let engine = Engine::new();
+        let text = r#"(module (func) (func (export "f")))"#;
+        let module = Module::from_wat(&engine, text).unwrap();
+        let mut store = Store::new(&engine);
+        let instance = Instance::new(&mut store, &module).unwrap();
+        let f = instance.get_func(&store, "f");
+        assert_eq!(instance.get_func_by_index(&store, 1), f);
+        assert_eq!(instance.get_func_by_index(&store, 2), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "handle used with a store it does not belong to")]
+    fn foreign_handles() {
+        let engine = Engine::new();
+        let module = Module::from_wat(&engine, r#"(module (memory (export "m") 1))"#).unwrap();
+        let mut store = Store::new(&engine);
+        let instance = Instance::new(&mut store, &module).unwrap();
+        let mem = instance.get_memory(&store, "m").unwrap();
+
+        let other = Store::new(&engine);
+        mem.size(&other);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::embedding::externs::Extern;
+use crate::embedding::funcs::Caller;
+use crate::embedding::instances::{Instance, Store};
+use crate::embedding::modules::{Engine, Module};
+use crate::errors::{Error, LinkError, Trap};
+use crate::execution::modules::check_import;
+use crate::execution::runtime::{ExternVal, FuncInst, HostFunc};
+use crate::execution::stack::Val;
+use crate::structure::types::{ExternType, FuncType};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Defines the values that imports are resolved to, by module and field name: host
+/// functions, and the exports of instances registered under a module name.
+///
+/// ```
+/// use rasm::structure::types::{FuncType, NumType, ResultType, ValType};
+/// use rasm::{Engine, Linker, Val};
+///
+/// let engine = Engine::new();
+/// let mut linker = Linker::new(&engine);
+/// let i32_ = ValType::NumType(NumType::I32);
+/// let ty = FuncType(ResultType(vec![i32_]), ResultType(vec![]));
+/// linker.func("env", "log", ty, |_caller, args| {
+///     println!("log: {:?}", args[0]);
+///     Ok(vec![])
+/// });
+/// ```
+#[derive(Debug, Default)]
+pub struct Linker {
+    definitions: HashMap<(String, String), Definition>,
+}
+
+#[derive(Debug)]
+enum Definition {
+    // A host function, allocated once in each store that it is imported into.
+    Host(HostFuncId, FuncInst),
+    Extern(Extern),
+}
+
+// Identifies a host function across the stores it is allocated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct HostFuncId(usize);
+
+static NEXT_HOST_FUNC_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Linker {
+    pub fn new(_engine: &Engine) -> Linker {
+        Default::default()
+    }
+
+    /// Defines a host function of type `ty` under `module` and `name`, replacing any
+    /// previous definition.
+    ///
+    /// The function is called with the calling instance and the arguments, which match
+    /// the parameter types of `ty`. It must return values of the result types of `ty`,
+    /// or a trap that aborts the call, such as [`Trap::Host`].
+    ///
+    /// The function is allocated in a store when the first instance importing it is
+    /// created there, and later instances in that store share it.
+    pub fn func<F>(&mut self, module: &str, name: &str, ty: FuncType, f: F) -> &mut Linker
+    where
+        F: Fn(&mut Caller, &[Val]) -> Result<Vec<Val>, Trap> + 'static,
+    {
+        let funcinst = FuncInst::Host {
+            type_: ty,
+            hostcode: HostFunc(Rc::new(f)),
+        };
+        let id = HostFuncId(NEXT_HOST_FUNC_ID.fetch_add(1, Ordering::Relaxed));
+        self.insert(module, name, Definition::Host(id, funcinst))
+    }
+
+    /// Defines `item` under `module` and `name`, replacing any previous definition.
+    /// Instances that import it share it by reference.
+    pub fn define(&mut self, module: &str, name: &str, item: impl Into<Extern>) -> &mut Linker {
+        self.insert(module, name, Definition::Extern(item.into()))
+    }
+
+    /// Defines every export of `instance` under `module` and its export name.
+    pub fn instance(&mut self, store: &Store, module: &str, instance: Instance) -> &mut Linker {
+        for (name, item) in instance.exports(store) {
+            self.define(module, name, item);
+        }
+        self
+    }
+
+    /// Instantiates `module` into `store`, resolving its imports to the definitions of
+    /// this linker, and runs its start function.
+    ///
+    /// Each import must match the type of its definition: function and global types
+    /// must be equal, and the current limits of tables and memories must lie within the
+    /// imported limits.
+    pub fn instantiate(&self, store: &mut Store, module: &Module) -> Result<Instance, Error> {
+        // Every import is resolved and checked before host functions are allocated, so
+        // that a failing instantiation leaves the store unchanged.
+        let mut definitions = vec![];
+        for import in &module.module.imports {
+            let key = (import.module.0.clone(), import.name.0.clone());
+            let definition = self.definitions.get(&key).ok_or(LinkError::UnknownImport {
+                module: key.0,
+                name: key.1,
+            })?;
+
+            let actual = match definition {
+                Definition::Host(_, funcinst) => ExternType::Func(funcinst.type_().clone()),
+                Definition::Extern(item) => item.value(store).type_(&store.store),
+            };
+            check_import(&module.module, import, &actual)?;
+            definitions.push(definition);
+        }
+
+        let externvals: Vec<_> = definitions
+            .into_iter()
+            .map(|definition| match definition {
+                Definition::Host(id, funcinst) => ExternVal::Func(store.host_func(*id, funcinst)),
+                Definition::Extern(item) => item.value(store),
+            })
+            .collect();
+
+        Instance::with_imports(store, module, &externvals)
+    }
+
+    fn insert(&mut self, module: &str, name: &str, definition: Definition) -> &mut Linker {
+        let key = (module.to_string(), name.to_string());
+        self.definitions.insert(key, definition);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::types::{NumType, ResultType, ValType};
+
+    // (module
+    //   (import "env" "f" (func (param i32) (result i32)))
+    //   (memory 1)
+    //   (func (export "g") (param i32) (result i32) local.get 0 call 0)
+    //   (data (i32.const 0) "\2a"))
+    const CALL_HOST: [u8; 62] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01,
+        0x7f, 0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00, 0x03, 0x02, 0x01,
+        0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x05, 0x01, 0x01, 0x67, 0x00, 0x01, 0x0a, 0x08,
+        0x01, 0x06, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b, 0x0b, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0b,
+        0x01, 0x2a,
+    ];
+
+    fn func_type(params: usize, results: usize) -> FuncType {
+        let i32_ = ValType::NumType(NumType::I32);
+        FuncType(
+            ResultType(vec![i32_.clone(); params]),
+            ResultType(vec![i32_; results]),
+        )
+    }
+
+    fn call_g(linker: &Linker, arg: i32) -> Result<Vec<Val>, Error> {
+        let engine = Engine::new();
+        let module = Module::new(&engine, CALL_HOST)?;
+        let mut store = Store::new(&engine);
+        let instance = linker.instantiate(&mut store, &module)?;
+        let g = instance.get_func(&store, "g").unwrap();
+        g.call(&mut store, &[Val::I32(arg)])
+    }
+
+    #[test]
+    fn host_funcs() {
+        let mut linker = Linker::new(&Engine::new());
+        // Adds the first byte of the caller's memory to the argument.
+        linker.func("env", "f", func_type(1, 1), |caller, args| {
+            let byte = caller.memory().unwrap()[0];
+            match args {
+                [Val::I32(x)] => Ok(vec![Val::I32(x + byte as i32)]),
+                _ => Err(Trap::Host("bad args".to_string())),
+            }
+        });
+        assert_eq!(call_g(&linker, 1).unwrap(), vec![Val::I32(43)]);
+        assert_eq!(call_g(&linker, -1).unwrap(), vec![Val::I32(41)]);
+    }
+
+    #[test]
+    fn host_traps() {
+        let mut linker = Linker::new(&Engine::new());
+        linker.func("env", "f", func_type(1, 1), |_, _| {
+            Err(Trap::Host("oops".to_string()))
+        });
+        assert_eq!(call_g(&linker, 1).unwrap_err().to_string(), "trap: oops");
+    }
+
+    #[test]
+    fn link_errors() {
+        let mut linker = Linker::new(&Engine::new());
+        let err = call_g(&linker, 1).unwrap_err();
+        assert_eq!(err.to_string(), "unknown import env.f");
+
+        linker.func("env", "f", func_type(1, 0), |_, _| Ok(vec![]));
+        let err = call_g(&linker, 1).unwrap_err();
+        assert_eq!(err.to_string(), "incompatible import type for env.f");
+    }
+
+    #[test]
+    fn host_func_allocation() {
+        let engine = Engine::new();
+        let mut store = Store::new(&engine);
+        let mut linker = Linker::new(&engine);
+        linker.func("env", "f", func_type(1, 1), |_, args| Ok(args.to_vec()));
+
+        // Nothing is allocated for the host function if another import fails.
+        let text = r#"(module
+          (import "env" "f" (func (param i32) (result i32)))
+          (import "env" "m" (memory 1)))"#;
+        let module = Module::from_wat(&engine, text).unwrap();
+        let err = linker.instantiate(&mut store, &module).unwrap_err();
+        assert_eq!(err.to_string(), "unknown import env.m");
+        assert!(store.store.funcs.is_empty());
+
+        // Instances in the same store share the host function.
+        let module = Module::new(&engine, CALL_HOST).unwrap();
+        linker.instantiate(&mut store, &module).unwrap();
+        linker.instantiate(&mut store, &module).unwrap();
+        assert_eq!(store.store.funcs.len(), 3);
+    }
+
+    // (module (memory (export "m") 1) (data (i32.const 0) "\2a"))
+    const EXPORT_MEM: [u8; 29] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x05,
+        0x01, 0x01, 0x6d, 0x02, 0x00, 0x0b, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x01, 0x2a,
+    ];
+
+    // (module
+    //   (import "a" "m" (memory 1))
+    //   (func (export "load") (result i32) i32.const 0 i32.load8_u))
+    const IMPORT_MEM: [u8; 50] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f,
+        0x02, 0x08, 0x01, 0x01, 0x61, 0x01, 0x6d, 0x02, 0x00, 0x01, 0x03, 0x02, 0x01, 0x00, 0x07,
+        0x08, 0x01, 0x04, 0x6c, 0x6f, 0x61, 0x64, 0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x41,
+        0x00, 0x2d, 0x00, 0x00, 0x0b,
+    ];
+
+    #[test]
+    fn shared_instances() {
+        let engine = Engine::new();
+        let mut store = Store::new(&engine);
+        let mut linker = Linker::new(&engine);
+        let a = Module::new(&engine, EXPORT_MEM).unwrap();
+        let a = linker.instantiate(&mut store, &a).unwrap();
+        linker.instance(&store, "a", a);
+
+        let b = Module::new(&engine, IMPORT_MEM).unwrap();
+        let b = linker.instantiate(&mut store, &b).unwrap();
+        let load = b.get_typed_func::<(), i32>(&store, "load").unwrap();
+        assert_eq!(load.call(&mut store, ()).unwrap(), 42);
+
+        // Both instances see writes to the memory through either of them.
+        let mem = a.get_memory(&store, "m").unwrap();
+        mem.data_mut(&mut store)[0] = 7;
+        assert_eq!(load.call(&mut store, ()).unwrap(), 7);
+    }
+
+    #[test]
+    fn cloned_stores() {
+        let engine = Engine::new();
+        let mut store = Store::new(&engine);
+        let mut linker = Linker::new(&engine);
+        let a = Module::new(&engine, EXPORT_MEM).unwrap();
+        let a = linker.instantiate(&mut store, &a).unwrap();
+        linker.instance(&store, "a", a);
+        let b = Module::new(&engine, IMPORT_MEM).unwrap();
+        let b = linker.instantiate(&mut store, &b).unwrap();
+        let load = b.get_typed_func::<(), i32>(&store, "load").unwrap();
+
+        // A clone has its own copy of the instances, which outlive the original store.
+        let mut clone = store.clone();
+        let mem = a.get_memory(&store, "m").unwrap();
+        mem.data_mut(&mut store)[0] = 7;
+        drop(store);
+        assert_eq!(load.call(&mut clone, ()).unwrap(), 42);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod externs;
+pub mod funcs;
+pub mod instances;
+pub mod linker;
+pub mod modules;
+pub mod values;
@@ -0,0 +1,88 @@
+use crate::binary;
+use crate::errors::Error;
+use crate::structure::modules as structure;
+use crate::text;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Global configuration shared by the modules and stores created from it.
+///
+/// There is nothing to configure yet, but every [`Module`] and [`Store`](crate::Store) is
+/// created through an engine so that options can be added without breaking embedders.
+#[derive(Debug, Clone, Default)]
+pub struct Engine {}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {}
+    }
+}
+
+/// A decoded and validated WebAssembly module.
+///
+/// A module is stateless and cheap to clone; it can be instantiated any number of times
+/// with [`Instance::new`](crate::Instance::new).
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub(crate) module: Rc<structure::Module>,
+}
+
+impl Module {
+    /// Decodes and validates a module from its binary format.
+    pub fn new(engine: &Engine, bytes: impl AsRef<[u8]>) -> Result<Module, Error> {
+        Module::from_reader(engine, &mut bytes.as_ref())
+    }
+
+    /// Parses and validates a module from the text format.
+    pub fn from_wat(_engine: &Engine, text: impl AsRef<str>) -> Result<Module, Error> {
+        let module = text::modules::module(text.as_ref())?;
+        Module::validated(module)
+    }
+
+    /// Loads and validates the module stored in the file at `path`, which is read as text
+    /// if it has a `.wat` extension and as a binary otherwise.
+    pub fn from_file(engine: &Engine, path: impl AsRef<Path>) -> Result<Module, Error> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "wat") {
+            return Module::from_wat(engine, std::fs::read_to_string(path)?);
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        Module::from_reader(engine, &mut reader)
+    }
+
+    fn from_reader<R: Read>(_engine: &Engine, reader: &mut R) -> Result<Module, Error> {
+        binary::modules::magic(reader)?;
+        binary::modules::version(reader)?;
+        let module = binary::modules::sections(reader)?;
+        Module::validated(module)
+    }
+
+    fn validated(module: structure::Module) -> Result<Module, Error> {
+        module.validate()?;
+        Ok(Module {
+            module: Rc::new(module),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> String {
+        Module::new(&Engine::new(), bytes).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(decode(b"\0asn\x01\0\0\0"), "magic header not detected");
+        assert_eq!(decode(b"\0asm\x02\0\0\0"), "unknown binary version");
+        // A type section that ends before its only type.
+        assert_eq!(
+            decode(b"\0asm\x01\0\0\0\x01\x01\x01"),
+            "unexpected end of section or function"
+        );
+    }
+}
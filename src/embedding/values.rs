@@ -0,0 +1,109 @@
+use crate::execution::stack::Val;
+use crate::structure::types::{NumType, ValType};
+
+/// A Rust type that corresponds to a WebAssembly value type.
+pub trait WasmTy: Sized {
+    fn val_type() -> ValType;
+    fn into_val(self) -> Val;
+    fn from_val(v: Val) -> Option<Self>;
+}
+
+macro_rules! wasm_ty {
+    ($t:ty, $variant:ident, $num_type:ident) => {
+        impl WasmTy for $t {
+            fn val_type() -> ValType {
+                ValType::NumType(NumType::$num_type)
+            }
+
+            fn into_val(self) -> Val {
+                Val::$variant(self)
+            }
+
+            fn from_val(v: Val) -> Option<Self> {
+                match v {
+                    Val::$variant(v) => Some(v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+wasm_ty!(i32, I32, I32);
+wasm_ty!(i64, I64, I64);
+wasm_ty!(f32, F32, F32);
+wasm_ty!(f64, F64, F64);
+
+/// The parameters or results of a typed function: `()`, a single [`WasmTy`] or a tuple
+/// of them.
+pub trait WasmTypeList: Sized {
+    fn types() -> Vec<ValType>;
+    fn into_vals(self) -> Vec<Val>;
+    fn from_vals(vals: &[Val]) -> Option<Self>;
+}
+
+impl WasmTypeList for () {
+    fn types() -> Vec<ValType> {
+        vec![]
+    }
+
+    fn into_vals(self) -> Vec<Val> {
+        vec![]
+    }
+
+    fn from_vals(vals: &[Val]) -> Option<Self> {
+        if vals.is_empty() {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: WasmTy> WasmTypeList for T {
+    fn types() -> Vec<ValType> {
+        vec![T::val_type()]
+    }
+
+    fn into_vals(self) -> Vec<Val> {
+        vec![self.into_val()]
+    }
+
+    fn from_vals(vals: &[Val]) -> Option<Self> {
+        match vals {
+            [v] => T::from_val(*v),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! wasm_type_list {
+    ($($t:ident $v:ident),+) => {
+        impl<$($t: WasmTy),+> WasmTypeList for ($($t,)+) {
+            fn types() -> Vec<ValType> {
+                vec![$($t::val_type()),+]
+            }
+
+            fn into_vals(self) -> Vec<Val> {
+                let ($($v,)+) = self;
+                vec![$($v.into_val()),+]
+            }
+
+            fn from_vals(vals: &[Val]) -> Option<Self> {
+                match vals {
+                    [$($v),+] => Some(($($t::from_val(*$v)?,)+)),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+wasm_type_list!(A a);
+wasm_type_list!(A a, B b);
+wasm_type_list!(A a, B b, C c);
+wasm_type_list!(A a, B b, C c, D d);
+wasm_type_list!(A a, B b, C c, D d, E e);
+wasm_type_list!(A a, B b, C c, D d, E e, F f);
+wasm_type_list!(A a, B b, C c, D d, E e, F f, G g);
+wasm_type_list!(A a, B b, C c, D d, E e, F f, G g, H h);
@@ -1,10 +1,376 @@
-use thiserror::Error;
+use crate::leb128;
+use crate::structure::types::ValType;
+use std::io;
+use thiserror::Error as TError;
 
-#[derive(Debug, Error)]
-pub enum RuntimeError {
-    #[error("invalid wasm binary: {0}")]
-    InvalidWasmError(String),
+/// Any error of loading, instantiating or running a module, classified by the stage
+/// that failed.
+#[derive(Debug, TError)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
 
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    #[error(transparent)]
+    Link(#[from] LinkError),
+
+    #[error("trap: {0}")]
+    Trap(#[from] Trap),
+
+    /// The module needs more resources than the implementation supports, so it cannot
+    /// be instantiated.
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+
+    /// The embedder asked for something the module does not provide, such as calling a
+    /// missing export or passing arguments of the wrong types.
     #[error("invalid parameters: {0}")]
     InvalidParameters(String),
 }
+
+/// A malformed binary module.
+#[derive(Debug, TError)]
+pub enum DecodeError {
+    #[error(transparent)]
+    Io(io::Error),
+
+    #[error("unexpected end")]
+    UnexpectedEnd,
+
+    /// A section or function body that ends before its contents.
+    #[error("unexpected end of section or function")]
+    UnexpectedEndOfSection,
+
+    /// A name longer than the rest of its section.
+    #[error("length out of bounds")]
+    LengthOutOfBounds,
+
+    #[error("magic header not detected")]
+    InvalidMagic,
+
+    #[error("unknown binary version")]
+    InvalidVersion,
+
+    #[error("integer too large")]
+    IntegerTooLarge,
+
+    #[error("integer representation too long")]
+    IntegerTooLong,
+
+    #[error("malformed UTF-8 encoding")]
+    InvalidUtf8,
+
+    #[error("malformed section id")]
+    InvalidSectionId(u8),
+
+    /// A section that is out of order or occurs a second time.
+    #[error("junk after last section")]
+    UnorderedSection(u8),
+
+    #[error("section size mismatch")]
+    SectionSizeMismatch,
+
+    #[error("function and code section have inconsistent lengths")]
+    FuncCodeMismatch,
+
+    #[error("data count and data section have inconsistent lengths")]
+    DataCountMismatch,
+
+    #[error("data count section required")]
+    DataCountRequired,
+
+    #[error("too many locals")]
+    TooManyLocals,
+
+    #[error("nesting too deep")]
+    NestingTooDeep,
+
+    #[error("unexpected else")]
+    UnexpectedElse,
+
+    #[error("illegal opcode {0:#x}")]
+    IllegalOpcode(u8),
+
+    #[error("illegal opcode 0xfc {0}")]
+    IllegalPrefixedOpcode(u32),
+
+    #[error("invalid block type")]
+    InvalidBlockType,
+
+    #[error("invalid func type")]
+    InvalidFuncType(u8),
+
+    #[error("zero flag expected")]
+    ZeroByteExpected,
+
+    #[error("unknown value type {0}")]
+    UnknownValType(u8),
+
+    #[error("unknown reference type {0}")]
+    UnknownRefType(u8),
+
+    #[error("invalid limits flag {0}")]
+    InvalidLimitsFlag(u8),
+
+    #[error("malformed mutability {0}")]
+    InvalidMutability(u8),
+
+    #[error("invalid element segment flag {0}")]
+    InvalidElemFlag(u32),
+
+    #[error("invalid element kind {0}")]
+    InvalidElemKind(u8),
+
+    #[error("malformed element type {0}")]
+    InvalidElemType(u8),
+
+    #[error("invalid data segment flag {0}")]
+    InvalidDataFlag(u32),
+
+    #[error("unknown import descriptor")]
+    UnknownImportDesc(u8),
+
+    #[error("unknown export descriptor")]
+    UnknownExportDesc(u8),
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => DecodeError::UnexpectedEnd,
+            _ => DecodeError::Io(e),
+        }
+    }
+}
+
+impl From<leb128::decode::Error> for DecodeError {
+    fn from(e: leb128::decode::Error) -> Self {
+        match e {
+            leb128::decode::Error::Io(e) => e.into(),
+            leb128::decode::Error::Overflow => DecodeError::IntegerTooLarge,
+            leb128::decode::Error::TooLong => DecodeError::IntegerTooLong,
+        }
+    }
+}
+
+/// A malformed text module, with the line and column where parsing failed.
+#[derive(Debug, TError)]
+#[error("{line}:{column}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// An invalid module, with the function, its name from the name section, and the byte
+/// offset of the instruction in the binary module when the reason lies in code.
+#[derive(Debug, TError)]
+#[error("invalid module: {kind}{}", location(.func, .name, .offset))]
+pub struct ValidationError {
+    pub func: Option<u32>,
+    pub name: Option<String>,
+    pub offset: Option<usize>,
+    pub kind: ValidationErrorKind,
+}
+
+/// The reason a module is invalid. Unknown indices are reported with the index.
+#[derive(Debug, Clone, PartialEq, TError)]
+pub enum ValidationErrorKind {
+    /// An operand of type `found`, or none, where one of type `expected`, or of any
+    /// type, is required.
+    #[error("type mismatch")]
+    TypeMismatch {
+        expected: Option<ValType>,
+        found: Option<ValType>,
+    },
+
+    /// The targets of a br_table carry different numbers of values.
+    #[error("type mismatch")]
+    ArityMismatch { expected: usize, found: usize },
+
+    #[error("empty control stack")]
+    EmptyControlStack,
+
+    #[error("unknown type {0}")]
+    UnknownType(u32),
+
+    #[error("unknown function {0}")]
+    UnknownFunc(u32),
+
+    #[error("unknown table {0}")]
+    UnknownTable(u32),
+
+    #[error("unknown memory {0}")]
+    UnknownMem(u32),
+
+    #[error("unknown global {0}")]
+    UnknownGlobal(u32),
+
+    #[error("unknown local {0}")]
+    UnknownLocal(u32),
+
+    #[error("unknown label {0}")]
+    UnknownLabel(u32),
+
+    #[error("unknown elem segment {0}")]
+    UnknownElem(u32),
+
+    #[error("unknown data segment {0}")]
+    UnknownData(u32),
+
+    #[error("unknown instruction")]
+    UnknownInstruction,
+
+    #[error("constant expression required")]
+    ConstantExprRequired,
+
+    #[error("global is immutable")]
+    ImmutableGlobal(u32),
+
+    #[error("invalid result arity")]
+    InvalidResultArity,
+
+    #[error("undeclared function reference")]
+    UndeclaredFuncRef(u32),
+
+    #[error("alignment must not be larger than natural")]
+    Alignment,
+
+    #[error("nesting too deep")]
+    NestingTooDeep,
+
+    #[error("multiple memories")]
+    MultipleMemories,
+
+    #[error("start function")]
+    StartFunction,
+
+    #[error("duplicate export name")]
+    DuplicateExportName(String),
+
+    #[error("size minimum must not be greater than maximum")]
+    LimitsMinGreaterThanMax,
+
+    #[error("table size must be at most 2^32-1")]
+    TableSizeTooLarge,
+
+    #[error("memory size must be at most 65536 pages (4GiB)")]
+    MemSizeTooLarge,
+}
+
+fn location(func: &Option<u32>, name: &Option<String>, offset: &Option<usize>) -> String {
+    let func = match (func, name) {
+        (Some(func), Some(name)) => format!("func {} ${}", func, name),
+        (Some(func), None) => format!("func {}", func),
+        _ => return String::new(),
+    };
+    match offset {
+        Some(offset) => format!(" ({}, at offset {:#x})", func, offset),
+        None => format!(" ({})", func),
+    }
+}
+
+/// A module whose imports cannot be satisfied.
+#[derive(Debug, TError)]
+pub enum LinkError {
+    #[error("unknown import {module}.{name}")]
+    UnknownImport { module: String, name: String },
+
+    #[error("incompatible import type for {module}.{name}")]
+    IncompatibleImportType { module: String, name: String },
+
+    #[error("expected {expected} imports, found {found}")]
+    ImportCount { expected: usize, found: usize },
+}
+
+/// A table or memory exceeding the implementation limit for its size, which is
+/// checked before it is allocated.
+#[derive(Debug, Clone, PartialEq, TError)]
+pub enum LimitError {
+    #[error("table of {0} elements exceeds the implementation limit")]
+    Table(u32),
+
+    #[error("memory of {0} pages exceeds the implementation limit")]
+    Memory(u32),
+}
+
+/// Traps: https://webassembly.github.io/spec/core/intro/overview.html#trap
+#[derive(Debug, Clone, PartialEq, TError)]
+pub enum Trap {
+    #[error("unreachable")]
+    Unreachable,
+
+    #[error("integer overflow")]
+    IntegerOverflow,
+
+    #[error("integer divide by zero")]
+    IntegerDivideByZero,
+
+    #[error("invalid conversion to integer")]
+    InvalidConversionToInteger,
+
+    #[error("out of bounds memory access")]
+    OutOfBoundsMemoryAccess,
+
+    #[error("out of bounds table access")]
+    OutOfBoundsTableAccess,
+
+    #[error("undefined element")]
+    UndefinedElement,
+
+    #[error("uninitialized element {0}")]
+    UninitializedElement(u32),
+
+    #[error("indirect call type mismatch")]
+    IndirectCallTypeMismatch,
+
+    #[error("call stack exhausted")]
+    CallStackExhausted,
+
+    /// Raised by a host function.
+    #[error("{0}")]
+    Host(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_errors() {
+        let eof = io::Error::new(io::ErrorKind::UnexpectedEof, "eof");
+        let e = Error::from(DecodeError::from(eof));
+        assert_eq!(e.to_string(), "unexpected end");
+        let e = Error::from(DecodeError::from(leb128::decode::Error::Overflow));
+        assert_eq!(e.to_string(), "integer too large");
+        let e = Error::from(DecodeError::from(leb128::decode::Error::TooLong));
+        assert_eq!(e.to_string(), "integer representation too long");
+    }
+
+    #[test]
+    fn messages() {
+        // Messages match the ones the spec testsuite expects.
+        let e = Error::from(Trap::IntegerDivideByZero);
+        assert_eq!(e.to_string(), "trap: integer divide by zero");
+        let e = Error::from(ValidationError {
+            func: Some(2),
+            name: None,
+            offset: None,
+            kind: ValidationErrorKind::UnknownLocal(5),
+        });
+        assert_eq!(e.to_string(), "invalid module: unknown local 5 (func 2)");
+        let e = Error::from(LinkError::UnknownImport {
+            module: "env".to_string(),
+            name: "f".to_string(),
+        });
+        assert_eq!(e.to_string(), "unknown import env.f");
+    }
+}
@@ -1,94 +1,1874 @@
-use crate::errors::RuntimeError::InvalidWasmError;
-use crate::execution::runtime::FuncInst;
+use crate::embedding::funcs::Caller;
+use crate::errors::Trap;
+use crate::execution::runtime::{FuncInst, HostFunc, MemInst, ModuleInst, Store, TableInst};
 use crate::execution::stack::Val::{F32, F64, I32, I64};
-use crate::execution::stack::{Frame, FrameStack, LabelStack, Val, ValueStack};
-use crate::structure::instructions::Instr;
-use crate::structure::types::ValType;
-use std::error::Error;
+use crate::execution::stack::{
+    Frame, FrameStack, FuncRef, Label, LabelStack, Ref, Val, ValueStack,
+};
+use crate::structure::instructions::{BlockType, Expr, Instr};
+use crate::structure::modules::{Func, LabelIdx, TableIdx};
+use crate::structure::types::{FuncType, NumType, ValType};
+use std::rc::Rc;
+
+// Bounds the depth of nested calls so that runaway recursion traps instead of
+// exhausting host memory. Calls don't grow the native stack, since frames are kept on
+// the frame stack.
+const MAX_CALL_DEPTH: usize = 1024;
+
+const F32_SIGN: u32 = 1 << 31;
+const F64_SIGN: u64 = 1 << 63;
+
+// The most significant bit of the payload, which is set in quiet NaNs.
+const F32_QUIET: u32 = 0x0040_0000;
+const F64_QUIET: u64 = 0x0008_0000_0000_0000;
+
+// Exclusive bounds of the floats that truncate to an i64 or u64. The lower i64 bound
+// is the greatest f64 below -2^63.
+const I64_LOWER: f64 = -9223372036854777856.0;
+const I64_UPPER: f64 = 9223372036854775808.0;
+const U64_UPPER: f64 = 18446744073709551616.0;
+
+// A sequence of instructions being executed: the body of a function, block, loop or
+// if. Each one has a label on the label stack of the current frame, except for
+// constant expressions.
+struct Control<'a> {
+    instrs: &'a [Instr],
+    // The position of the next instruction.
+    pc: usize,
+    // Whether a branch to the label restarts the sequence instead of leaving it.
+    loop_: bool,
+    // Whether the sequence is the else branch of an if.
+    else_: bool,
+}
+
+// A function whose execution is suspended while it calls another, with the `pc`,
+// `loop_` and `else_` of each sequence it has entered, from the body inwards.
+struct Activation {
+    module: Rc<ModuleInst>,
+    code: Rc<Func>,
+    positions: Vec<(usize, bool, bool)>,
+}
+
+// How the execution of a function body or constant expression stopped.
+enum Exit {
+    Return,
+    // A call of a function defined by a module, whose arguments are on the stack.
+    Call(FuncInst),
+}
 
 impl FuncInst {
+    // Calls the function with the arguments on top of the stack. `caller` is the
+    // instance of the calling function, if any, whose memory host functions can access.
+    //
+    // Calls between module functions are made in a loop rather than by recursion: the
+    // caller is suspended on a stack of activations and resumed when the callee returns.
     pub fn call(
         &self,
+        caller: Option<&ModuleInst>,
+        store: &mut Store,
         value_stack: &mut ValueStack,
         frame_stack: &mut FrameStack,
-    ) -> Result<(), Box<dyn Error>> {
-        let mut locals = vec![];
+    ) -> Result<(), Trap> {
+        let mut current = match self {
+            FuncInst::Module { .. } => enter(self, value_stack, frame_stack),
+            FuncInst::Host { type_, hostcode } => {
+                return call_host(type_, hostcode, caller, store, value_stack);
+            }
+        };
+        let mut activations = vec![];
 
-        for _ in &self.type_.0 .0 {
-            let v = value_stack.pop().ok_or("result error")?;
-            locals.push(v);
-        }
-        locals.reverse();
+        loop {
+            let code = current.code.clone();
+            let module = current.module.clone();
+            let mut controls = resume(&code.body.0, &current.positions);
 
-        for _ in &self.code.locals {
-            locals.push(Val::I32(0));
+            match execute(&module, store, &mut controls, value_stack, frame_stack)? {
+                Exit::Return => {
+                    frame_stack.pop();
+                    match activations.pop() {
+                        Some(activation) => current = activation,
+                        None => return Ok(()),
+                    }
+                }
+                Exit::Call(funcinst) => {
+                    current.positions = controls.iter().map(|c| (c.pc, c.loop_, c.else_)).collect();
+                    activations.push(current);
+                    current = enter(&funcinst, value_stack, frame_stack);
+                }
+            }
         }
+    }
+}
+
+// Pushes the frame of a module function, with the arguments on top of the stack as its
+// first locals, and returns its activation at the start of the body.
+fn enter(funcinst: &FuncInst, stack: &mut ValueStack, frames: &mut FrameStack) -> Activation {
+    let (type_, module, code) = match funcinst {
+        FuncInst::Module {
+            type_,
+            module,
+            code,
+        } => (type_, module, code),
+        FuncInst::Host { .. } => unreachable!("host functions have no frame"),
+    };
+
+    let mut locals = vec![];
 
-        frame_stack.push(Frame {
-            locals: &mut locals,
-            module: self.module,
-            labels: LabelStack(vec![]),
+    for _ in &type_.0 .0 {
+        let v = stack.pop().expect("empty stack");
+        locals.push(v);
+    }
+    locals.reverse();
+
+    for t in &code.locals {
+        let v = match t {
+            ValType::NumType(NumType::I32) => I32(0),
+            ValType::NumType(NumType::I64) => I64(0),
+            ValType::NumType(NumType::F32) => F32(0.0),
+            ValType::NumType(NumType::F64) => F64(0.0),
+            ValType::RefType(t) => Val::Ref(Ref::Null(*t)),
+        };
+        locals.push(v);
+    }
+
+    // The body is executed as a block whose label carries the function results.
+    let mut labels = LabelStack(vec![]);
+    labels.push(Label {
+        n: type_.1 .0.len(),
+        height: stack.len(),
+    });
+    frames.push(Frame {
+        locals,
+        module: module.clone(),
+        labels,
+    });
+
+    Activation {
+        module: module.clone(),
+        code: code.clone(),
+        positions: vec![(0, false, false)],
+    }
+}
+
+// Restores the sequences of a suspended function from their positions. Each sequence
+// after the body is entered by the instruction before the position of the previous one.
+fn resume<'a>(body: &'a [Instr], positions: &[(usize, bool, bool)]) -> Vec<Control<'a>> {
+    let mut controls: Vec<Control> = vec![];
+    for &(pc, loop_, else_) in positions {
+        let instrs = match controls.last() {
+            None => body,
+            Some(parent) => match &parent.instrs[parent.pc - 1] {
+                Instr::Block(_, instrs) | Instr::Loop(_, instrs) => instrs,
+                Instr::If(_, _, instrs) if else_ => instrs,
+                Instr::If(_, instrs, _) => instrs,
+                instr => unreachable!("{:?} has no body", instr),
+            },
+        };
+        controls.push(Control {
+            instrs,
+            pc,
+            loop_,
+            else_,
         });
+    }
+    controls
+}
 
-        for instr in &self.code.body.0 {
-            self.invoke(&instr, &mut locals, value_stack)?;
-        }
+// Calls a host function with the arguments on top of the stack and pushes its results.
+fn call_host(
+    type_: &FuncType,
+    hostcode: &HostFunc,
+    caller: Option<&ModuleInst>,
+    store: &mut Store,
+    stack: &mut ValueStack,
+) -> Result<(), Trap> {
+    let height = stack.len() - type_.0 .0.len();
+    let args = stack.0.split_off(height);
+    let results = (hostcode.0)(&mut Caller::new(store, caller), &args)?;
 
-        Ok(())
+    let types: Vec<ValType> = results.iter().map(Val::type_).collect();
+    if types != type_.1 .0 {
+        return Err(Trap::Host(format!(
+            "host function returned values of types {:?}, expected {:?}",
+            types, type_.1 .0
+        )));
     }
+    if !results.iter().all(|v| store.contains(v)) {
+        return Err(Trap::Host(
+            "host function returned a function reference not in the store".to_string(),
+        ));
+    }
+    stack.0.extend(results);
+
+    Ok(())
+}
+
+// Evaluates a constant expression, such as a global initializer or a segment offset,
+// outside of any function.
+pub fn eval_const(inst: &Rc<ModuleInst>, store: &mut Store, expr: &Expr) -> Result<Val, Trap> {
+    let mut stack = ValueStack(vec![]);
+    let mut frames = FrameStack(vec![Frame {
+        locals: vec![],
+        module: inst.clone(),
+        labels: LabelStack(vec![]),
+    }]);
+    let mut controls = vec![Control {
+        instrs: &expr.0,
+        pc: 0,
+        loop_: false,
+        else_: false,
+    }];
+
+    execute(inst, store, &mut controls, &mut stack, &mut frames)?;
+
+    let v = stack.pop().expect("empty stack");
+    Ok(v)
+}
+
+// Executes the sequences of a function body or constant expression until it returns or
+// calls a module function. Blocks are executed on an explicit control stack so that
+// deep nesting doesn't grow the native stack.
+fn execute(
+    inst: &ModuleInst,
+    store: &mut Store,
+    controls: &mut Vec<Control>,
+    stack: &mut ValueStack,
+    frames: &mut FrameStack,
+) -> Result<Exit, Trap> {
+    while let Some(control) = controls.last_mut() {
+        let instr = match control.instrs.get(control.pc) {
+            Some(instr) => instr,
+            None => {
+                // The end of the sequence leaves its results on the stack.
+                controls.pop();
+                frames.labels().pop();
+                continue;
+            }
+        };
+        control.pc += 1;
 
-    fn invoke(
-        &self,
-        instr: &Instr,
-        locals: &mut Vec<Val>,
-        stack: &mut ValueStack,
-    ) -> Result<(), Box<dyn Error>> {
         match instr {
-            Instr::I32Const(num) => stack.push(I32(*num)),
-            Instr::I64Const(num) => stack.push(I64(*num)),
-            Instr::F32Const(num) => stack.push(F32(*num)),
-            Instr::F64Const(num) => stack.push(F64(*num)),
-            Instr::I32Add => {
-                let mut a2 = i32_pop(stack)?;
-                let mut a1 = i32_pop(stack)?;
-                stack.push(I32(a1 + a2));
+            Instr::Unreachable => return Err(Trap::Unreachable),
+            Instr::Nop => {}
+            Instr::Block(bt, body) => {
+                let (params, results) = block_arity(inst, bt);
+                frames.labels().push(Label {
+                    n: results,
+                    height: stack.len() - params,
+                });
+                controls.push(Control {
+                    instrs: body,
+                    pc: 0,
+                    loop_: false,
+                    else_: false,
+                });
+            }
+            Instr::Loop(bt, body) => {
+                let (params, _) = block_arity(inst, bt);
+                frames.labels().push(Label {
+                    n: params,
+                    height: stack.len() - params,
+                });
+                controls.push(Control {
+                    instrs: body,
+                    pc: 0,
+                    loop_: true,
+                    else_: false,
+                });
+            }
+            Instr::If(bt, then, else_) => {
+                let c = i32_pop(stack);
+                let (params, results) = block_arity(inst, bt);
+                frames.labels().push(Label {
+                    n: results,
+                    height: stack.len() - params,
+                });
+                controls.push(Control {
+                    instrs: if c != 0 { then } else { else_ },
+                    pc: 0,
+                    loop_: false,
+                    else_: c == 0,
+                });
             }
-            Instr::I32Sub => {
-                let mut a2 = i32_pop(stack)?;
-                let mut a1 = i32_pop(stack)?;
-                stack.push(I32(a1 - a2));
+            Instr::Br(l) => br(l, stack, frames, controls),
+            Instr::BrIf(l) => {
+                let c = i32_pop(stack);
+                if c != 0 {
+                    br(l, stack, frames, controls);
+                }
             }
-            Instr::I32Mul => {
-                let mut a2 = i32_pop(stack)?;
-                let mut a1 = i32_pop(stack)?;
-                stack.push(I32(a1 * a2));
+            Instr::BrTable(ls, default) => {
+                let i = i32_pop(stack) as u32 as usize;
+                br(ls.get(i).unwrap_or(default), stack, frames, controls);
             }
-            Instr::LocalGet(idx) => {
-                let idx = (*idx).0 as usize;
-                let v = locals.get(idx).ok_or("invalid locals")?;
-                stack.push(*v)
+            Instr::Return => {
+                let label = &frames.labels().0[0];
+                stack.unwind(label.height, label.n);
+                return Ok(Exit::Return);
             }
-            Instr::LocalSet(idx) => {
-                let idx = (*idx).0 as usize;
-                let v = stack.pop().ok_or("empty stack")?;
-                locals[idx] = v;
+            Instr::Call(x) => {
+                if frames.0.len() >= MAX_CALL_DEPTH {
+                    return Err(Trap::CallStackExhausted);
+                }
+                let funcinst = store.funcs[inst.funcaddrs[x.0 as usize]].clone();
+                match funcinst {
+                    FuncInst::Host { type_, hostcode } => {
+                        call_host(&type_, &hostcode, Some(inst), store, stack)?
+                    }
+                    funcinst => return Ok(Exit::Call(funcinst)),
+                }
             }
-            _ => {
-                return Err(Box::new(InvalidWasmError(
-                    "not implemented instruction".to_string(),
-                )));
+            Instr::CallIndirect(y, x) => {
+                if frames.0.len() >= MAX_CALL_DEPTH {
+                    return Err(Trap::CallStackExhausted);
+                }
+                let table = &store.tables[inst.tableaddrs[x.0 as usize]];
+                let i = i32_pop(stack) as u32 as usize;
+                let a = match table.elem.get(i).ok_or(Trap::UndefinedElement)? {
+                    Ref::Func(r) => r.addr,
+                    Ref::Null(_) => return Err(Trap::UninitializedElement(i as u32)),
+                    Ref::Extern(_) => return Err(Trap::IndirectCallTypeMismatch),
+                };
+                let funcinst = store.funcs[a].clone();
+                if *funcinst.type_() != inst.types[y.0 as usize] {
+                    return Err(Trap::IndirectCallTypeMismatch);
+                }
+                match funcinst {
+                    FuncInst::Host { type_, hostcode } => {
+                        call_host(&type_, &hostcode, Some(inst), store, stack)?
+                    }
+                    funcinst => return Ok(Exit::Call(funcinst)),
+                }
             }
+            Instr::Drop => {
+                stack.pop().expect("empty stack");
+            }
+            Instr::Select | Instr::SelectT(_) => {
+                let c = i32_pop(stack);
+                let v2 = stack.pop().expect("empty stack");
+                let v1 = stack.pop().expect("empty stack");
+                stack.push(if c != 0 { v1 } else { v2 });
+            }
+            _ => invoke(inst, store, instr, frames.locals(), stack)?,
+        }
+    }
+    Ok(Exit::Return)
+}
+
+fn invoke(
+    inst: &ModuleInst,
+    store: &mut Store,
+    instr: &Instr,
+    locals: &mut [Val],
+    stack: &mut ValueStack,
+) -> Result<(), Trap> {
+    match instr {
+        Instr::I32Const(num) => stack.push(I32(*num)),
+        Instr::I64Const(num) => stack.push(I64(*num)),
+        Instr::F32Const(num) => stack.push(F32(*num)),
+        Instr::F64Const(num) => stack.push(F64(*num)),
+
+        Instr::I32Eqz => i32_unop(stack, |a| (a == 0) as i32),
+        Instr::I32Eq => i32_relop(stack, |a, b| a == b),
+        Instr::I32Ne => i32_relop(stack, |a, b| a != b),
+        Instr::I32LtS => i32_relop(stack, |a, b| a < b),
+        Instr::I32LtU => i32_relop(stack, |a, b| (a as u32) < (b as u32)),
+        Instr::I32GtS => i32_relop(stack, |a, b| a > b),
+        Instr::I32GtU => i32_relop(stack, |a, b| (a as u32) > (b as u32)),
+        Instr::I32LeS => i32_relop(stack, |a, b| a <= b),
+        Instr::I32LeU => i32_relop(stack, |a, b| (a as u32) <= (b as u32)),
+        Instr::I32GeS => i32_relop(stack, |a, b| a >= b),
+        Instr::I32GeU => i32_relop(stack, |a, b| (a as u32) >= (b as u32)),
+
+        Instr::I32Clz => i32_unop(stack, |a| a.leading_zeros() as i32),
+        Instr::I32Ctz => i32_unop(stack, |a| a.trailing_zeros() as i32),
+        Instr::I32Popcnt => i32_unop(stack, |a| a.count_ones() as i32),
+        Instr::I32Add => i32_binop(stack, |a, b| Ok(a.wrapping_add(b)))?,
+        Instr::I32Sub => i32_binop(stack, |a, b| Ok(a.wrapping_sub(b)))?,
+        Instr::I32Mul => i32_binop(stack, |a, b| Ok(a.wrapping_mul(b)))?,
+        Instr::I32DivS => i32_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            -1 if a == i32::MIN => Err(Trap::IntegerOverflow),
+            _ => Ok(a / b),
+        })?,
+        Instr::I32DivU => i32_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(((a as u32) / (b as u32)) as i32),
+        })?,
+        Instr::I32RemS => i32_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(a.wrapping_rem(b)),
+        })?,
+        Instr::I32RemU => i32_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(((a as u32) % (b as u32)) as i32),
+        })?,
+        Instr::I32And => i32_binop(stack, |a, b| Ok(a & b))?,
+        Instr::I32Or => i32_binop(stack, |a, b| Ok(a | b))?,
+        Instr::I32Xor => i32_binop(stack, |a, b| Ok(a ^ b))?,
+        Instr::I32Shl => i32_binop(stack, |a, b| Ok(a.wrapping_shl(b as u32)))?,
+        Instr::I32ShrS => i32_binop(stack, |a, b| Ok(a.wrapping_shr(b as u32)))?,
+        Instr::I32ShrU => i32_binop(stack, |a, b| Ok((a as u32).wrapping_shr(b as u32) as i32))?,
+        Instr::I32Rotl => i32_binop(stack, |a, b| Ok(a.rotate_left(b as u32 % 32)))?,
+        Instr::I32Rotr => i32_binop(stack, |a, b| Ok(a.rotate_right(b as u32 % 32)))?,
+
+        Instr::I64Eqz => {
+            let a = i64_pop(stack);
+            stack.push(I32((a == 0) as i32));
+        }
+        Instr::I64Eq => i64_relop(stack, |a, b| a == b),
+        Instr::I64Ne => i64_relop(stack, |a, b| a != b),
+        Instr::I64LtS => i64_relop(stack, |a, b| a < b),
+        Instr::I64LtU => i64_relop(stack, |a, b| (a as u64) < (b as u64)),
+        Instr::I64GtS => i64_relop(stack, |a, b| a > b),
+        Instr::I64GtU => i64_relop(stack, |a, b| (a as u64) > (b as u64)),
+        Instr::I64LeS => i64_relop(stack, |a, b| a <= b),
+        Instr::I64LeU => i64_relop(stack, |a, b| (a as u64) <= (b as u64)),
+        Instr::I64GeS => i64_relop(stack, |a, b| a >= b),
+        Instr::I64GeU => i64_relop(stack, |a, b| (a as u64) >= (b as u64)),
+
+        Instr::F32Eq => f32_relop(stack, |a, b| a == b),
+        Instr::F32Ne => f32_relop(stack, |a, b| a != b),
+        Instr::F32Lt => f32_relop(stack, |a, b| a < b),
+        Instr::F32Gt => f32_relop(stack, |a, b| a > b),
+        Instr::F32Le => f32_relop(stack, |a, b| a <= b),
+        Instr::F32Ge => f32_relop(stack, |a, b| a >= b),
+
+        Instr::F64Eq => f64_relop(stack, |a, b| a == b),
+        Instr::F64Ne => f64_relop(stack, |a, b| a != b),
+        Instr::F64Lt => f64_relop(stack, |a, b| a < b),
+        Instr::F64Gt => f64_relop(stack, |a, b| a > b),
+        Instr::F64Le => f64_relop(stack, |a, b| a <= b),
+        Instr::F64Ge => f64_relop(stack, |a, b| a >= b),
+
+        Instr::I64Clz => i64_unop(stack, |a| a.leading_zeros() as i64),
+        Instr::I64Ctz => i64_unop(stack, |a| a.trailing_zeros() as i64),
+        Instr::I64Popcnt => i64_unop(stack, |a| a.count_ones() as i64),
+        Instr::I64Add => i64_binop(stack, |a, b| Ok(a.wrapping_add(b)))?,
+        Instr::I64Sub => i64_binop(stack, |a, b| Ok(a.wrapping_sub(b)))?,
+        Instr::I64Mul => i64_binop(stack, |a, b| Ok(a.wrapping_mul(b)))?,
+        Instr::I64DivS => i64_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            -1 if a == i64::MIN => Err(Trap::IntegerOverflow),
+            _ => Ok(a / b),
+        })?,
+        Instr::I64DivU => i64_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(((a as u64) / (b as u64)) as i64),
+        })?,
+        Instr::I64RemS => i64_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(a.wrapping_rem(b)),
+        })?,
+        Instr::I64RemU => i64_binop(stack, |a, b| match b {
+            0 => Err(Trap::IntegerDivideByZero),
+            _ => Ok(((a as u64) % (b as u64)) as i64),
+        })?,
+        Instr::I64And => i64_binop(stack, |a, b| Ok(a & b))?,
+        Instr::I64Or => i64_binop(stack, |a, b| Ok(a | b))?,
+        Instr::I64Xor => i64_binop(stack, |a, b| Ok(a ^ b))?,
+        Instr::I64Shl => i64_binop(stack, |a, b| Ok(a.wrapping_shl(b as u32)))?,
+        Instr::I64ShrS => i64_binop(stack, |a, b| Ok(a.wrapping_shr(b as u32)))?,
+        Instr::I64ShrU => i64_binop(stack, |a, b| Ok((a as u64).wrapping_shr(b as u32) as i64))?,
+        Instr::I64Rotl => i64_binop(stack, |a, b| Ok(a.rotate_left(b as u32 % 64)))?,
+        Instr::I64Rotr => i64_binop(stack, |a, b| Ok(a.rotate_right(b as u32 % 64)))?,
+
+        Instr::F32Abs => f32_unop(stack, |a| f32::from_bits(a.to_bits() & !F32_SIGN)),
+        Instr::F32Neg => f32_unop(stack, |a| f32::from_bits(a.to_bits() ^ F32_SIGN)),
+        Instr::F32Ceil => f32_unop(stack, |a| f32_quiet(a.ceil())),
+        Instr::F32Floor => f32_unop(stack, |a| f32_quiet(a.floor())),
+        Instr::F32Trunc => f32_unop(stack, |a| f32_quiet(a.trunc())),
+        Instr::F32Nearest => f32_unop(stack, |a| f32_quiet(a.round_ties_even())),
+        Instr::F32Sqrt => f32_unop(stack, f32::sqrt),
+        Instr::F32Add => f32_binop(stack, |a, b| a + b),
+        Instr::F32Sub => f32_binop(stack, |a, b| a - b),
+        Instr::F32Mul => f32_binop(stack, |a, b| a * b),
+        Instr::F32Div => f32_binop(stack, |a, b| a / b),
+        Instr::F32Min => f32_binop(stack, f32_min),
+        Instr::F32Max => f32_binop(stack, f32_max),
+        Instr::F32Copysign => f32_binop(stack, |a, b| {
+            f32::from_bits((a.to_bits() & !F32_SIGN) | (b.to_bits() & F32_SIGN))
+        }),
+
+        Instr::F64Abs => f64_unop(stack, |a| f64::from_bits(a.to_bits() & !F64_SIGN)),
+        Instr::F64Neg => f64_unop(stack, |a| f64::from_bits(a.to_bits() ^ F64_SIGN)),
+        Instr::F64Ceil => f64_unop(stack, |a| f64_quiet(a.ceil())),
+        Instr::F64Floor => f64_unop(stack, |a| f64_quiet(a.floor())),
+        Instr::F64Trunc => f64_unop(stack, |a| f64_quiet(a.trunc())),
+        Instr::F64Nearest => f64_unop(stack, |a| f64_quiet(a.round_ties_even())),
+        Instr::F64Sqrt => f64_unop(stack, f64::sqrt),
+        Instr::F64Add => f64_binop(stack, |a, b| a + b),
+        Instr::F64Sub => f64_binop(stack, |a, b| a - b),
+        Instr::F64Mul => f64_binop(stack, |a, b| a * b),
+        Instr::F64Div => f64_binop(stack, |a, b| a / b),
+        Instr::F64Min => f64_binop(stack, f64_min),
+        Instr::F64Max => f64_binop(stack, f64_max),
+        Instr::F64Copysign => f64_binop(stack, |a, b| {
+            f64::from_bits((a.to_bits() & !F64_SIGN) | (b.to_bits() & F64_SIGN))
+        }),
+        Instr::LocalGet(idx) => {
+            let idx = idx.0 as usize;
+            stack.push(locals[idx])
+        }
+        Instr::LocalSet(idx) => {
+            let idx = idx.0 as usize;
+            let v = stack.pop().expect("empty stack");
+            locals[idx] = v;
+        }
+        Instr::LocalTee(idx) => {
+            let idx = idx.0 as usize;
+            let v = *stack.0.last().expect("empty stack");
+            locals[idx] = v;
+        }
+        Instr::GlobalGet(idx) => {
+            stack.push(store.globals[inst.globaladdrs[idx.0 as usize]].value);
+        }
+        Instr::GlobalSet(idx) => {
+            let v = stack.pop().expect("empty stack");
+            store.globals[inst.globaladdrs[idx.0 as usize]].value = v;
+        }
+        Instr::I32Load(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I32(i32::from_le_bytes(bytes)));
+        }
+        Instr::I64Load(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(i64::from_le_bytes(bytes)));
+        }
+        Instr::F32Load(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(F32(f32::from_le_bytes(bytes)));
+        }
+        Instr::F64Load(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(F64(f64::from_le_bytes(bytes)));
+        }
+        Instr::I32Load8S(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I32(i8::from_le_bytes(bytes) as i32));
+        }
+        Instr::I32Load8U(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I32(u8::from_le_bytes(bytes) as i32));
+        }
+        Instr::I32Load16S(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I32(i16::from_le_bytes(bytes) as i32));
+        }
+        Instr::I32Load16U(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I32(u16::from_le_bytes(bytes) as i32));
+        }
+        Instr::I64Load8S(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(i8::from_le_bytes(bytes) as i64));
+        }
+        Instr::I64Load8U(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(u8::from_le_bytes(bytes) as i64));
+        }
+        Instr::I64Load16S(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(i16::from_le_bytes(bytes) as i64));
+        }
+        Instr::I64Load16U(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(u16::from_le_bytes(bytes) as i64));
+        }
+        Instr::I64Load32S(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(i32::from_le_bytes(bytes) as i64));
+        }
+        Instr::I64Load32U(m) => {
+            let bytes = mem(inst, store).read(i32_pop(stack), m.offset)?;
+            stack.push(I64(u32::from_le_bytes(bytes) as i64));
+        }
+        Instr::I32Store(m) => {
+            let c = i32_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &c.to_le_bytes())?;
+        }
+        Instr::I64Store(m) => {
+            let c = i64_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &c.to_le_bytes())?;
+        }
+        Instr::F32Store(m) => {
+            let c = f32_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &c.to_le_bytes())?;
+        }
+        Instr::F64Store(m) => {
+            let c = f64_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &c.to_le_bytes())?;
+        }
+        Instr::I32Store8(m) => {
+            let c = i32_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &(c as u8).to_le_bytes())?;
+        }
+        Instr::I32Store16(m) => {
+            let c = i32_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &(c as u16).to_le_bytes())?;
+        }
+        Instr::I64Store8(m) => {
+            let c = i64_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &(c as u8).to_le_bytes())?;
+        }
+        Instr::I64Store16(m) => {
+            let c = i64_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &(c as u16).to_le_bytes())?;
         }
-        Ok(())
+        Instr::I64Store32(m) => {
+            let c = i64_pop(stack);
+            mem(inst, store).write(i32_pop(stack), m.offset, &(c as u32).to_le_bytes())?;
+        }
+        Instr::MemorySize => {
+            let size = mem(inst, store).size();
+            stack.push(I32(size as i32));
+        }
+        Instr::MemoryGrow => {
+            let n = i32_pop(stack);
+            let size = mem(inst, store).grow(n as u32);
+            stack.push(I32(size.map_or(-1, |size| size as i32)));
+        }
+        Instr::MemoryFill => {
+            let n = i32_pop(stack);
+            let val = i32_pop(stack);
+            let d = i32_pop(stack);
+            mem(inst, store).fill(d, val as u8, n)?;
+        }
+        Instr::MemoryCopy => {
+            let n = i32_pop(stack);
+            let s = i32_pop(stack);
+            let d = i32_pop(stack);
+            mem(inst, store).copy(d, s, n)?;
+        }
+        Instr::MemoryInit(x) => {
+            let n = i32_pop(stack);
+            let s = i32_pop(stack);
+            let d = i32_pop(stack);
+            let data = &store.datas[inst.dataaddrs[x.0 as usize]].data;
+            let bytes = segment(data, s, n).ok_or(Trap::OutOfBoundsMemoryAccess)?;
+            let bytes = bytes.to_vec();
+            mem(inst, store).write(d, 0, &bytes)?;
+        }
+        Instr::DataDrop(x) => {
+            store.datas[inst.dataaddrs[x.0 as usize]].data.clear();
+        }
+
+        Instr::TableGet(x) => {
+            let i = i32_pop(stack);
+            let r = table(inst, store, x).get(i)?;
+            stack.push(Val::Ref(r));
+        }
+        Instr::TableSet(x) => {
+            let r = ref_pop(stack);
+            let i = i32_pop(stack);
+            table(inst, store, x).write(i, &[r])?;
+        }
+        Instr::TableSize(x) => {
+            let size = table(inst, store, x).size();
+            stack.push(I32(size as i32));
+        }
+        Instr::TableGrow(x) => {
+            let n = i32_pop(stack);
+            let r = ref_pop(stack);
+            let size = table(inst, store, x).grow(n as u32, r);
+            stack.push(I32(size.map_or(-1, |size| size as i32)));
+        }
+        Instr::TableFill(x) => {
+            let n = i32_pop(stack);
+            let r = ref_pop(stack);
+            let i = i32_pop(stack);
+            table(inst, store, x).fill(i, n, r)?;
+        }
+
+        Instr::TableCopy(x, y) => {
+            let n = i32_pop(stack);
+            let s = i32_pop(stack);
+            let d = i32_pop(stack);
+            let refs = table(inst, store, y).read(s, n)?;
+            table(inst, store, x).write(d, &refs)?;
+        }
+        Instr::TableInit(x, y) => {
+            let n = i32_pop(stack);
+            let s = i32_pop(stack);
+            let d = i32_pop(stack);
+            let elem = &store.elems[inst.elemaddrs[y.0 as usize]].elem;
+            let refs = segment(elem, s, n).ok_or(Trap::OutOfBoundsTableAccess)?;
+            let refs = refs.to_vec();
+            table(inst, store, x).write(d, &refs)?;
+        }
+        Instr::ElemDrop(x) => {
+            store.elems[inst.elemaddrs[x.0 as usize]].elem.clear();
+        }
+
+        Instr::I32WrapI64 => {
+            let a = i64_pop(stack);
+            stack.push(I32(a as i32));
+        }
+        Instr::I32TruncF32S => {
+            let a = trunc(f32_pop(stack) as f64, -2147483649.0, 2147483648.0)?;
+            stack.push(I32(a as i32));
+        }
+        Instr::I32TruncF32U => {
+            let a = trunc(f32_pop(stack) as f64, -1.0, 4294967296.0)?;
+            stack.push(I32(a as u32 as i32));
+        }
+        Instr::I32TruncF64S => {
+            let a = trunc(f64_pop(stack), -2147483649.0, 2147483648.0)?;
+            stack.push(I32(a as i32));
+        }
+        Instr::I32TruncF64U => {
+            let a = trunc(f64_pop(stack), -1.0, 4294967296.0)?;
+            stack.push(I32(a as u32 as i32));
+        }
+        Instr::I64ExtendI32S => {
+            let a = i32_pop(stack);
+            stack.push(I64(a as i64));
+        }
+        Instr::I64ExtendI32U => {
+            let a = i32_pop(stack);
+            stack.push(I64(a as u32 as i64));
+        }
+        Instr::I64TruncF32S => {
+            let a = trunc(f32_pop(stack) as f64, I64_LOWER, I64_UPPER)?;
+            stack.push(I64(a as i64));
+        }
+        Instr::I64TruncF32U => {
+            let a = trunc(f32_pop(stack) as f64, -1.0, U64_UPPER)?;
+            stack.push(I64(a as u64 as i64));
+        }
+        Instr::I64TruncF64S => {
+            let a = trunc(f64_pop(stack), I64_LOWER, I64_UPPER)?;
+            stack.push(I64(a as i64));
+        }
+        Instr::I64TruncF64U => {
+            let a = trunc(f64_pop(stack), -1.0, U64_UPPER)?;
+            stack.push(I64(a as u64 as i64));
+        }
+        Instr::F32ConvertI32S => {
+            let a = i32_pop(stack);
+            stack.push(F32(a as f32));
+        }
+        Instr::F32ConvertI32U => {
+            let a = i32_pop(stack);
+            stack.push(F32(a as u32 as f32));
+        }
+        Instr::F32ConvertI64S => {
+            let a = i64_pop(stack);
+            stack.push(F32(a as f32));
+        }
+        Instr::F32ConvertI64U => {
+            let a = i64_pop(stack);
+            stack.push(F32(a as u64 as f32));
+        }
+        Instr::F32DemoteF64 => {
+            let a = f64_pop(stack);
+            stack.push(F32(a as f32));
+        }
+        Instr::F64ConvertI32S => {
+            let a = i32_pop(stack);
+            stack.push(F64(a as f64));
+        }
+        Instr::F64ConvertI32U => {
+            let a = i32_pop(stack);
+            stack.push(F64(a as u32 as f64));
+        }
+        Instr::F64ConvertI64S => {
+            let a = i64_pop(stack);
+            stack.push(F64(a as f64));
+        }
+        Instr::F64ConvertI64U => {
+            let a = i64_pop(stack);
+            stack.push(F64(a as u64 as f64));
+        }
+        Instr::F64PromoteF32 => {
+            let a = f32_pop(stack);
+            stack.push(F64(a as f64));
+        }
+        Instr::I32ReinterpretF32 => {
+            let a = f32_pop(stack);
+            stack.push(I32(a.to_bits() as i32));
+        }
+        Instr::I64ReinterpretF64 => {
+            let a = f64_pop(stack);
+            stack.push(I64(a.to_bits() as i64));
+        }
+        Instr::F32ReinterpretI32 => {
+            let a = i32_pop(stack);
+            stack.push(F32(f32::from_bits(a as u32)));
+        }
+        Instr::F64ReinterpretI64 => {
+            let a = i64_pop(stack);
+            stack.push(F64(f64::from_bits(a as u64)));
+        }
+        Instr::I32Extend8S => i32_unop(stack, |a| a as i8 as i32),
+        Instr::I32Extend16S => i32_unop(stack, |a| a as i16 as i32),
+        Instr::I64Extend8S => i64_unop(stack, |a| a as i8 as i64),
+        Instr::I64Extend16S => i64_unop(stack, |a| a as i16 as i64),
+        Instr::I64Extend32S => i64_unop(stack, |a| a as i32 as i64),
+
+        // Rust's float to integer casts saturate, and map NaN to 0.
+        Instr::I32TruncSatF32S => {
+            let a = f32_pop(stack);
+            stack.push(I32(a as i32));
+        }
+        Instr::I32TruncSatF32U => {
+            let a = f32_pop(stack);
+            stack.push(I32(a as u32 as i32));
+        }
+        Instr::I32TruncSatF64S => {
+            let a = f64_pop(stack);
+            stack.push(I32(a as i32));
+        }
+        Instr::I32TruncSatF64U => {
+            let a = f64_pop(stack);
+            stack.push(I32(a as u32 as i32));
+        }
+        Instr::I64TruncSatF32S => {
+            let a = f32_pop(stack);
+            stack.push(I64(a as i64));
+        }
+        Instr::I64TruncSatF32U => {
+            let a = f32_pop(stack);
+            stack.push(I64(a as u64 as i64));
+        }
+        Instr::I64TruncSatF64S => {
+            let a = f64_pop(stack);
+            stack.push(I64(a as i64));
+        }
+        Instr::I64TruncSatF64U => {
+            let a = f64_pop(stack);
+            stack.push(I64(a as u64 as i64));
+        }
+
+        Instr::RefNull(t) => stack.push(Val::Ref(Ref::Null(*t))),
+        Instr::RefIsNull => {
+            let r = ref_pop(stack);
+            stack.push(I32(matches!(r, Ref::Null(_)) as i32));
+        }
+        Instr::RefFunc(x) => {
+            let addr = inst.funcaddrs[x.0 as usize];
+            stack.push(Val::Ref(Ref::Func(FuncRef {
+                store: store.id,
+                addr,
+            })))
+        }
+        _ => unreachable!("{:?} is executed by execute", instr),
     }
+    Ok(())
 }
 
-fn i32_pop(stack: &mut ValueStack) -> Result<i32, Box<dyn Error>> {
-    let num = if let I32(num) = stack.pop().ok_or("no param")? {
-        num
+// Branches to the label with the given relative index: the sequences inside it are
+// left, and its own sequence is either left or, for a loop, restarted.
+fn br(l: &LabelIdx, stack: &mut ValueStack, frames: &mut FrameStack, controls: &mut Vec<Control>) {
+    let l = l.0 as usize;
+    let label = frames.labels().get(l).expect("unknown label");
+    stack.unwind(label.height, label.n);
+
+    for _ in 0..l {
+        controls.pop();
+        frames.labels().pop();
+    }
+    let control = controls.last_mut().expect("unknown label");
+    if control.loop_ {
+        control.pc = 0;
     } else {
-        return Err(Box::new(InvalidWasmError("invalid param type".to_string())));
+        controls.pop();
+        frames.labels().pop();
+    }
+}
+
+// Returns the number of parameters and results of a block type.
+fn block_arity(inst: &ModuleInst, bt: &BlockType) -> (usize, usize) {
+    match bt {
+        BlockType::Empty => (0, 0),
+        BlockType::ValType(_) => (0, 1),
+        BlockType::TypeIdx(idx) => {
+            let functype = &inst.types[idx.0 as usize];
+            (functype.0 .0.len(), functype.1 .0.len())
+        }
+    }
+}
+
+fn ref_pop(stack: &mut ValueStack) -> Ref {
+    match stack.pop() {
+        Some(Val::Ref(r)) => r,
+        v => unreachable!("expected a reference operand, found {:?}", v),
+    }
+}
+
+fn i32_pop(stack: &mut ValueStack) -> i32 {
+    match stack.pop() {
+        Some(I32(num)) => num,
+        v => unreachable!("expected an i32 operand, found {:?}", v),
+    }
+}
+
+fn i32_unop(stack: &mut ValueStack, f: impl FnOnce(i32) -> i32) {
+    let a = i32_pop(stack);
+    stack.push(I32(f(a)));
+}
+
+fn i32_binop(
+    stack: &mut ValueStack,
+    f: impl FnOnce(i32, i32) -> Result<i32, Trap>,
+) -> Result<(), Trap> {
+    let b = i32_pop(stack);
+    let a = i32_pop(stack);
+    stack.push(I32(f(a, b)?));
+    Ok(())
+}
+
+fn i32_relop(stack: &mut ValueStack, f: impl FnOnce(i32, i32) -> bool) {
+    let b = i32_pop(stack);
+    let a = i32_pop(stack);
+    stack.push(I32(f(a, b) as i32));
+}
+
+fn i64_pop(stack: &mut ValueStack) -> i64 {
+    match stack.pop() {
+        Some(I64(num)) => num,
+        v => unreachable!("expected an i64 operand, found {:?}", v),
+    }
+}
+
+fn i64_unop(stack: &mut ValueStack, f: impl FnOnce(i64) -> i64) {
+    let a = i64_pop(stack);
+    stack.push(I64(f(a)));
+}
+
+fn i64_binop(
+    stack: &mut ValueStack,
+    f: impl FnOnce(i64, i64) -> Result<i64, Trap>,
+) -> Result<(), Trap> {
+    let b = i64_pop(stack);
+    let a = i64_pop(stack);
+    stack.push(I64(f(a, b)?));
+    Ok(())
+}
+
+fn i64_relop(stack: &mut ValueStack, f: impl FnOnce(i64, i64) -> bool) {
+    let b = i64_pop(stack);
+    let a = i64_pop(stack);
+    stack.push(I32(f(a, b) as i32));
+}
+
+fn f32_pop(stack: &mut ValueStack) -> f32 {
+    match stack.pop() {
+        Some(F32(num)) => num,
+        v => unreachable!("expected an f32 operand, found {:?}", v),
+    }
+}
+
+fn f64_pop(stack: &mut ValueStack) -> f64 {
+    match stack.pop() {
+        Some(F64(num)) => num,
+        v => unreachable!("expected an f64 operand, found {:?}", v),
+    }
+}
+
+fn f32_unop(stack: &mut ValueStack, f: impl FnOnce(f32) -> f32) {
+    let a = f32_pop(stack);
+    stack.push(F32(f(a)));
+}
+
+fn f32_binop(stack: &mut ValueStack, f: impl FnOnce(f32, f32) -> f32) {
+    let b = f32_pop(stack);
+    let a = f32_pop(stack);
+    stack.push(F32(f(a, b)));
+}
+
+fn f32_relop(stack: &mut ValueStack, f: impl FnOnce(f32, f32) -> bool) {
+    let b = f32_pop(stack);
+    let a = f32_pop(stack);
+    stack.push(I32(f(a, b) as i32));
+}
+
+// Rounding a NaN has to return a quiet NaN: https://webassembly.github.io/spec/core/exec/numerics.html#op-fceil
+fn f32_quiet(a: f32) -> f32 {
+    if a.is_nan() {
+        f32::from_bits(a.to_bits() | F32_QUIET)
+    } else {
+        a
+    }
+}
+
+// Unlike f32::min, a NaN operand makes the result NaN, and -0 is less than +0.
+fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+// Unlike f32::max, a NaN operand makes the result NaN, and +0 is greater than -0.
+fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f32::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+fn f64_unop(stack: &mut ValueStack, f: impl FnOnce(f64) -> f64) {
+    let a = f64_pop(stack);
+    stack.push(F64(f(a)));
+}
+
+fn f64_binop(stack: &mut ValueStack, f: impl FnOnce(f64, f64) -> f64) {
+    let b = f64_pop(stack);
+    let a = f64_pop(stack);
+    stack.push(F64(f(a, b)));
+}
+
+fn f64_relop(stack: &mut ValueStack, f: impl FnOnce(f64, f64) -> bool) {
+    let b = f64_pop(stack);
+    let a = f64_pop(stack);
+    stack.push(I32(f(a, b) as i32));
+}
+
+fn f64_quiet(a: f64) -> f64 {
+    if a.is_nan() {
+        f64::from_bits(a.to_bits() | F64_QUIET)
+    } else {
+        a
+    }
+}
+
+// Unlike f64::min, a NaN operand makes the result NaN, and -0 is less than +0.
+fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+// Unlike f64::max, a NaN operand makes the result NaN, and +0 is greater than -0.
+fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+// Truncates a float that has to lie strictly between `lower` and `upper` to fit in
+// the target integer type.
+fn trunc(a: f64, lower: f64, upper: f64) -> Result<f64, Trap> {
+    if a.is_nan() {
+        return Err(Trap::InvalidConversionToInteger);
+    }
+    if a <= lower || a >= upper {
+        return Err(Trap::IntegerOverflow);
+    }
+    Ok(a.trunc())
+}
+
+// Returns the `n` items of a passive segment starting at `s`, or None if they are out
+// of its bounds.
+fn segment<T>(seg: &[T], s: i32, n: i32) -> Option<&[T]> {
+    let s = s as u32 as usize;
+    let end = s.checked_add(n as u32 as usize)?;
+    seg.get(s..end)
+}
+
+fn table<'a>(inst: &ModuleInst, store: &'a mut Store, x: &TableIdx) -> &'a mut TableInst {
+    &mut store.tables[inst.tableaddrs[x.0 as usize]]
+}
+
+// Returns the memory instructions operate on, which is always the first one.
+fn mem<'a>(inst: &ModuleInst, store: &'a mut Store) -> &'a mut MemInst {
+    &mut store.mems[inst.memaddrs[0]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Error;
+    use crate::execution::modules::invoke;
+    use crate::execution::runtime::Store;
+    use crate::execution::runtime::PAGE_SIZE;
+    use crate::structure::instructions::Instr::*;
+    use crate::structure::instructions::{Expr, MemArg};
+    use crate::structure::modules::{
+        Data, DataIdx, DataMode, Elem, ElemIdx, ElemMode, Export, ExportDesc, Func, FuncIdx,
+        Global, GlobalIdx, LocalIdx, Mem, MemIdx, Module, Table, TableIdx, TypeIdx,
     };
+    use crate::structure::types::{
+        FuncType, GlobalType, Limits, MemType, Mut, RefType, ResultType, TableType,
+    };
+    use crate::structure::values::{Byte, Name};
+
+    const I32_TYPE: ValType = ValType::NumType(NumType::I32);
+    const I64_TYPE: ValType = ValType::NumType(NumType::I64);
+    const F32_TYPE: ValType = ValType::NumType(NumType::F32);
+    const F64_TYPE: ValType = ValType::NumType(NumType::F64);
+
+    // A function taking the given parameters and returning an i32.
+    struct TestFunc(Vec<ValType>, Vec<ValType>, Vec<Instr>);
+
+    // Calls the first of the given functions, added to `module`, which may call each
+    // other by index.
+    fn run(mut module: Module, funcs: Vec<TestFunc>, args: Vec<Val>) -> Result<Val, Error> {
+        module.exports.push(Export {
+            name: Name("f".to_string()),
+            desc: ExportDesc::Func(FuncIdx(0)),
+        });
+        for (i, TestFunc(params, locals, body)) in funcs.into_iter().enumerate() {
+            let results = ResultType(vec![I32_TYPE]);
+            module.types.push(FuncType(ResultType(params), results));
+            module.funcs.push(Func {
+                type_: TypeIdx(i as u32),
+                locals,
+                body: Expr(body),
+                offsets: vec![],
+            });
+        }
+        module.validate()?;
+        let mut store = Store::default();
+        store.instantiate(&module, &[])?;
+        let results = invoke(&mut store, 0, &args)?;
+        Ok(results[0])
+    }
 
-    Ok(num)
+    // Evaluates a function body without parameters or locals, which returns one value.
+    fn eval(result: ValType, body: Vec<Instr>) -> Result<Val, Error> {
+        let module = Module {
+            types: vec![FuncType(ResultType(vec![]), ResultType(vec![result]))],
+            funcs: vec![Func {
+                type_: TypeIdx(0),
+                locals: vec![],
+                body: Expr(body),
+                offsets: vec![],
+            }],
+            exports: vec![Export {
+                name: Name("f".to_string()),
+                desc: ExportDesc::Func(FuncIdx(0)),
+            }],
+            ..Default::default()
+        };
+        module.validate()?;
+        let mut store = Store::default();
+        store.instantiate(&module, &[])?;
+        let results = invoke(&mut store, 0, &[])?;
+        Ok(results[0])
+    }
+
+    fn call_funcs(funcs: Vec<TestFunc>, args: Vec<Val>) -> Val {
+        run(Default::default(), funcs, args).unwrap()
+    }
+
+    fn call(params: Vec<ValType>, locals: Vec<ValType>, body: Vec<Instr>, args: Vec<Val>) -> Val {
+        call_funcs(vec![TestFunc(params, locals, body)], args)
+    }
+
+    #[test]
+    fn loop_br_if() {
+        // Sums n, n - 1, ..., 1 by branching back to the loop while n is not zero.
+        let (n, sum) = (LocalIdx(0), LocalIdx(1));
+        let body = vec![
+            Loop(
+                BlockType::Empty,
+                vec![
+                    LocalGet(sum.clone()),
+                    LocalGet(n.clone()),
+                    I32Add,
+                    LocalSet(sum.clone()),
+                    LocalGet(n.clone()),
+                    I32Const(1),
+                    I32Sub,
+                    LocalTee(n),
+                    BrIf(LabelIdx(0)),
+                ],
+            ),
+            LocalGet(sum),
+        ];
+        assert_eq!(
+            call(vec![I32_TYPE], vec![I32_TYPE], body, vec![I32(10)]),
+            I32(55)
+        );
+    }
+
+    #[test]
+    fn br_table() {
+        // Each label leads to a different result, and the default one returns the index.
+        let i32_block = |body| Block(BlockType::ValType(I32_TYPE), body);
+        let body = |i| {
+            vec![
+                i32_block(vec![
+                    i32_block(vec![
+                        I32Const(i),
+                        I32Const(i),
+                        BrTable(vec![LabelIdx(0), LabelIdx(1)], LabelIdx(2)),
+                    ]),
+                    I32Const(10),
+                    I32Add,
+                    Return,
+                ]),
+                I32Const(20),
+                I32Add,
+            ]
+        };
+        assert_eq!(call(vec![], vec![], body(0), vec![]), I32(10));
+        assert_eq!(call(vec![], vec![], body(1), vec![]), I32(21));
+        // Out of range indices select the default label, which is the function body.
+        assert_eq!(call(vec![], vec![], body(7), vec![]), I32(7));
+    }
+
+    #[test]
+    fn br_keeps_block_results() {
+        // The branch discards the values below the result of the block.
+        let i32 = BlockType::ValType(I32_TYPE);
+        let body = vec![
+            Block(
+                i32.clone(),
+                vec![
+                    I32Const(1),
+                    I32Const(2),
+                    If(i32, vec![I32Const(3), Br(LabelIdx(1))], vec![I32Const(4)]),
+                    Drop,
+                ],
+            ),
+            I32Const(100),
+            I32Add,
+        ];
+        assert_eq!(call(vec![], vec![], body, vec![]), I32(103));
+    }
+
+    #[test]
+    fn return_from_nested_blocks() {
+        let body = vec![
+            I32Const(7),
+            Block(
+                BlockType::Empty,
+                vec![Loop(BlockType::Empty, vec![I32Const(42), Return])],
+            ),
+            Unreachable,
+        ];
+        assert_eq!(call(vec![], vec![], body, vec![]), I32(42));
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // Blocks nested as deeply as the decoder allows, left by a single branch.
+        let mut body = vec![I32Const(7), Br(LabelIdx(511))];
+        for _ in 0..512 {
+            body = vec![Block(BlockType::ValType(I32_TYPE), body)];
+        }
+        body.extend(vec![I32Const(1), I32Add]);
+        assert_eq!(call(vec![], vec![], body, vec![]), I32(8));
+    }
+
+    #[test]
+    fn calls() {
+        // The arguments are passed in order to a helper.
+        let f = TestFunc(
+            vec![],
+            vec![],
+            vec![I32Const(10), I32Const(3), Call(FuncIdx(1))],
+        );
+        let sub = TestFunc(
+            vec![I32_TYPE, I32_TYPE],
+            vec![],
+            vec![LocalGet(LocalIdx(0)), LocalGet(LocalIdx(1)), I32Sub],
+        );
+        assert_eq!(call_funcs(vec![f, sub], vec![]), I32(7));
+    }
+
+    #[test]
+    fn recursion() {
+        let n = LocalIdx(0);
+        let factorial = vec![
+            LocalGet(n.clone()),
+            If(
+                BlockType::ValType(I32_TYPE),
+                vec![
+                    LocalGet(n.clone()),
+                    LocalGet(n),
+                    I32Const(1),
+                    I32Sub,
+                    Call(FuncIdx(0)),
+                    I32Mul,
+                ],
+                vec![I32Const(1)],
+            ),
+        ];
+        assert_eq!(
+            call(vec![I32_TYPE], vec![], factorial, vec![I32(10)]),
+            I32(3628800)
+        );
+    }
+
+    #[test]
+    fn calls_in_blocks() {
+        // Sums n, n - 1, ..., 1 by recursion from a loop in the else branch of an if,
+        // which are resumed when the call returns.
+        let n = LocalIdx(0);
+        let loop_ = vec![
+            LocalGet(n.clone()),
+            LocalGet(n.clone()),
+            I32Const(1),
+            I32Sub,
+            Call(FuncIdx(0)),
+            I32Add,
+        ];
+        let sum = vec![
+            LocalGet(n),
+            I32Eqz,
+            If(
+                BlockType::ValType(I32_TYPE),
+                vec![I32Const(0)],
+                vec![Block(
+                    BlockType::ValType(I32_TYPE),
+                    vec![Loop(BlockType::ValType(I32_TYPE), loop_)],
+                )],
+            ),
+        ];
+        assert_eq!(call(vec![I32_TYPE], vec![], sum, vec![I32(100)]), I32(5050));
+    }
+
+    #[test]
+    fn call_stack_exhausted() {
+        // Calls don't use the native stack, so the depth limit is reached on the test
+        // thread, which has the default stack size.
+        let f = TestFunc(vec![], vec![], vec![Call(FuncIdx(0))]);
+        let err = run(Default::default(), vec![f], vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "trap: call stack exhausted");
+    }
+
+    // A module with one page of memory, holding the bytes 1, 2, 3, 4 and 0xff at 8.
+    fn memory() -> Module {
+        Module {
+            mems: vec![Mem {
+                type_: MemType(Limits {
+                    min: 1,
+                    max: Some(2),
+                }),
+            }],
+            datas: vec![Data {
+                init: [1, 2, 3, 4, 0xff].iter().map(|&b| Byte(b)).collect(),
+                mode: DataMode::Active {
+                    memory: MemIdx(0),
+                    offset: Expr(vec![I32Const(8)]),
+                },
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn memarg(offset: u32) -> MemArg {
+        MemArg { offset, align: 0 }
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        let load = |instrs: Vec<Instr>| {
+            let f = TestFunc(vec![], vec![], instrs);
+            run(memory(), vec![f], vec![]).unwrap()
+        };
+        assert_eq!(load(vec![I32Const(0), I32Load(memarg(8))]), I32(0x04030201));
+        assert_eq!(load(vec![I32Const(12), I32Load8S(memarg(0))]), I32(-1));
+        assert_eq!(load(vec![I32Const(12), I32Load8U(memarg(0))]), I32(0xff));
+        assert_eq!(load(vec![I32Const(10), I32Load16U(memarg(1))]), I32(0xff04));
+
+        // Stores are little endian and narrow stores keep the low bits.
+        let store = vec![
+            I32Const(0),
+            I32Const(0x1234_5678),
+            I32Store(memarg(0)),
+            I32Const(2),
+            I32Const(0x1ff),
+            I32Store8(memarg(0)),
+            I32Const(0),
+            I32Load(memarg(0)),
+        ];
+        assert_eq!(load(store), I32(0x12ff_5678));
+    }
+
+    #[test]
+    fn out_of_bounds_memory_access() {
+        let load = |addr, offset| {
+            let f = TestFunc(
+                vec![],
+                vec![],
+                vec![I32Const(addr), I32Load(memarg(offset))],
+            );
+            run(memory(), vec![f], vec![])
+        };
+        assert!(load(PAGE_SIZE as i32 - 4, 0).is_ok());
+        for (addr, offset) in [(PAGE_SIZE as i32 - 3, 0), (0, u32::MAX), (-1, 0)] {
+            let err = load(addr, offset).unwrap_err();
+            assert_eq!(err.to_string(), "trap: out of bounds memory access");
+        }
+    }
+
+    #[test]
+    fn memory_grow() {
+        let grow = |n| {
+            let body = vec![I32Const(n), MemoryGrow, Drop, MemorySize];
+            let f = TestFunc(vec![], vec![], body);
+            run(memory(), vec![f], vec![]).unwrap()
+        };
+        assert_eq!(grow(0), I32(1));
+        assert_eq!(grow(1), I32(2));
+        // Growing beyond the maximum fails without changing the size.
+        assert_eq!(grow(2), I32(1));
+
+        let body = vec![I32Const(2), MemoryGrow];
+        let f = TestFunc(vec![], vec![], body);
+        assert_eq!(run(memory(), vec![f], vec![]).unwrap(), I32(-1));
+
+        // Without a maximum, the implementation limit applies.
+        let mut module = memory();
+        module.mems[0].type_.0.max = None;
+        let body = vec![I32Const(0x4000), MemoryGrow];
+        let f = TestFunc(vec![], vec![], body);
+        assert_eq!(run(module, vec![f], vec![]).unwrap(), I32(-1));
+    }
+
+    #[test]
+    fn globals() {
+        let global = |mut_, value| Global {
+            type_: GlobalType(mut_, I32_TYPE),
+            init: Expr(vec![I32Const(value)]),
+        };
+        let module = || Module {
+            globals: vec![global(Mut::Const, 2), global(Mut::Var, 40)],
+            ..Default::default()
+        };
+
+        let body = vec![
+            GlobalGet(GlobalIdx(1)),
+            GlobalGet(GlobalIdx(0)),
+            I32Add,
+            GlobalSet(GlobalIdx(1)),
+            GlobalGet(GlobalIdx(1)),
+        ];
+        let f = TestFunc(vec![], vec![], body);
+        assert_eq!(run(module(), vec![f], vec![]).unwrap(), I32(42));
+
+        let body = vec![I32Const(0), GlobalSet(GlobalIdx(0)), I32Const(0)];
+        let f = TestFunc(vec![], vec![], body);
+        let err = run(module(), vec![f], vec![]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid module: global is immutable (func 0)"
+        );
+    }
+
+    fn i32_binop(instr: Instr, a: i32, b: i32) -> Result<Val, Error> {
+        eval(I32_TYPE, vec![I32Const(a), I32Const(b), instr])
+    }
+
+    #[test]
+    fn i32_arithmetic() {
+        let binop = |instr, a, b| i32_binop(instr, a, b).unwrap();
+        assert_eq!(binop(I32Add, i32::MAX, 1), I32(i32::MIN));
+        assert_eq!(binop(I32Sub, i32::MIN, 1), I32(i32::MAX));
+        assert_eq!(binop(I32Mul, 0x1000_0001, 16), I32(16));
+        assert_eq!(binop(I32DivS, -7, 2), I32(-3));
+        assert_eq!(binop(I32DivU, -7, 2), I32(0x7fff_fffc));
+        assert_eq!(binop(I32RemS, -7, 2), I32(-1));
+        assert_eq!(binop(I32RemS, i32::MIN, -1), I32(0));
+        assert_eq!(binop(I32RemU, -7, 2), I32(1));
+        // Shift and rotate counts are taken modulo 32.
+        assert_eq!(binop(I32Shl, 1, 33), I32(2));
+        assert_eq!(binop(I32ShrS, -8, 1), I32(-4));
+        assert_eq!(binop(I32ShrU, -8, 1), I32(0x7fff_fffc));
+        assert_eq!(binop(I32Rotl, 0x8000_0001u32 as i32, 1), I32(3));
+        assert_eq!(binop(I32Rotr, 3, 33), I32(0x8000_0001u32 as i32));
+        // Comparisons interpret the operands as signed or unsigned.
+        assert_eq!(binop(I32LtS, -1, 0), I32(1));
+        assert_eq!(binop(I32LtU, -1, 0), I32(0));
+        assert_eq!(binop(I32GeU, -1, 0), I32(1));
+
+        let unop = |instr, a| eval(I32_TYPE, vec![I32Const(a), instr]).unwrap();
+        assert_eq!(unop(I32Eqz, 0), I32(1));
+        assert_eq!(unop(I32Clz, 0), I32(32));
+        assert_eq!(unop(I32Ctz, 0x100), I32(8));
+        assert_eq!(unop(I32Popcnt, -1), I32(32));
+    }
+
+    #[test]
+    fn i32_traps() {
+        for instr in [I32DivS, I32DivU, I32RemS, I32RemU] {
+            let err = i32_binop(instr, 1, 0).unwrap_err();
+            assert_eq!(err.to_string(), "trap: integer divide by zero");
+        }
+        let err = i32_binop(I32DivS, i32::MIN, -1).unwrap_err();
+        assert_eq!(err.to_string(), "trap: integer overflow");
+    }
+
+    fn i64_binop(instr: Instr, a: i64, b: i64) -> Result<Val, Error> {
+        let result = match instr {
+            I64Eq | I64Ne | I64LtS | I64LtU | I64GtS | I64GtU | I64LeS | I64LeU | I64GeS
+            | I64GeU => I32_TYPE,
+            _ => I64_TYPE,
+        };
+        eval(result, vec![I64Const(a), I64Const(b), instr])
+    }
+
+    #[test]
+    fn i64_arithmetic() {
+        let binop = |instr, a, b| i64_binop(instr, a, b).unwrap();
+        assert_eq!(binop(I64Add, i64::MAX, 1), I64(i64::MIN));
+        assert_eq!(binop(I64Mul, 1 << 32, 1 << 32), I64(0));
+        assert_eq!(binop(I64DivU, -2, 2), I64(i64::MAX));
+        assert_eq!(binop(I64RemS, i64::MIN, -1), I64(0));
+        assert_eq!(binop(I64Shl, 1, 65), I64(2));
+        assert_eq!(binop(I64ShrU, -1, 60), I64(15));
+        assert_eq!(binop(I64Rotr, 1, 1), I64(i64::MIN));
+        assert_eq!(binop(I64GtU, -1, 1), I32(1));
+        assert_eq!(binop(I64GtS, -1, 1), I32(0));
+
+        let unop = |instr, a, result| eval(result, vec![I64Const(a), instr]).unwrap();
+        assert_eq!(unop(I64Eqz, 0, I32_TYPE), I32(1));
+        assert_eq!(unop(I64Clz, 1, I64_TYPE), I64(63));
+        assert_eq!(unop(I64Popcnt, -1, I64_TYPE), I64(64));
+    }
+
+    #[test]
+    fn i64_traps() {
+        let err = i64_binop(I64RemU, 1, 0).unwrap_err();
+        assert_eq!(err.to_string(), "trap: integer divide by zero");
+        let err = i64_binop(I64DivS, i64::MIN, -1).unwrap_err();
+        assert_eq!(err.to_string(), "trap: integer overflow");
+    }
+
+    fn f32_bits(v: Val) -> u32 {
+        match v {
+            F32(f) => f.to_bits(),
+            v => panic!("not an f32: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn float_min_max() {
+        assert_eq!(f32_min(0.0, -0.0).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(f32_max(-0.0, 0.0).to_bits(), 0.0f32.to_bits());
+        assert!(f32_min(f32::NAN, 1.0).is_nan());
+        assert!(f32_max(1.0, f32::NAN).is_nan());
+        assert_eq!(f64_min(-0.0, 0.0).to_bits(), (-0.0f64).to_bits());
+        assert_eq!(f64_max(0.0, -0.0).to_bits(), 0.0f64.to_bits());
+        assert!(f64_min(1.0, f64::NAN).is_nan());
+        assert_eq!(f64_max(-1.0, 2.0), 2.0);
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        let f32_unop = |instr, a| eval(F32_TYPE, vec![F32Const(a), instr]).unwrap();
+        assert_eq!(f32_unop(F32Nearest, 2.5), F32(2.0));
+        assert_eq!(f32_unop(F32Nearest, -3.5), F32(-4.0));
+        assert_eq!(f32_unop(F32Ceil, -0.5), F32(-0.0));
+        assert_eq!(f32_unop(F32Sqrt, 4.0), F32(2.0));
+
+        let f64_binop = |instr, a, b| eval(F64_TYPE, vec![F64Const(a), F64Const(b), instr]);
+        assert_eq!(f64_binop(F64Div, 1.0, 0.0).unwrap(), F64(f64::INFINITY));
+        assert_eq!(f64_binop(F64Copysign, 2.0, -0.0).unwrap(), F64(-2.0));
+
+        // NaN compares unequal to everything, itself included.
+        let ne = vec![F64Const(f64::NAN), F64Const(f64::NAN), F64Ne];
+        assert_eq!(eval(I32_TYPE, ne).unwrap(), I32(1));
+        let lt = vec![F32Const(f32::NAN), F32Const(0.0), F32Lt];
+        assert_eq!(eval(I32_TYPE, lt).unwrap(), I32(0));
+    }
+
+    #[test]
+    fn nan_payloads() {
+        // Constants, abs, neg and copysign only touch the sign bit of NaNs.
+        let nan = f32::from_bits(0x7fa0_0001);
+        let unop = |instrs: Vec<Instr>| f32_bits(eval(F32_TYPE, instrs).unwrap());
+        assert_eq!(unop(vec![F32Const(nan)]), 0x7fa0_0001);
+        assert_eq!(unop(vec![F32Const(nan), F32Neg]), 0xffa0_0001);
+        assert_eq!(unop(vec![F32Const(-nan), F32Abs]), 0x7fa0_0001);
+        let copysign = vec![F32Const(nan), F32Const(-1.0), F32Copysign];
+        assert_eq!(unop(copysign), 0xffa0_0001);
+
+        // Rounding a signaling NaN quiets it and keeps the rest of its payload.
+        for instr in [F32Ceil, F32Floor, F32Trunc, F32Nearest] {
+            assert_eq!(unop(vec![F32Const(nan), instr]), 0x7fe0_0001);
+        }
+        let f64_nan = f64::from_bits(0xfff0_0000_0000_0001);
+        match eval(F64_TYPE, vec![F64Const(f64_nan), F64Nearest]).unwrap() {
+            F64(result) => assert_eq!(result.to_bits(), 0xfff8_0000_0000_0001),
+            v => panic!("unexpected result {:?}", v),
+        }
+    }
+
+    #[test]
+    fn truncation() {
+        let i32_trunc = |instr, a| eval(I32_TYPE, vec![F64Const(a), instr]);
+        assert_eq!(
+            i32_trunc(I32TruncF64S, -2147483648.9).unwrap(),
+            I32(i32::MIN)
+        );
+        assert_eq!(i32_trunc(I32TruncF64U, -0.9).unwrap(), I32(0));
+        assert_eq!(i32_trunc(I32TruncF64U, 4294967295.9).unwrap(), I32(-1));
+
+        let i64_trunc = |instr, a| eval(I64_TYPE, vec![F32Const(a), instr]);
+        assert_eq!(
+            i64_trunc(I64TruncF32S, -9.223372e18).unwrap(),
+            I64(i64::MIN)
+        );
+        assert_eq!(i64_trunc(I64TruncF32U, 1.5).unwrap(), I64(1));
+
+        for (instr, a) in [(I32TruncF64S, 2147483648.0), (I32TruncF64U, -1.0)] {
+            let err = i32_trunc(instr, a).unwrap_err();
+            assert_eq!(err.to_string(), "trap: integer overflow");
+        }
+        let err = i64_trunc(I64TruncF32S, 9.223372e18).unwrap_err();
+        assert_eq!(err.to_string(), "trap: integer overflow");
+        let err = i64_trunc(I64TruncF32U, f32::NAN).unwrap_err();
+        assert_eq!(err.to_string(), "trap: invalid conversion to integer");
+    }
+
+    #[test]
+    fn saturating_truncation() {
+        let trunc = |instr, result, a| eval(result, vec![F64Const(a), instr]).unwrap();
+        assert_eq!(trunc(I32TruncSatF64S, I32_TYPE, f64::NAN), I32(0));
+        assert_eq!(trunc(I32TruncSatF64S, I32_TYPE, -1e10), I32(i32::MIN));
+        assert_eq!(trunc(I32TruncSatF64U, I32_TYPE, 1e10), I32(-1));
+        assert_eq!(trunc(I64TruncSatF64U, I64_TYPE, -1.0), I64(0));
+        assert_eq!(
+            trunc(I64TruncSatF64S, I64_TYPE, f64::INFINITY),
+            I64(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        let convert = |instrs, result| eval(result, instrs).unwrap();
+        assert_eq!(
+            convert(vec![I64Const(0x1_0000_0002), I32WrapI64], I32_TYPE),
+            I32(2)
+        );
+        assert_eq!(
+            convert(vec![I32Const(-1), I64ExtendI32U], I64_TYPE),
+            I64(0xffff_ffff)
+        );
+        assert_eq!(
+            convert(vec![I32Const(-1), F64ConvertI32U], F64_TYPE),
+            F64(4294967295.0)
+        );
+        assert_eq!(
+            convert(vec![I64Const(-1), F32ConvertI64U], F32_TYPE),
+            F32(18446744073709551616.0)
+        );
+        assert_eq!(
+            convert(vec![F32Const(-0.0), I32ReinterpretF32], I32_TYPE),
+            I32(i32::MIN)
+        );
+        assert_eq!(
+            convert(vec![I32Const(0x80), I32Extend8S], I32_TYPE),
+            I32(-128)
+        );
+        assert_eq!(
+            convert(vec![I64Const(0x8000_0000), I64Extend32S], I64_TYPE),
+            I64(-0x8000_0000)
+        );
+    }
+
+    #[test]
+    fn call_indirect() {
+        // A table of func 1, func 2 and a null element.
+        let module = || Module {
+            tables: vec![Table {
+                type_: TableType(Limits { min: 3, max: None }, RefType::FuncRef),
+            }],
+            elems: vec![Elem {
+                type_: RefType::FuncRef,
+                init: vec![
+                    Expr(vec![RefFunc(FuncIdx(1))]),
+                    Expr(vec![RefFunc(FuncIdx(2))]),
+                    Expr(vec![RefNull(RefType::FuncRef)]),
+                ],
+                mode: ElemMode::Active {
+                    table: TableIdx(0),
+                    offset: Expr(vec![I32Const(0)]),
+                },
+            }],
+            ..Default::default()
+        };
+        let call = |i| {
+            // Calls the element i with the type [i32] -> [i32] of func 1.
+            let f = TestFunc(
+                vec![I32_TYPE],
+                vec![],
+                vec![
+                    I32Const(5),
+                    LocalGet(LocalIdx(0)),
+                    CallIndirect(TypeIdx(1), TableIdx(0)),
+                ],
+            );
+            let inc = TestFunc(
+                vec![I32_TYPE],
+                vec![],
+                vec![LocalGet(LocalIdx(0)), I32Const(1), I32Add],
+            );
+            let answer = TestFunc(vec![], vec![], vec![I32Const(42)]);
+            run(module(), vec![f, inc, answer], vec![I32(i)])
+        };
+        assert_eq!(call(0).unwrap(), I32(6));
+
+        let err = call(1).unwrap_err();
+        assert_eq!(err.to_string(), "trap: indirect call type mismatch");
+        let err = call(2).unwrap_err();
+        assert_eq!(err.to_string(), "trap: uninitialized element 2");
+        let err = call(3).unwrap_err();
+        assert_eq!(err.to_string(), "trap: undefined element");
+    }
+
+    #[test]
+    fn references() {
+        let null = vec![RefNull(RefType::ExternRef), RefIsNull];
+        assert_eq!(eval(I32_TYPE, null).unwrap(), I32(1));
+        let func = vec![RefFunc(FuncIdx(0)), RefIsNull];
+        assert_eq!(eval(I32_TYPE, func).unwrap(), I32(0));
+
+        let select = vec![
+            RefFunc(FuncIdx(0)),
+            RefNull(RefType::FuncRef),
+            I32Const(0),
+            SelectT(vec![ValType::RefType(RefType::FuncRef)]),
+            RefIsNull,
+        ];
+        assert_eq!(eval(I32_TYPE, select).unwrap(), I32(1));
+    }
+
+    #[test]
+    fn table_instructions() {
+        let module = || Module {
+            tables: vec![Table {
+                type_: TableType(
+                    Limits {
+                        min: 1,
+                        max: Some(3),
+                    },
+                    RefType::FuncRef,
+                ),
+            }],
+            ..Default::default()
+        };
+        let run_body = |body| run(module(), vec![TestFunc(vec![], vec![], body)], vec![]);
+
+        let grow = |n| vec![RefFunc(FuncIdx(0)), I32Const(n), TableGrow(TableIdx(0))];
+        assert_eq!(run_body(grow(2)).unwrap(), I32(1));
+        assert_eq!(run_body(grow(3)).unwrap(), I32(-1));
+
+        let mut body = grow(2);
+        body.extend(vec![
+            Drop,
+            I32Const(1),
+            RefNull(RefType::FuncRef),
+            I32Const(2),
+            TableFill(TableIdx(0)),
+            I32Const(0),
+            I32Const(2),
+            TableGet(TableIdx(0)),
+            TableSet(TableIdx(0)),
+            I32Const(0),
+            TableGet(TableIdx(0)),
+            RefIsNull,
+            TableSize(TableIdx(0)),
+            I32Add,
+        ]);
+        assert_eq!(run_body(body).unwrap(), I32(4));
+
+        let body = vec![I32Const(1), TableGet(TableIdx(0)), RefIsNull];
+        let err = run_body(body).unwrap_err();
+        assert_eq!(err.to_string(), "trap: out of bounds table access");
+    }
+
+    #[test]
+    fn bulk_memory() {
+        let run_body = |mut body: Vec<Instr>| {
+            let mut module = memory();
+            module.datas.push(Data {
+                init: [7, 8, 9].iter().map(|&b| Byte(b)).collect(),
+                mode: DataMode::Passive,
+            });
+            body.extend(vec![I32Const(0), I32Load(memarg(8))]);
+            run(module, vec![TestFunc(vec![], vec![], body)], vec![])
+        };
+
+        let fill = vec![I32Const(9), I32Const(0xaa), I32Const(2), MemoryFill];
+        assert_eq!(run_body(fill).unwrap(), I32(0x04aaaa01));
+        // Overlapping regions are copied as if through a temporary buffer.
+        let copy = vec![I32Const(9), I32Const(8), I32Const(3), MemoryCopy];
+        assert_eq!(run_body(copy).unwrap(), I32(0x03020101));
+        let init = vec![
+            I32Const(8),
+            I32Const(1),
+            I32Const(2),
+            MemoryInit(DataIdx(1)),
+        ];
+        assert_eq!(run_body(init).unwrap(), I32(0x04030908));
+
+        // Active segments are dropped after instantiation, like a dropped passive one.
+        let init = |x| {
+            vec![
+                I32Const(0),
+                I32Const(0),
+                I32Const(1),
+                MemoryInit(DataIdx(x)),
+            ]
+        };
+        let err = run_body(init(0)).unwrap_err();
+        assert_eq!(err.to_string(), "trap: out of bounds memory access");
+        let mut drop = vec![DataDrop(DataIdx(1))];
+        drop.extend(init(1));
+        let err = run_body(drop).unwrap_err();
+        assert_eq!(err.to_string(), "trap: out of bounds memory access");
+
+        let copy = vec![
+            I32Const(0),
+            I32Const(PAGE_SIZE as i32),
+            I32Const(1),
+            MemoryCopy,
+        ];
+        let err = run_body(copy).unwrap_err();
+        assert_eq!(err.to_string(), "trap: out of bounds memory access");
+    }
+
+    #[test]
+    fn bulk_table() {
+        let run_body = |mut body: Vec<Instr>| {
+            let module = Module {
+                tables: vec![Table {
+                    type_: TableType(Limits { min: 3, max: None }, RefType::FuncRef),
+                }],
+                elems: vec![Elem {
+                    type_: RefType::FuncRef,
+                    init: vec![Expr(vec![RefFunc(FuncIdx(0))])],
+                    mode: ElemMode::Passive,
+                }],
+                ..Default::default()
+            };
+            body.extend(vec![I32Const(0), TableGet(TableIdx(0)), RefIsNull]);
+            run(module, vec![TestFunc(vec![], vec![], body)], vec![])
+        };
+
+        let init = vec![I32Const(2), I32Const(0), I32Const(1)];
+        let copy = vec![I32Const(0), I32Const(2), I32Const(1)];
+        let mut body = init.clone();
+        body.push(TableInit(TableIdx(0), ElemIdx(0)));
+        body.extend(copy);
+        body.push(TableCopy(TableIdx(0), TableIdx(0)));
+        assert_eq!(run_body(body).unwrap(), I32(0));
+
+        let mut body = vec![ElemDrop(ElemIdx(0))];
+        body.extend(init);
+        body.push(TableInit(TableIdx(0), ElemIdx(0)));
+        let err = run_body(body).unwrap_err();
+        assert_eq!(err.to_string(), "trap: out of bounds table access");
+    }
 }
@@ -1,63 +1,415 @@
-use crate::errors::RuntimeError;
-use crate::execution::runtime::{ModuleInst, Store};
+use crate::errors::{Error, LimitError, LinkError};
+use crate::execution::instructions::eval_const;
+use crate::execution::runtime::{
+    Addr, DataInst, ElemInst, ExportInst, ExternVal, FuncInst, GlobalInst, MemInst, ModuleInst,
+    Store, TableInst,
+};
 use crate::execution::stack::{Frame, FrameStack, LabelStack, Val, ValueStack};
-use crate::structure::modules::ExportDesc::Func;
-use crate::structure::modules::Module;
-use std::error::Error;
-
-impl Module {
-    pub fn call(
-        &self,
-        store: Store,
-        name: &String,
-        args: Vec<Val>,
-    ) -> Result<Vec<Val>, Box<dyn Error>> {
-        let export = self
-            .exports
-            .iter()
-            .find(|&e| e.name.0 == *name)
-            .ok_or("unknown function")?;
-        let funcaddr = if let Func(x) = &export.desc {
-            x
-        } else {
-            return Err(Box::new(RuntimeError::InvalidParameters(
-                "unknown function".to_string(),
-            )));
+use crate::structure::modules::{DataMode, ElemMode, ExportDesc, Import, ImportDesc, Module};
+use crate::structure::types::{ExternType, Limits, MemType, TableType, ValType};
+use std::rc::Rc;
+
+impl Store {
+    // Instantiation: https://webassembly.github.io/spec/core/exec/modules.html#instantiation
+    // Instantiates a validated module, where `externvals` are the values its imports
+    // resolve to, in import order. A trap while initializing segments or running the
+    // start function fails the instantiation, but keeps the effects on the store so far.
+    pub fn instantiate(
+        &mut self,
+        module: &Module,
+        externvals: &[ExternVal],
+    ) -> Result<Rc<ModuleInst>, Error> {
+        if externvals.len() != module.imports.len() {
+            return Err(LinkError::ImportCount {
+                expected: module.imports.len(),
+                found: externvals.len(),
+            }
+            .into());
+        }
+        for (import, v) in module.imports.iter().zip(externvals) {
+            check_import(module, import, &v.type_(self))?;
+        }
+
+        // Tables and memories are allocated up front, so that one exceeding the limit
+        // fails the instantiation before the store is modified.
+        let mut tables = vec![];
+        for table in &module.tables {
+            let type_ = table.type_.clone();
+            let min = type_.0.min;
+            tables.push(TableInst::new(type_).ok_or(LimitError::Table(min))?);
+        }
+        let mut mems = vec![];
+        for mem in &module.mems {
+            let type_ = mem.type_.clone();
+            let min = type_.0.min;
+            mems.push(MemInst::new(type_).ok_or(LimitError::Memory(min))?);
+        }
+
+        let inst = Rc::new(self.alloc_module_inst(module, externvals));
+
+        // Initializers can only refer to imported globals and to functions, whose
+        // addresses are already known.
+        let mut vals = vec![];
+        for global in &module.globals {
+            vals.push(eval_const(&inst, self, &global.init)?);
+        }
+        let mut refs = vec![];
+        for elem in &module.elems {
+            let mut r = vec![];
+            for init in &elem.init {
+                match eval_const(&inst, self, init)? {
+                    Val::Ref(v) => r.push(v),
+                    v => unreachable!("invalid element expression result {:?}", v),
+                }
+            }
+            refs.push(r);
+        }
+
+        // Allocation: https://webassembly.github.io/spec/core/exec/modules.html#alloc-module
+        for f in &module.funcs {
+            self.funcs.push(FuncInst::Module {
+                type_: inst.types[f.type_.0 as usize].clone(),
+                module: inst.clone(),
+                code: Rc::new(f.clone()),
+            });
+        }
+        self.tables.extend(tables);
+        self.mems.extend(mems);
+        for (global, value) in module.globals.iter().zip(vals) {
+            self.globals.push(GlobalInst {
+                type_: global.type_.clone(),
+                value,
+            });
+        }
+        for (elem, elem_refs) in module.elems.iter().zip(refs) {
+            self.elems.push(ElemInst {
+                type_: elem.type_,
+                elem: elem_refs,
+            });
+        }
+        for data in &module.datas {
+            self.datas.push(DataInst {
+                data: data.init.iter().map(|b| b.0).collect(),
+            });
+        }
+
+        // Active segments are copied in order and then dropped, like table.init and
+        // memory.init followed by elem.drop and data.drop. Declarative segments only
+        // forward-declare references and are dropped right away.
+        for (i, elem) in module.elems.iter().enumerate() {
+            let elemaddr = inst.elemaddrs[i];
+            match &elem.mode {
+                ElemMode::Passive => {}
+                ElemMode::Active { table, offset } => {
+                    let offset = match eval_const(&inst, self, offset)? {
+                        Val::I32(offset) => offset,
+                        v => unreachable!("invalid element segment offset {:?}", v),
+                    };
+                    let tableaddr = inst.tableaddrs[table.0 as usize];
+                    let refs = std::mem::take(&mut self.elems[elemaddr].elem);
+                    self.tables[tableaddr].write(offset, &refs)?;
+                }
+                ElemMode::Declarative => self.elems[elemaddr].elem.clear(),
+            }
+        }
+
+        for (i, data) in module.datas.iter().enumerate() {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                let offset = match eval_const(&inst, self, offset)? {
+                    Val::I32(offset) => offset,
+                    v => unreachable!("invalid data segment offset {:?}", v),
+                };
+                let memaddr = inst.memaddrs[memory.0 as usize];
+                let bytes = std::mem::take(&mut self.datas[inst.dataaddrs[i]].data);
+                self.mems[memaddr].write(offset, 0, &bytes)?;
+            }
+        }
+
+        if let Some(start) = &module.start {
+            invoke(self, inst.funcaddrs[start.func.0 as usize], &[])?;
+        }
+
+        Ok(inst)
+    }
+
+    // Computes the addresses the module's instances will be allocated at, following
+    // the instances of its imports, and its exports.
+    fn alloc_module_inst(&self, module: &Module, externvals: &[ExternVal]) -> ModuleInst {
+        let mut inst = ModuleInst {
+            types: module.types.clone(),
+            ..Default::default()
         };
+        for v in externvals {
+            match *v {
+                ExternVal::Func(a) => inst.funcaddrs.push(a),
+                ExternVal::Table(a) => inst.tableaddrs.push(a),
+                ExternVal::Mem(a) => inst.memaddrs.push(a),
+                ExternVal::Global(a) => inst.globaladdrs.push(a),
+            }
+        }
+
+        let funcs = self.funcs.len();
+        inst.funcaddrs.extend(funcs..funcs + module.funcs.len());
+        let tables = self.tables.len();
+        inst.tableaddrs.extend(tables..tables + module.tables.len());
+        let mems = self.mems.len();
+        inst.memaddrs.extend(mems..mems + module.mems.len());
+        let globals = self.globals.len();
+        inst.globaladdrs
+            .extend(globals..globals + module.globals.len());
+        let elems = self.elems.len();
+        inst.elemaddrs.extend(elems..elems + module.elems.len());
+        let datas = self.datas.len();
+        inst.dataaddrs.extend(datas..datas + module.datas.len());
 
-        let funcinst = store
-            .funcs
-            .get((*funcaddr).0 as usize)
-            .ok_or("unknown func")?;
-        if args.len() != (*funcinst).type_.0 .0.len() {
-            return Err(Box::new(RuntimeError::InvalidParameters(
-                "invalid args".to_string(),
-            )));
+        for export in &module.exports {
+            let value = match &export.desc {
+                ExportDesc::Func(x) => ExternVal::Func(inst.funcaddrs[x.0 as usize]),
+                ExportDesc::Table(x) => ExternVal::Table(inst.tableaddrs[x.0 as usize]),
+                ExportDesc::Mem(x) => ExternVal::Mem(inst.memaddrs[x.0 as usize]),
+                ExportDesc::Global(x) => ExternVal::Global(inst.globaladdrs[x.0 as usize]),
+            };
+            inst.exports.push(ExportInst {
+                name: export.name.0.clone(),
+                value,
+            });
         }
 
-        let mut frame_stack = FrameStack(vec![]);
-        let mut value_stack = ValueStack(vec![]);
+        inst
+    }
+}
+
+// Invocation: https://webassembly.github.io/spec/core/exec/modules.html#invocation
+pub fn invoke(store: &mut Store, funcaddr: Addr, args: &[Val]) -> Result<Vec<Val>, Error> {
+    let funcinst = store.funcs[funcaddr].clone();
+    let types: Vec<ValType> = args.iter().map(Val::type_).collect();
+    if types != funcinst.type_().0 .0 {
+        return Err(Error::InvalidParameters(format!(
+            "expected arguments of types {:?}, found {:?}",
+            funcinst.type_().0 .0,
+            types
+        )));
+    }
+    // References are values the embedder can construct, so they have to be checked
+    // against the store before the function can use them.
+    if !args.iter().all(|v| store.contains(v)) {
+        return Err(Error::InvalidParameters(
+            "function reference not in the store".to_string(),
+        ));
+    }
+
+    let mut frame_stack = FrameStack(vec![]);
+    let mut value_stack = ValueStack(vec![]);
+
+    // The dummy frame the invocation starts from, which belongs to no instance.
+    frame_stack.push(Frame {
+        locals: vec![],
+        module: Default::default(),
+        labels: LabelStack(vec![]),
+    });
+
+    // Push the args to the stack
+    for v in args {
+        value_stack.push(*v);
+    }
+
+    funcinst.call(None, store, &mut value_stack, &mut frame_stack)?;
+
+    // The results are on top of the stack in declaration order.
+    let n = funcinst.type_().1 .0.len();
+    let height = value_stack.len() - n;
+    let results = value_stack.0.split_off(height);
+
+    Ok(results)
+}
 
-        // TODO: Push the dummy frame
-        frame_stack.push(Frame {
-            locals: &mut vec![],
-            module: &mut Default::default(),
-            labels: LabelStack(vec![]),
+// Checks that a value of type `actual` can be imported by `import`, an import of
+// `module`.
+pub fn check_import(
+    module: &Module,
+    import: &Import,
+    actual: &ExternType,
+) -> Result<(), LinkError> {
+    let expected = match &import.desc {
+        ImportDesc::Func(x) => ExternType::Func(module.types[x.0 as usize].clone()),
+        ImportDesc::Table(t) => ExternType::Table(t.clone()),
+        ImportDesc::Mem(t) => ExternType::Mem(t.clone()),
+        ImportDesc::Global(t) => ExternType::Global(t.clone()),
+    };
+    if !matches(actual, &expected) {
+        return Err(LinkError::IncompatibleImportType {
+            module: import.module.0.clone(),
+            name: import.name.0.clone(),
         });
+    }
+    Ok(())
+}
 
-        // Push the args to the stack
-        for v in args {
-            value_stack.push(v);
+// Import Matching: https://webassembly.github.io/spec/core/exec/modules.html#import-matching
+pub fn matches(actual: &ExternType, expected: &ExternType) -> bool {
+    match (actual, expected) {
+        (ExternType::Func(f1), ExternType::Func(f2)) => f1 == f2,
+        (ExternType::Table(TableType(l1, t1)), ExternType::Table(TableType(l2, t2))) => {
+            limits_match(l1, l2) && t1 == t2
         }
+        (ExternType::Mem(MemType(l1)), ExternType::Mem(MemType(l2))) => limits_match(l1, l2),
+        (ExternType::Global(g1), ExternType::Global(g2)) => g1 == g2,
+        _ => false,
+    }
+}
+
+// Limits: https://webassembly.github.io/spec/core/exec/modules.html#limits
+fn limits_match(l1: &Limits, l2: &Limits) -> bool {
+    l1.min >= l2.min
+        && match (l1.max, l2.max) {
+            (_, None) => true,
+            (Some(m1), Some(m2)) => m1 <= m2,
+            (None, Some(_)) => false,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::instructions::{Expr, Instr};
+    use crate::structure::modules::{
+        Data, Func, FuncIdx, Global, GlobalIdx, Import, Mem, MemIdx, Module, Start, Table, TypeIdx,
+    };
+    use crate::structure::types::{
+        FuncType, GlobalType, Mut, NumType, RefType, ResultType, ValType,
+    };
+    use crate::structure::values::{Byte, Name};
+
+    // A module whose start function runs `body`.
+    fn start(body: Vec<Instr>) -> Module {
+        Module {
+            types: vec![FuncType(ResultType(vec![]), ResultType(vec![]))],
+            funcs: vec![Func {
+                type_: TypeIdx(0),
+                locals: vec![],
+                body: Expr(body),
+                offsets: vec![],
+            }],
+            start: Some(Start { func: FuncIdx(0) }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn multiple_results() {
+        let results = vec![
+            ValType::NumType(NumType::I32),
+            ValType::NumType(NumType::I64),
+            ValType::NumType(NumType::I32),
+        ];
+        let module = Module {
+            types: vec![FuncType(ResultType(vec![]), ResultType(results))],
+            funcs: vec![Func {
+                type_: TypeIdx(0),
+                locals: vec![],
+                body: Expr(vec![
+                    Instr::I32Const(1),
+                    Instr::I64Const(2),
+                    Instr::I32Const(3),
+                ]),
+                offsets: vec![],
+            }],
+            ..Default::default()
+        };
+        let mut store = Store::default();
+        store.instantiate(&module, &[]).unwrap();
+        let results = invoke(&mut store, 0, &[]).unwrap();
+        assert_eq!(results, vec![Val::I32(1), Val::I64(2), Val::I32(3)]);
+    }
 
-        funcinst.call(&mut value_stack, &mut frame_stack)?;
+    #[test]
+    fn limits_matching() {
+        let mem = |min, max| ExternType::Mem(MemType(Limits { min, max }));
+        assert!(matches(&mem(2, Some(3)), &mem(1, None)));
+        assert!(matches(&mem(2, Some(3)), &mem(2, Some(4))));
+        assert!(!matches(&mem(1, Some(3)), &mem(2, None)));
+        assert!(!matches(&mem(2, None), &mem(2, Some(4))));
+        assert!(!matches(&mem(2, Some(5)), &mem(2, Some(4))));
+    }
 
-        let mut results = vec![];
-        for _ in &funcinst.type_.1 .0 {
-            let t = value_stack.pop().ok_or("result error")?;
-            results.push(t);
+    #[test]
+    fn start_function() {
+        let mut module = start(vec![Instr::I32Const(42), Instr::GlobalSet(GlobalIdx(0))]);
+        module.globals.push(Global {
+            type_: GlobalType(Mut::Var, ValType::NumType(NumType::I32)),
+            init: Expr(vec![Instr::I32Const(0)]),
+        });
+        let mut store = Store::default();
+        store.instantiate(&module, &[]).unwrap();
+        assert_eq!(store.globals[0].value, Val::I32(42));
+    }
+
+    #[test]
+    fn trap_keeps_effects() {
+        // The data segment is written before the start function traps.
+        let mut module = start(vec![Instr::Unreachable]);
+        module.mems.push(Mem {
+            type_: MemType(Limits { min: 1, max: None }),
+        });
+        module.datas.push(Data {
+            init: vec![Byte(42)],
+            mode: DataMode::Active {
+                memory: MemIdx(0),
+                offset: Expr(vec![Instr::I32Const(0)]),
+            },
+        });
+        let mut store = Store::default();
+        let err = store.instantiate(&module, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "trap: unreachable");
+        assert_eq!(store.mems[0].data[0], 42);
+    }
+
+    #[test]
+    fn import_count() {
+        let mut module = start(vec![]);
+        module.imports.push(Import {
+            module: Name("env".to_string()),
+            name: Name("f".to_string()),
+            desc: ImportDesc::Func(TypeIdx(0)),
+        });
+        let err = Store::default().instantiate(&module, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "expected 1 imports, found 0");
+    }
+
+    #[test]
+    fn memory_too_large() {
+        let mut module = start(vec![]);
+        module.mems.push(Mem {
+            type_: MemType(Limits {
+                min: 20_000,
+                max: None,
+            }),
+        });
+        let mut store = Store::default();
+        match store.instantiate(&module, &[]).unwrap_err() {
+            Error::Limit(err) => assert_eq!(err, LimitError::Memory(20_000)),
+            err => panic!("unexpected error: {}", err),
         }
+        assert!(store.funcs.is_empty() && store.mems.is_empty());
+    }
 
-        Ok(results)
+    #[test]
+    fn table_too_large() {
+        let mut module = start(vec![]);
+        module.tables.push(Table {
+            type_: TableType(
+                Limits {
+                    min: 20_000_000,
+                    max: None,
+                },
+                RefType::FuncRef,
+            ),
+        });
+        let mut store = Store::default();
+        match store.instantiate(&module, &[]).unwrap_err() {
+            Error::Limit(err) => assert_eq!(err, LimitError::Table(20_000_000)),
+            err => panic!("unexpected error: {}", err),
+        }
+        assert!(store.funcs.is_empty() && store.tables.is_empty());
     }
 }
@@ -1,11 +1,22 @@
-use crate::structure::modules::{Func, Global, Module};
-use crate::structure::types::{FuncType, TableType};
-use std::borrow::BorrowMut;
-use std::error::Error;
-use std::rc::Weak;
+use crate::embedding::funcs::Caller;
+use crate::errors::Trap;
+use crate::execution::stack::{Ref, Val};
+use crate::structure::modules::Func;
+use crate::structure::types::{ExternType, FuncType, GlobalType, MemType, RefType, TableType};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Clone, PartialEq, Default)]
+// Identifies a store, so that handles and references created in it can't be used with
+// another store. A clone of a store keeps its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreId(usize);
+
+static NEXT_STORE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Store {
+    pub id: StoreId,
     pub funcs: Vec<FuncInst>,
     pub tables: Vec<TableInst>,
     pub mems: Vec<MemInst>,
@@ -14,27 +25,53 @@ pub struct Store {
     pub datas: Vec<DataInst>,
 }
 
+impl Default for Store {
+    fn default() -> Store {
+        Store {
+            id: StoreId(NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed)),
+            funcs: vec![],
+            tables: vec![],
+            mems: vec![],
+            globals: vec![],
+            elems: vec![],
+            datas: vec![],
+        }
+    }
+}
+
 impl Store {
-    pub fn new(module: &Module) -> Result<Store, Box<dyn Error>> {
-        let mut store: Store = Default::default();
-        let mut moduleinst: ModuleInst = Default::default();
-        for (i, f) in module.funcs.iter().enumerate() {
-            let functype = module
-                .types
-                .get(f.type_.0 as usize)
-                .ok_or("unknown functype")?;
-            moduleinst.funcaddrs.push(i);
-            store.funcs.push(FuncInst {
-                type_: functype.clone(),
-                module: &mut moduleinst,
-                code: f.clone(),
-            });
+    // Whether a function reference in `v` was created in the store.
+    pub fn contains(&self, v: &Val) -> bool {
+        match v {
+            Val::Ref(Ref::Func(r)) => r.store == self.id && r.addr < self.funcs.len(),
+            _ => true,
         }
-        Ok(store)
     }
 }
 
-type Addr = usize;
+pub type Addr = usize;
+
+// External Values: https://webassembly.github.io/spec/core/exec/runtime.html#external-values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExternVal {
+    Func(Addr),
+    Table(Addr),
+    Mem(Addr),
+    Global(Addr),
+}
+
+impl ExternVal {
+    // Returns the current type of the external value, which reflects any growth of
+    // tables and memories.
+    pub fn type_(&self, store: &Store) -> ExternType {
+        match *self {
+            ExternVal::Func(a) => ExternType::Func(store.funcs[a].type_().clone()),
+            ExternVal::Table(a) => ExternType::Table(store.tables[a].type_.clone()),
+            ExternVal::Mem(a) => ExternType::Mem(store.mems[a].type_.clone()),
+            ExternVal::Global(a) => ExternType::Global(store.globals[a].type_.clone()),
+        }
+    }
+}
 
 // Module Instances: https://webassembly.github.io/spec/core/exec/runtime.html#module-instances
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -46,32 +83,231 @@ pub struct ModuleInst {
     pub globaladdrs: Vec<Addr>,
     pub elemaddrs: Vec<Addr>,
     pub dataaddrs: Vec<Addr>,
-    pub exports: Vec<Addr>,
+    pub exports: Vec<ExportInst>,
 }
 
+// Export Instances: https://webassembly.github.io/spec/core/exec/runtime.html#export-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct FuncInst {
-    pub type_: FuncType,
-    pub module: *mut ModuleInst,
-    pub code: Func,
+pub struct ExportInst {
+    pub name: String,
+    pub value: ExternVal,
 }
 
-// TODO
+// Function Instances: https://webassembly.github.io/spec/core/exec/runtime.html#function-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct TableInst {}
+pub enum FuncInst {
+    Module {
+        type_: FuncType,
+        module: Rc<ModuleInst>,
+        // Shared so that a function instance can be cloned out of the store to call it.
+        code: Rc<Func>,
+    },
+    Host {
+        type_: FuncType,
+        hostcode: HostFunc,
+    },
+}
+
+impl FuncInst {
+    pub fn type_(&self) -> &FuncType {
+        match self {
+            FuncInst::Module { type_, .. } | FuncInst::Host { type_, .. } => type_,
+        }
+    }
+}
+
+// A function provided by the embedder. It is called with the caller's state and the
+// arguments, and returns the results or a trap.
+#[derive(Clone)]
+pub struct HostFunc(pub Rc<HostCode>);
 
-// TODO
+pub type HostCode = dyn Fn(&mut Caller, &[Val]) -> Result<Vec<Val>, Trap>;
+
+impl fmt::Debug for HostFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HostFunc")
+    }
+}
+
+impl PartialEq for HostFunc {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Table Instances: https://webassembly.github.io/spec/core/exec/runtime.html#table-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct MemInst {}
+pub struct TableInst {
+    pub type_: TableType,
+    pub elem: Vec<Ref>,
+}
+
+// An implementation limit on the number of table elements, so that neither allocation
+// nor table.grow can exhaust host memory.
+const MAX_TABLE_SIZE: u32 = 10_000_000;
+
+impl TableInst {
+    // Allocates a table of the minimum size, or returns None if it exceeds the limit.
+    pub fn new(type_: TableType) -> Option<TableInst> {
+        if type_.0.min > MAX_TABLE_SIZE {
+            return None;
+        }
+        let elem = vec![Ref::Null(type_.1); type_.0.min as usize];
+        Some(TableInst { type_, elem })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.elem.len() as u32
+    }
+
+    // Grows the table by `n` elements set to `init`, returning the previous size, or
+    // None if the table cannot grow that far.
+    pub fn grow(&mut self, n: u32, init: Ref) -> Option<u32> {
+        let size = self.size();
+        let max = self.type_.0.max.unwrap_or(u32::MAX).min(MAX_TABLE_SIZE);
+        let len = size.checked_add(n).filter(|&len| len <= max)?;
+
+        self.elem.resize(len as usize, init);
+        self.type_.0.min = len;
+        Some(size)
+    }
+
+    // Reads the element at index `i`, where `i` is the operand interpreted as unsigned.
+    pub fn get(&self, i: i32) -> Result<Ref, Trap> {
+        let i = self.index(i, 1)?;
+        Ok(self.elem[i])
+    }
 
-// TODO
+    // Writes `refs` to the elements starting at index `i`.
+    pub fn write(&mut self, i: i32, refs: &[Ref]) -> Result<(), Trap> {
+        let i = self.index(i, refs.len())?;
+        self.elem[i..i + refs.len()].copy_from_slice(refs);
+        Ok(())
+    }
+
+    // Reads `n` elements starting at index `i`.
+    pub fn read(&self, i: i32, n: i32) -> Result<Vec<Ref>, Trap> {
+        let n = n as u32 as usize;
+        let i = self.index(i, n)?;
+        Ok(self.elem[i..i + n].to_vec())
+    }
+
+    // Sets `n` elements starting at index `i` to `val`.
+    pub fn fill(&mut self, i: i32, n: i32, val: Ref) -> Result<(), Trap> {
+        let n = n as u32 as usize;
+        let i = self.index(i, n)?;
+        self.elem[i..i + n].fill(val);
+        Ok(())
+    }
+
+    fn index(&self, i: i32, n: usize) -> Result<usize, Trap> {
+        let i = i as u32 as u64;
+        if i + n as u64 > self.elem.len() as u64 {
+            return Err(Trap::OutOfBoundsTableAccess);
+        }
+        Ok(i as usize)
+    }
+}
+
+// Memory Instances: https://webassembly.github.io/spec/core/exec/runtime.html#memory-instances
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemInst {
+    pub type_: MemType,
+    pub data: Vec<u8>,
+}
+
+pub const PAGE_SIZE: usize = 65536;
+
+// An implementation limit on the number of pages, 1 GiB, below the 4 GiB addressable
+// with 32-bit memory indices, so that neither allocation nor memory.grow can exhaust
+// host memory.
+const MAX_MEMORY_SIZE: u32 = 16384;
+
+impl MemInst {
+    // Allocates a memory of the minimum size, or returns None if it exceeds the limit.
+    pub fn new(type_: MemType) -> Option<MemInst> {
+        if type_.0.min > MAX_MEMORY_SIZE {
+            return None;
+        }
+        let data = vec![0; type_.0.min as usize * PAGE_SIZE];
+        Some(MemInst { type_, data })
+    }
+
+    // Returns the current size in pages.
+    pub fn size(&self) -> u32 {
+        (self.data.len() / PAGE_SIZE) as u32
+    }
+
+    // Grows the memory by `n` pages, returning the previous size in pages, or None if
+    // the memory cannot grow that far.
+    pub fn grow(&mut self, n: u32) -> Option<u32> {
+        let size = self.size();
+        let max = self.type_.0.max.unwrap_or(u32::MAX).min(MAX_MEMORY_SIZE);
+        let len = size.checked_add(n).filter(|&len| len <= max)?;
+
+        self.data.resize(len as usize * PAGE_SIZE, 0);
+        self.type_.0.min = len;
+        Some(size)
+    }
+
+    // Reads `N` bytes at the effective address `addr + offset`, where `addr` is the
+    // operand interpreted as unsigned.
+    pub fn read<const N: usize>(&self, addr: i32, offset: u32) -> Result<[u8; N], Trap> {
+        let ea = self.effective_address(addr, offset, N)?;
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.data[ea..ea + N]);
+        Ok(bytes)
+    }
+
+    // Writes `bytes` at the effective address `addr + offset`.
+    pub fn write(&mut self, addr: i32, offset: u32, bytes: &[u8]) -> Result<(), Trap> {
+        let ea = self.effective_address(addr, offset, bytes.len())?;
+        self.data[ea..ea + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Copies `n` bytes from `s` to `d`, where the two regions may overlap.
+    pub fn copy(&mut self, d: i32, s: i32, n: i32) -> Result<(), Trap> {
+        let n = n as u32 as usize;
+        let s = self.effective_address(s, 0, n)?;
+        let d = self.effective_address(d, 0, n)?;
+        self.data.copy_within(s..s + n, d);
+        Ok(())
+    }
+
+    // Sets `n` bytes starting at `d` to `val`.
+    pub fn fill(&mut self, d: i32, val: u8, n: i32) -> Result<(), Trap> {
+        let n = n as u32 as usize;
+        let d = self.effective_address(d, 0, n)?;
+        self.data[d..d + n].fill(val);
+        Ok(())
+    }
+
+    fn effective_address(&self, addr: i32, offset: u32, n: usize) -> Result<usize, Trap> {
+        let ea = addr as u32 as u64 + offset as u64;
+        if ea + n as u64 > self.data.len() as u64 {
+            return Err(Trap::OutOfBoundsMemoryAccess);
+        }
+        Ok(ea as usize)
+    }
+}
+
+// Global Instances: https://webassembly.github.io/spec/core/exec/runtime.html#global-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct GlobalInst {}
+pub struct GlobalInst {
+    pub type_: GlobalType,
+    pub value: Val,
+}
 
-// TODO
+// Element Instances: https://webassembly.github.io/spec/core/exec/runtime.html#element-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct ElemInst {}
+pub struct ElemInst {
+    pub type_: RefType,
+    pub elem: Vec<Ref>,
+}
 
-// TODO
+// Data Instances: https://webassembly.github.io/spec/core/exec/runtime.html#data-instances
 #[derive(Debug, Clone, PartialEq)]
-pub struct DataInst {}
+pub struct DataInst {
+    pub data: Vec<u8>,
+}
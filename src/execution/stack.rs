@@ -1,5 +1,6 @@
-use crate::execution::runtime::ModuleInst;
-use crate::structure::instructions::Instr;
+use crate::execution::runtime::{Addr, ModuleInst, StoreId};
+use crate::structure::types::{NumType, RefType, ValType};
+use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub enum Val {
@@ -7,6 +8,40 @@ pub enum Val {
     I64(i64),
     F32(f32),
     F64(f64),
+    Ref(Ref),
+}
+
+impl Val {
+    pub fn type_(&self) -> ValType {
+        match self {
+            Val::I32(_) => ValType::NumType(NumType::I32),
+            Val::I64(_) => ValType::NumType(NumType::I64),
+            Val::F32(_) => ValType::NumType(NumType::F32),
+            Val::F64(_) => ValType::NumType(NumType::F64),
+            Val::Ref(Ref::Null(t)) => ValType::RefType(*t),
+            Val::Ref(Ref::Func(_)) => ValType::RefType(RefType::FuncRef),
+            Val::Ref(Ref::Extern(_)) => ValType::RefType(RefType::ExternRef),
+        }
+    }
+}
+
+// References: https://webassembly.github.io/spec/core/exec/runtime.html#values
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Ref {
+    Null(RefType),
+    Func(FuncRef),
+    // An opaque value chosen by the host, which guest code can only pass around. It is
+    // not an address, so it is valid in every store.
+    Extern(usize),
+}
+
+// A reference to a function, tied to the store it was created in. Outside the crate it
+// can only be obtained from running code or from a Func handle, so the embedder can't
+// forge one that refers to a function of another store.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FuncRef {
+    pub(crate) store: StoreId,
+    pub(crate) addr: Addr,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -20,21 +55,49 @@ impl ValueStack {
     pub fn pop(&mut self) -> Option<Val> {
         self.0.pop()
     }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    // Keeps the top `n` values and discards the values between them and `height`.
+    pub fn unwind(&mut self, height: usize, n: usize) {
+        let top = self.0.len() - n;
+        self.0.drain(height..top);
+    }
 }
 
+// Labels: https://webassembly.github.io/spec/core/exec/runtime.html#labels
 #[derive(Debug, Clone, PartialEq)]
 pub struct Label {
-    pub instrs: Vec<Instr>,
+    // The number of values a branch to this label carries.
     pub n: usize,
+    // The value stack height below the block's parameters.
+    pub height: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct LabelStack(pub Vec<Label>);
 
+impl LabelStack {
+    pub fn push(&mut self, l: Label) {
+        self.0.push(l);
+    }
+
+    pub fn pop(&mut self) -> Option<Label> {
+        self.0.pop()
+    }
+
+    // Looks up a label by its relative index, where 0 is the innermost label.
+    pub fn get(&self, idx: usize) -> Option<&Label> {
+        self.0.iter().rev().nth(idx)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Frame {
-    pub locals: *mut Vec<Val>,
-    pub module: *mut ModuleInst,
+    pub locals: Vec<Val>,
+    pub module: Rc<ModuleInst>,
     pub labels: LabelStack,
 }
 
@@ -49,4 +112,12 @@ impl FrameStack {
     pub fn pop(&mut self) -> Option<Frame> {
         self.0.pop()
     }
+
+    pub fn labels(&mut self) -> &mut LabelStack {
+        &mut self.0.last_mut().expect("no frame").labels
+    }
+
+    pub fn locals(&mut self) -> &mut Vec<Val> {
+        &mut self.0.last_mut().expect("no frame").locals
+    }
 }
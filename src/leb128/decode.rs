@@ -13,15 +13,18 @@ pub fn low_bits_of_byte(byte: u8) -> u8 {
 pub enum Error {
     /// There was an underlying IO error.
     #[error("IO error: {0}")]
-    IoError(io::Error),
+    Io(io::Error),
     /// The number being read is larger than can be represented.
     #[error("leb128 overflow")]
     Overflow,
+    /// The number continues past the last byte its type can have.
+    #[error("leb128 representation too long")]
+    TooLong,
 }
 
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
-        Error::IoError(e)
+        Error::Io(e)
     }
 }
 
@@ -43,11 +46,14 @@ where
         size += 1;
 
         byte = buf[0];
-        if shift == 31 && byte != 0x00 && byte != 0x01 {
-            while byte & CONTINUATION_BIT != 0 {
-                r.read_exact(&mut buf)?;
+        // The fifth byte holds the top 4 bits and must end the number.
+        if shift == 28 {
+            if low_bits_of_byte(byte) > 0x0f {
+                return Err(Error::Overflow);
+            }
+            if byte & CONTINUATION_BIT != 0 {
+                return Err(Error::TooLong);
             }
-            return Err(Error::Overflow);
         }
 
         let low_bits = low_bits_of_byte(byte) as u32;
@@ -79,11 +85,16 @@ where
         size += 1;
 
         byte = buf[0];
-        if shift == 63 && byte != 0x00 && byte != 0x7f {
-            while buf[0] & CONTINUATION_BIT != 0 {
-                r.read_exact(&mut buf)?;
+        // The fifth byte holds the top 4 bits, its unused bits must sign-extend them,
+        // and it must end the number.
+        if shift == 28 {
+            let unused = low_bits_of_byte(byte) & 0x78;
+            if unused != 0 && unused != 0x78 {
+                return Err(Error::Overflow);
+            }
+            if byte & CONTINUATION_BIT != 0 {
+                return Err(Error::TooLong);
             }
-            return Err(Error::Overflow);
         }
 
         let low_bits = low_bits_of_byte(byte) as i32;
@@ -102,3 +113,92 @@ where
 
     Ok((size, result))
 }
+
+/// Read a signed 64-bit LEB128-encoded number from the `std::io::Read`
+/// stream `r`.
+///
+/// On success, return the number.
+pub fn i64<R>(r: &mut R) -> Result<(usize, i64), Error>
+where
+    R: ?Sized + io::Read,
+{
+    let mut result: i64 = 0;
+    let mut shift = 0;
+    let mut size: usize = 0;
+    let mut byte;
+
+    loop {
+        let mut buf = [0];
+        r.read_exact(&mut buf)?;
+        size += 1;
+
+        byte = buf[0];
+        // The tenth byte holds the top bit, its unused bits must sign-extend it, and it
+        // must end the number.
+        if shift == 63 {
+            let unused = low_bits_of_byte(byte);
+            if unused != 0 && unused != 0x7f {
+                return Err(Error::Overflow);
+            }
+            if byte & CONTINUATION_BIT != 0 {
+                return Err(Error::TooLong);
+            }
+        }
+
+        let low_bits = low_bits_of_byte(byte) as i64;
+        result |= low_bits << shift;
+        shift += 7;
+
+        if byte & CONTINUATION_BIT == 0 {
+            break;
+        }
+    }
+
+    if shift < 64 && (SIGN_BIT & byte) == SIGN_BIT {
+        // Sign extend the result.
+        result |= !0 << shift;
+    }
+
+    Ok((size, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_32_bits() {
+        let max = [0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert_eq!(u32(&mut &max[..]).unwrap(), (5, u32::MAX));
+
+        // The fifth byte holds the top 4 bits and ends the number.
+        let too_large = [0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert!(matches!(u32(&mut &too_large[..]), Err(Error::Overflow)));
+        let too_long = [0x80, 0x80, 0x80, 0x80, 0x80, 0];
+        assert!(matches!(u32(&mut &too_long[..]), Err(Error::TooLong)));
+    }
+
+    #[test]
+    fn signed_32_bits() {
+        let min = [0x80, 0x80, 0x80, 0x80, 0x78];
+        assert_eq!(i32(&mut &min[..]).unwrap(), (5, i32::MIN));
+        let minus_one = [0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(i32(&mut &minus_one[..]).unwrap(), (5, -1));
+
+        // The unused bits of the fifth byte must sign-extend the top bit.
+        let unextended = [0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert!(matches!(i32(&mut &unextended[..]), Err(Error::Overflow)));
+    }
+
+    #[test]
+    fn signed_64_bits() {
+        assert_eq!(i64(&mut &[0x7e][..]).unwrap(), (1, -2));
+        assert_eq!(i64(&mut &[0x80, 0x7f][..]).unwrap(), (2, -128));
+
+        let min = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f];
+        assert_eq!(i64(&mut &min[..]).unwrap(), (10, i64::MIN));
+
+        let too_long = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert!(matches!(i64(&mut &too_long[..]), Err(Error::Overflow)));
+    }
+}
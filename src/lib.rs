@@ -1,34 +1,113 @@
+//! A WebAssembly interpreter.
+//!
+//! Modules are decoded and validated into a [`Module`], instantiated into a [`Store`]
+//! and called through the functions they export:
+//!
+//! ```
+//! use rasm::{Engine, Instance, Module, Store, Val};
+//!
+//! // (module (func (export "add") (param i32 i32) (result i32)
+//! //   local.get 0 local.get 1 i32.add))
+//! let bytes = [
+//!     0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f,
+//!     0x7f, 0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+//!     0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+//! ];
+//!
+//! let engine = Engine::new();
+//! let module = Module::new(&engine, bytes)?;
+//! let mut store = Store::new(&engine);
+//! let instance = Instance::new(&mut store, &module)?;
+//!
+//! let add = instance.get_func(&store, "add").expect("exported");
+//! assert_eq!(add.call(&mut store, &[Val::I32(1), Val::I32(2)])?, vec![Val::I32(3)]);
+//!
+//! let add = instance.get_typed_func::<(i32, i32), i32>(&store, "add")?;
+//! assert_eq!(add.call(&mut store, (1, 2))?, 3);
+//! # Ok::<(), rasm::errors::Error>(())
+//! ```
+//!
+//! Modules in the text format are parsed with [`Module::from_wat`]:
+//!
+//! ```
+//! # use rasm::{Engine, Module};
+//! let text = r#"(module (func (export "add") (param i32 i32) (result i32)
+//!   (i32.add (local.get 0) (local.get 1))))"#;
+//! let module = Module::from_wat(&Engine::new(), text)?;
+//! # Ok::<(), rasm::errors::Error>(())
+//! ```
+
 mod binary;
-mod errors;
+mod embedding;
+pub mod errors;
 mod execution;
 mod leb128;
-mod structure;
+pub mod structure;
+mod text;
+mod validation;
 
-use crate::execution::runtime::Store;
-use crate::execution::stack::Val;
-use std::error::Error;
-use std::fs::File;
-use std::io::{BufReader, Read};
+pub use crate::embedding::externs::{Extern, Global, Memory, Table};
+pub use crate::embedding::funcs::{Caller, Func, TypedFunc};
+pub use crate::embedding::instances::{Instance, Store};
+pub use crate::embedding::linker::Linker;
+pub use crate::embedding::modules::{Engine, Module};
+pub use crate::embedding::values::{WasmTy, WasmTypeList};
+pub use crate::execution::runtime::Addr;
+pub use crate::execution::stack::{FuncRef, Ref, Val};
 
-pub fn run(file_name: &String, func_name: &String, args: &[String]) -> Result<(), Box<dyn Error>> {
-    let mut reader = BufReader::new(File::open(file_name)?);
+use crate::errors::Error;
+use crate::structure::types::{NumType, RefType, ValType};
+use std::str::FromStr;
 
-    binary::modules::magic(&mut reader)?;
-    binary::modules::version(&mut reader)?;
-    let module = binary::modules::sections(&mut reader)?;
-    let store = Store::new(&module)?;
+pub fn run(file_name: &str, func_name: &str, args: &[String]) -> Result<(), Error> {
+    let engine = Engine::new();
+    let module = Module::from_file(&engine, file_name)?;
+    let mut store = Store::new(&engine);
+    let instance = Instance::new(&mut store, &module)?;
 
+    let func = instance
+        .get_func(&store, func_name)
+        .ok_or_else(|| Error::InvalidParameters(format!("unknown function {}", func_name)))?;
     let args = args
         .iter()
-        .map(|a| a.parse::<i32>().unwrap())
-        .map(|a| Val::I32(a))
-        .collect();
+        .zip(&func.ty(&store).0 .0)
+        .map(|(a, t)| parse_arg(a, t))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let result = module.call(store, func_name, args)?;
+    let result = func.call(&mut store, &args)?;
 
-    if let Val::I32(res) = result[0] {
-        println!("result: {}", res);
+    for v in result {
+        match v {
+            Val::I32(res) => println!("result: {}", res),
+            Val::I64(res) => println!("result: {}", res),
+            Val::F32(res) => println!("result: {}", res),
+            Val::F64(res) => println!("result: {}", res),
+            Val::Ref(Ref::Null(_)) => println!("result: null"),
+            Val::Ref(Ref::Func(r)) => println!("result: func {}", r.addr),
+            Val::Ref(Ref::Extern(a)) => println!("result: extern {}", a),
+        }
     }
 
     Ok(())
 }
+
+fn parse_arg(arg: &str, t: &ValType) -> Result<Val, Error> {
+    let v = match t {
+        ValType::NumType(NumType::I32) => Val::I32(parse(arg)?),
+        ValType::NumType(NumType::I64) => Val::I64(parse(arg)?),
+        ValType::NumType(NumType::F32) => Val::F32(parse(arg)?),
+        ValType::NumType(NumType::F64) => Val::F64(parse(arg)?),
+        ValType::RefType(t) if arg == "null" => Val::Ref(Ref::Null(*t)),
+        // Any other externref argument is passed to the function as an opaque handle.
+        ValType::RefType(RefType::ExternRef) => Val::Ref(Ref::Extern(parse(arg)?)),
+        ValType::RefType(RefType::FuncRef) => Err(Error::InvalidParameters(
+            "unsupported argument type".to_string(),
+        ))?,
+    };
+    Ok(v)
+}
+
+fn parse<T: FromStr>(arg: &str) -> Result<T, Error> {
+    arg.parse()
+        .map_err(|_| Error::InvalidParameters(format!("invalid argument {}", arg)))
+}
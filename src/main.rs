@@ -11,5 +11,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     let (file_name, func_name, args) = (&args[1], &args[2], &args[3..]);
-    run(file_name, func_name, args)
+    run(file_name, func_name, args)?;
+    Ok(())
 }
@@ -1,6 +1,5 @@
 use super::instructions::Expr;
-use super::types::{FuncType, GlobalType, MemType, TableType, ValType};
-use crate::structure::types::RefType;
+use super::types::{FuncType, GlobalType, MemType, RefType, TableType, ValType};
 use crate::structure::values::{Byte, Name};
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -15,6 +14,8 @@ pub struct Module {
     pub start: Option<Start>,
     pub imports: Vec<Import>,
     pub exports: Vec<Export>,
+    pub customs: Vec<Custom>,
+    pub names: Names,
 }
 
 // Indices: https://webassembly.github.io/spec/core/syntax/modules.html#indices
@@ -51,6 +52,9 @@ pub struct Func {
     pub type_: TypeIdx,
     pub locals: Vec<ValType>,
     pub body: Expr,
+    // The byte offsets in the binary module of the instructions of the body, in the order
+    // they appear. Empty for functions that weren't decoded from a binary module.
+    pub offsets: Vec<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -81,11 +85,11 @@ pub struct Elem {
 #[derive(Debug, Clone, PartialEq)]
 pub enum ElemMode {
     Passive,
-    Active,
+    Active { table: TableIdx, offset: Expr },
     Declarative,
 }
 
-// Data Segments
+// Data Segments: https://webassembly.github.io/spec/core/syntax/modules.html#data-segments
 #[derive(Debug, Clone, PartialEq)]
 pub struct Data {
     pub init: Vec<Byte>,
@@ -95,7 +99,7 @@ pub struct Data {
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataMode {
     Passive,
-    Active,
+    Active { memory: MemIdx, offset: Expr },
 }
 
 // Start Function: https://webassembly.github.io/spec/core/syntax/modules.html#start-function
@@ -129,8 +133,37 @@ pub struct Import {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ImportDesc {
-    Func(FuncIdx),
-    Table(TableIdx),
-    Mem(MemIdx),
-    Global(GlobalIdx),
+    Func(TypeIdx),
+    Table(TableType),
+    Mem(MemType),
+    Global(GlobalType),
+}
+
+// Custom Sections: https://webassembly.github.io/spec/core/binary/modules.html#custom-section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Custom {
+    pub name: Name,
+    pub bytes: Vec<Byte>,
+}
+
+// Name Section: https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Names {
+    pub module: Option<Name>,
+    pub funcs: Vec<(FuncIdx, Name)>,
+    pub locals: Vec<(FuncIdx, Vec<(LocalIdx, Name)>)>,
+}
+
+impl Names {
+    pub fn func(&self, idx: &FuncIdx) -> Option<&Name> {
+        self.funcs.iter().find(|(i, _)| i == idx).map(|(_, n)| n)
+    }
+
+    pub fn local(&self, func: &FuncIdx, idx: &LocalIdx) -> Option<&Name> {
+        self.locals
+            .iter()
+            .find(|(i, _)| i == func)
+            .and_then(|(_, locals)| locals.iter().find(|(i, _)| i == idx))
+            .map(|(_, n)| n)
+    }
 }
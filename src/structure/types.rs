@@ -6,7 +6,7 @@ pub enum NumType {
     F64,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Copy)]
 pub enum RefType {
     FuncRef,
     ExternRef,
@@ -45,3 +45,12 @@ pub enum Mut {
     Const,
     Var,
 }
+
+// External Types: https://webassembly.github.io/spec/core/syntax/types.html#external-types
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternType {
+    Func(FuncType),
+    Table(TableType),
+    Mem(MemType),
+    Global(GlobalType),
+}
@@ -0,0 +1,53 @@
+use crate::structure::types::FuncType;
+use std::collections::HashMap;
+
+// Contexts: https://webassembly.github.io/spec/core/text/conventions.html#contexts
+// The identifiers bound in each index space, and the type definitions of the module,
+// which implicit type uses are added to.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Context<'a> {
+    pub types: Ids<'a>,
+    pub funcs: Ids<'a>,
+    pub tables: Ids<'a>,
+    pub mems: Ids<'a>,
+    pub globals: Ids<'a>,
+    pub elems: Ids<'a>,
+    pub datas: Ids<'a>,
+    pub locals: Ids<'a>,
+    // The labels of the enclosing blocks, innermost last.
+    pub labels: Vec<Option<&'a str>>,
+    pub typedefs: Vec<FuncType>,
+}
+
+// The identifiers of an index space and the number of indices in it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ids<'a> {
+    ids: HashMap<&'a str, u32>,
+    pub len: u32,
+}
+
+impl<'a> Ids<'a> {
+    // Allocates the next index, bound to `id` if any. Returns `None` if `id` is already
+    // bound.
+    pub fn push(&mut self, id: Option<&'a str>) -> Option<u32> {
+        let idx = self.len;
+        if let Some(id) = id {
+            if self.ids.insert(id, idx).is_some() {
+                return None;
+            }
+        }
+        self.len += 1;
+        Some(idx)
+    }
+
+    pub fn get(&self, id: &str) -> Option<u32> {
+        self.ids.get(id).copied()
+    }
+
+    // The identifiers in index order.
+    pub fn names(&self) -> Vec<(u32, &'a str)> {
+        let mut names: Vec<_> = self.ids.iter().map(|(id, idx)| (*idx, *id)).collect();
+        names.sort_unstable();
+        names
+    }
+}
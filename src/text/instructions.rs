@@ -0,0 +1,569 @@
+use crate::errors::ParseError;
+use crate::structure::instructions::Instr::*;
+use crate::structure::instructions::{BlockType, Expr, Instr, MemArg};
+use crate::structure::modules::{
+    DataIdx, ElemIdx, FuncIdx, GlobalIdx, LabelIdx, LocalIdx, TableIdx,
+};
+use crate::structure::types::{FuncType, ResultType};
+use crate::text::lexer::TokenKind;
+use crate::text::parser::Parser;
+use crate::text::values::{is_integer, uint};
+
+impl<'a> Parser<'a> {
+    // Expressions: https://webassembly.github.io/spec/core/text/instructions.html#expressions
+    pub fn expr(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr(self.instrs()?))
+    }
+
+    // Parses instructions in flat or folded form up to the closing parenthesis of the
+    // enclosing s-expression, or the `end` or `else` of the enclosing block.
+    pub fn instrs(&mut self) -> Result<Vec<Instr>, ParseError> {
+        let mut instrs = vec![];
+        loop {
+            match self.peek() {
+                Some(TokenKind::LParen) => self.folded(&mut instrs)?,
+                Some(TokenKind::Atom("end" | "else")) | Some(TokenKind::RParen) | None => {
+                    return Ok(instrs)
+                }
+                Some(TokenKind::Atom(_)) => {
+                    let instr = self.instr()?;
+                    instrs.push(instr);
+                }
+                Some(_) => return Err(self.error("unexpected token")),
+            }
+        }
+    }
+
+    // Parses a single folded instruction, which abbreviates an expression such as the
+    // offset of a segment.
+    pub fn folded_instr(&mut self) -> Result<Expr, ParseError> {
+        let mut instrs = vec![];
+        self.folded(&mut instrs)?;
+        Ok(Expr(instrs))
+    }
+
+    // Block instructions are parsed apart from plain instructions, so that the frames of
+    // the recursion stay small.
+    fn instr(&mut self) -> Result<Instr, ParseError> {
+        let kw = self.any_keyword()?;
+        match kw {
+            "block" | "loop" => self.block(kw),
+            "if" => self.if_(),
+            _ => self.plain(kw),
+        }
+    }
+
+    // Block Instructions: https://webassembly.github.io/spec/core/text/instructions.html#control-instructions
+    fn block(&mut self, kw: &str) -> Result<Instr, ParseError> {
+        self.nest()?;
+        let label = self.label();
+        let bt = self.blocktype()?;
+        let body = self.instrs()?;
+        self.keyword("end")?;
+        self.end_label(label)?;
+        self.depth -= 1;
+        if kw == "block" {
+            Ok(Block(bt, body))
+        } else {
+            Ok(Loop(bt, body))
+        }
+    }
+
+    fn if_(&mut self) -> Result<Instr, ParseError> {
+        self.nest()?;
+        let label = self.label();
+        let bt = self.blocktype()?;
+        let then = self.instrs()?;
+        let else_ = if self.eat_keyword("else") {
+            self.label_id(label)?;
+            self.instrs()?
+        } else {
+            vec![]
+        };
+        self.keyword("end")?;
+        self.end_label(label)?;
+        self.depth -= 1;
+        Ok(If(bt, then, else_))
+    }
+
+    // Folded Instructions: https://webassembly.github.io/spec/core/text/instructions.html#folded-instructions
+    // The operands of a folded instruction are evaluated before it.
+    fn folded(&mut self, instrs: &mut Vec<Instr>) -> Result<(), ParseError> {
+        self.lparen()?;
+        self.nest()?;
+        let kw = self.any_keyword()?;
+        match kw {
+            "block" | "loop" => self.folded_block(kw, instrs)?,
+            "if" => self.folded_if(instrs)?,
+            _ => self.folded_plain(kw, instrs)?,
+        }
+        self.depth -= 1;
+        self.rparen()
+    }
+
+    fn folded_block(&mut self, kw: &str, instrs: &mut Vec<Instr>) -> Result<(), ParseError> {
+        self.label();
+        let bt = self.blocktype()?;
+        let body = self.instrs()?;
+        self.c.labels.pop();
+        instrs.push(if kw == "block" {
+            Block(bt, body)
+        } else {
+            Loop(bt, body)
+        });
+        Ok(())
+    }
+
+    fn folded_if(&mut self, instrs: &mut Vec<Instr>) -> Result<(), ParseError> {
+        // The condition is outside the block, so the label is bound after it.
+        let label = self.id();
+        let bt = self.blocktype()?;
+        while self.peek_lparen() && !self.peek_sexpr("then") {
+            self.folded(instrs)?;
+        }
+        self.c.labels.push(label);
+        self.lparen()?;
+        self.keyword("then")?;
+        let then = self.instrs()?;
+        self.rparen()?;
+        let else_ = if self.peek_sexpr("else") {
+            self.lparen()?;
+            self.keyword("else")?;
+            let else_ = self.instrs()?;
+            self.rparen()?;
+            else_
+        } else {
+            vec![]
+        };
+        self.c.labels.pop();
+        instrs.push(If(bt, then, else_));
+        Ok(())
+    }
+
+    fn folded_plain(&mut self, kw: &str, instrs: &mut Vec<Instr>) -> Result<(), ParseError> {
+        let instr = self.plain(kw)?;
+        while self.peek_lparen() {
+            self.folded(instrs)?;
+        }
+        instrs.push(instr);
+        Ok(())
+    }
+
+    // Labels: https://webassembly.github.io/spec/core/text/instructions.html#labels
+    // Binds the optional label of a block for its body.
+    fn label(&mut self) -> Option<&'a str> {
+        let label = self.id();
+        self.c.labels.push(label);
+        label
+    }
+
+    // Parses the optional identifier repeating the label after `else` or `end`.
+    fn label_id(&mut self, label: Option<&'a str>) -> Result<(), ParseError> {
+        if let Some(id) = self.id() {
+            if Some(id) != label {
+                return Err(self.error_at(self.pos - 1, "mismatching label"));
+            }
+        }
+        Ok(())
+    }
+
+    fn end_label(&mut self, label: Option<&'a str>) -> Result<(), ParseError> {
+        self.label_id(label)?;
+        self.c.labels.pop();
+        Ok(())
+    }
+
+    fn labelidx(&mut self) -> Result<LabelIdx, ParseError> {
+        if let Some(TokenKind::Id(id)) = self.peek() {
+            let depth = self
+                .c
+                .labels
+                .iter()
+                .rev()
+                .position(|l| l == &Some(*id))
+                .ok_or_else(|| self.error(&format!("unknown label ${}", id)))?;
+            self.pos += 1;
+            return Ok(LabelIdx(depth as u32));
+        }
+        Ok(LabelIdx(self.u32()?))
+    }
+
+    // Block types: https://webassembly.github.io/spec/core/text/instructions.html#text-blocktype
+    fn blocktype(&mut self) -> Result<BlockType, ParseError> {
+        if self.peek_sexpr("type") {
+            let (x, _) = self.typeuse()?;
+            return Ok(BlockType::TypeIdx(x));
+        }
+        let params = self.params()?;
+        if params.iter().any(|(id, _)| id.is_some()) {
+            return Err(self.error("unexpected token"));
+        }
+        let results = self.results()?;
+        let bt = match (params.is_empty(), results.as_slice()) {
+            (true, []) => BlockType::Empty,
+            (true, [t]) => BlockType::ValType(t.clone()),
+            _ => {
+                let params = params.into_iter().map(|(_, t)| t).collect();
+                let functype = FuncType(ResultType(params), ResultType(results));
+                BlockType::TypeIdx(self.type_index(functype))
+            }
+        };
+        Ok(bt)
+    }
+
+    // Plain Instructions: https://webassembly.github.io/spec/core/text/instructions.html#plain-instructions
+    fn plain(&mut self, kw: &str) -> Result<Instr, ParseError> {
+        let instr = match kw {
+            "br" => Br(self.labelidx()?),
+            "br_if" => BrIf(self.labelidx()?),
+            "br_table" => {
+                let mut labels = vec![self.labelidx()?];
+                while self.peek_index() {
+                    labels.push(self.labelidx()?);
+                }
+                let default = labels.pop().expect("one label");
+                BrTable(labels, default)
+            }
+            "call" => Call(FuncIdx(self.index(|c| &c.funcs)?)),
+            "call_indirect" => {
+                let table = self.tableidx()?;
+                let (x, ids) = self.typeuse()?;
+                if ids.iter().any(Option::is_some) {
+                    return Err(self.error("unexpected token"));
+                }
+                CallIndirect(x, table)
+            }
+            "select" if self.peek_sexpr("result") => SelectT(self.results()?),
+            "local.get" => LocalGet(self.localidx()?),
+            "local.set" => LocalSet(self.localidx()?),
+            "local.tee" => LocalTee(self.localidx()?),
+            "global.get" => GlobalGet(self.globalidx()?),
+            "global.set" => GlobalSet(self.globalidx()?),
+            "table.get" => TableGet(self.tableidx()?),
+            "table.set" => TableSet(self.tableidx()?),
+            "table.size" => TableSize(self.tableidx()?),
+            "table.grow" => TableGrow(self.tableidx()?),
+            "table.fill" => TableFill(self.tableidx()?),
+            "table.copy" => {
+                let x = self.tableidx()?;
+                let y = self.tableidx()?;
+                TableCopy(x, y)
+            }
+            "table.init" => {
+                // The table index can only be omitted if it is 0.
+                let table = if self.peek_index_at(1) {
+                    TableIdx(self.index(|c| &c.tables)?)
+                } else {
+                    TableIdx(0)
+                };
+                TableInit(table, ElemIdx(self.index(|c| &c.elems)?))
+            }
+            "elem.drop" => ElemDrop(ElemIdx(self.index(|c| &c.elems)?)),
+            "i32.load" => I32Load(self.memarg(4)?),
+            "i64.load" => I64Load(self.memarg(8)?),
+            "f32.load" => F32Load(self.memarg(4)?),
+            "f64.load" => F64Load(self.memarg(8)?),
+            "i32.load8_s" => I32Load8S(self.memarg(1)?),
+            "i32.load8_u" => I32Load8U(self.memarg(1)?),
+            "i32.load16_s" => I32Load16S(self.memarg(2)?),
+            "i32.load16_u" => I32Load16U(self.memarg(2)?),
+            "i64.load8_s" => I64Load8S(self.memarg(1)?),
+            "i64.load8_u" => I64Load8U(self.memarg(1)?),
+            "i64.load16_s" => I64Load16S(self.memarg(2)?),
+            "i64.load16_u" => I64Load16U(self.memarg(2)?),
+            "i64.load32_s" => I64Load32S(self.memarg(4)?),
+            "i64.load32_u" => I64Load32U(self.memarg(4)?),
+            "i32.store" => I32Store(self.memarg(4)?),
+            "i64.store" => I64Store(self.memarg(8)?),
+            "f32.store" => F32Store(self.memarg(4)?),
+            "f64.store" => F64Store(self.memarg(8)?),
+            "i32.store8" => I32Store8(self.memarg(1)?),
+            "i32.store16" => I32Store16(self.memarg(2)?),
+            "i64.store8" => I64Store8(self.memarg(1)?),
+            "i64.store16" => I64Store16(self.memarg(2)?),
+            "i64.store32" => I64Store32(self.memarg(4)?),
+            "memory.init" => MemoryInit(DataIdx(self.index(|c| &c.datas)?)),
+            "data.drop" => DataDrop(DataIdx(self.index(|c| &c.datas)?)),
+            "i32.const" => I32Const(self.i32()?),
+            "i64.const" => I64Const(self.i64()?),
+            "f32.const" => F32Const(self.f32()?),
+            "f64.const" => F64Const(self.f64()?),
+            "ref.null" => RefNull(self.heaptype()?),
+            "ref.func" => RefFunc(FuncIdx(self.index(|c| &c.funcs)?)),
+            // Keywords of the enclosing definition are misplaced rather than unknown.
+            "type" | "param" | "result" | "local" => {
+                return Err(self.error_at(self.pos - 1, "unexpected token"))
+            }
+            kw => op(kw).ok_or_else(|| self.error_at(self.pos - 1, "unknown operator"))?,
+        };
+        Ok(instr)
+    }
+
+    fn localidx(&mut self) -> Result<LocalIdx, ParseError> {
+        Ok(LocalIdx(self.index(|c| &c.locals)?))
+    }
+
+    fn globalidx(&mut self) -> Result<GlobalIdx, ParseError> {
+        Ok(GlobalIdx(self.index(|c| &c.globals)?))
+    }
+
+    // An optional table index, which defaults to 0.
+    fn tableidx(&mut self) -> Result<TableIdx, ParseError> {
+        if !self.peek_index() {
+            return Ok(TableIdx(0));
+        }
+        Ok(TableIdx(self.index(|c| &c.tables)?))
+    }
+
+    // Memory Instructions: https://webassembly.github.io/spec/core/text/instructions.html#memory-instructions
+    // The alignment is given in bytes, but stored as its exponent. It defaults to the
+    // `n` bytes accessed.
+    fn memarg(&mut self, n: u32) -> Result<MemArg, ParseError> {
+        let mut memarg = MemArg {
+            offset: 0,
+            align: n.trailing_zeros(),
+        };
+        if let Some(offset) = self.peek_keyword().and_then(|s| s.strip_prefix("offset=")) {
+            memarg.offset = match uint(offset) {
+                Some(offset) => offset,
+                None if is_integer(offset) => return Err(self.error("i32 constant")),
+                None => return Err(self.error("unknown operator")),
+            };
+            self.pos += 1;
+        }
+        if let Some(align) = self.peek_keyword().and_then(|s| s.strip_prefix("align=")) {
+            let align = uint(align)
+                .filter(|a| a.is_power_of_two())
+                .ok_or_else(|| self.error("alignment"))?;
+            memarg.align = align.trailing_zeros();
+            self.pos += 1;
+        }
+        Ok(memarg)
+    }
+}
+
+// The instructions without immediates.
+fn op(kw: &str) -> Option<Instr> {
+    let instr = match kw {
+        "unreachable" => Unreachable,
+        "nop" => Nop,
+        "return" => Return,
+        "drop" => Drop,
+        "select" => Select,
+        "memory.size" => MemorySize,
+        "memory.grow" => MemoryGrow,
+        "memory.fill" => MemoryFill,
+        "memory.copy" => MemoryCopy,
+        "ref.is_null" => RefIsNull,
+        "i32.eqz" => I32Eqz,
+        "i32.eq" => I32Eq,
+        "i32.ne" => I32Ne,
+        "i32.lt_s" => I32LtS,
+        "i32.lt_u" => I32LtU,
+        "i32.gt_s" => I32GtS,
+        "i32.gt_u" => I32GtU,
+        "i32.le_s" => I32LeS,
+        "i32.le_u" => I32LeU,
+        "i32.ge_s" => I32GeS,
+        "i32.ge_u" => I32GeU,
+        "i64.eqz" => I64Eqz,
+        "i64.eq" => I64Eq,
+        "i64.ne" => I64Ne,
+        "i64.lt_s" => I64LtS,
+        "i64.lt_u" => I64LtU,
+        "i64.gt_s" => I64GtS,
+        "i64.gt_u" => I64GtU,
+        "i64.le_s" => I64LeS,
+        "i64.le_u" => I64LeU,
+        "i64.ge_s" => I64GeS,
+        "i64.ge_u" => I64GeU,
+        "f32.eq" => F32Eq,
+        "f32.ne" => F32Ne,
+        "f32.lt" => F32Lt,
+        "f32.gt" => F32Gt,
+        "f32.le" => F32Le,
+        "f32.ge" => F32Ge,
+        "f64.eq" => F64Eq,
+        "f64.ne" => F64Ne,
+        "f64.lt" => F64Lt,
+        "f64.gt" => F64Gt,
+        "f64.le" => F64Le,
+        "f64.ge" => F64Ge,
+        "i32.clz" => I32Clz,
+        "i32.ctz" => I32Ctz,
+        "i32.popcnt" => I32Popcnt,
+        "i32.add" => I32Add,
+        "i32.sub" => I32Sub,
+        "i32.mul" => I32Mul,
+        "i32.div_s" => I32DivS,
+        "i32.div_u" => I32DivU,
+        "i32.rem_s" => I32RemS,
+        "i32.rem_u" => I32RemU,
+        "i32.and" => I32And,
+        "i32.or" => I32Or,
+        "i32.xor" => I32Xor,
+        "i32.shl" => I32Shl,
+        "i32.shr_s" => I32ShrS,
+        "i32.shr_u" => I32ShrU,
+        "i32.rotl" => I32Rotl,
+        "i32.rotr" => I32Rotr,
+        "i64.clz" => I64Clz,
+        "i64.ctz" => I64Ctz,
+        "i64.popcnt" => I64Popcnt,
+        "i64.add" => I64Add,
+        "i64.sub" => I64Sub,
+        "i64.mul" => I64Mul,
+        "i64.div_s" => I64DivS,
+        "i64.div_u" => I64DivU,
+        "i64.rem_s" => I64RemS,
+        "i64.rem_u" => I64RemU,
+        "i64.and" => I64And,
+        "i64.or" => I64Or,
+        "i64.xor" => I64Xor,
+        "i64.shl" => I64Shl,
+        "i64.shr_s" => I64ShrS,
+        "i64.shr_u" => I64ShrU,
+        "i64.rotl" => I64Rotl,
+        "i64.rotr" => I64Rotr,
+        "f32.abs" => F32Abs,
+        "f32.neg" => F32Neg,
+        "f32.ceil" => F32Ceil,
+        "f32.floor" => F32Floor,
+        "f32.trunc" => F32Trunc,
+        "f32.nearest" => F32Nearest,
+        "f32.sqrt" => F32Sqrt,
+        "f32.add" => F32Add,
+        "f32.sub" => F32Sub,
+        "f32.mul" => F32Mul,
+        "f32.div" => F32Div,
+        "f32.min" => F32Min,
+        "f32.max" => F32Max,
+        "f32.copysign" => F32Copysign,
+        "f64.abs" => F64Abs,
+        "f64.neg" => F64Neg,
+        "f64.ceil" => F64Ceil,
+        "f64.floor" => F64Floor,
+        "f64.trunc" => F64Trunc,
+        "f64.nearest" => F64Nearest,
+        "f64.sqrt" => F64Sqrt,
+        "f64.add" => F64Add,
+        "f64.sub" => F64Sub,
+        "f64.mul" => F64Mul,
+        "f64.div" => F64Div,
+        "f64.min" => F64Min,
+        "f64.max" => F64Max,
+        "f64.copysign" => F64Copysign,
+        "i32.wrap_i64" => I32WrapI64,
+        "i32.trunc_f32_s" => I32TruncF32S,
+        "i32.trunc_f32_u" => I32TruncF32U,
+        "i32.trunc_f64_s" => I32TruncF64S,
+        "i32.trunc_f64_u" => I32TruncF64U,
+        "i64.extend_i32_s" => I64ExtendI32S,
+        "i64.extend_i32_u" => I64ExtendI32U,
+        "i64.trunc_f32_s" => I64TruncF32S,
+        "i64.trunc_f32_u" => I64TruncF32U,
+        "i64.trunc_f64_s" => I64TruncF64S,
+        "i64.trunc_f64_u" => I64TruncF64U,
+        "f32.convert_i32_s" => F32ConvertI32S,
+        "f32.convert_i32_u" => F32ConvertI32U,
+        "f32.convert_i64_s" => F32ConvertI64S,
+        "f32.convert_i64_u" => F32ConvertI64U,
+        "f32.demote_f64" => F32DemoteF64,
+        "f64.convert_i32_s" => F64ConvertI32S,
+        "f64.convert_i32_u" => F64ConvertI32U,
+        "f64.convert_i64_s" => F64ConvertI64S,
+        "f64.convert_i64_u" => F64ConvertI64U,
+        "f64.promote_f32" => F64PromoteF32,
+        "i32.reinterpret_f32" => I32ReinterpretF32,
+        "i64.reinterpret_f64" => I64ReinterpretF64,
+        "f32.reinterpret_i32" => F32ReinterpretI32,
+        "f64.reinterpret_i64" => F64ReinterpretI64,
+        "i32.extend8_s" => I32Extend8S,
+        "i32.extend16_s" => I32Extend16S,
+        "i64.extend8_s" => I64Extend8S,
+        "i64.extend16_s" => I64Extend16S,
+        "i64.extend32_s" => I64Extend32S,
+        "i32.trunc_sat_f32_s" => I32TruncSatF32S,
+        "i32.trunc_sat_f32_u" => I32TruncSatF32U,
+        "i32.trunc_sat_f64_s" => I32TruncSatF64S,
+        "i32.trunc_sat_f64_u" => I32TruncSatF64U,
+        "i64.trunc_sat_f32_s" => I64TruncSatF32S,
+        "i64.trunc_sat_f32_u" => I64TruncSatF32U,
+        "i64.trunc_sat_f64_s" => I64TruncSatF64S,
+        "i64.trunc_sat_f64_u" => I64TruncSatF64U,
+        _ => return None,
+    };
+    Some(instr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::modules::module;
+
+    fn func(body: &str) -> String {
+        format!("(module (func (result i32) {}))", body)
+    }
+
+    #[test]
+    fn nesting() {
+        let depth = 511;
+        let bodies = [
+            // Folded blocks.
+            format!(
+                "{} (i32.const 0) {}",
+                "(block (result i32) ".repeat(depth),
+                ")".repeat(depth)
+            ),
+            // Plain blocks.
+            format!(
+                "{} i32.const 0 {}",
+                "block (result i32) ".repeat(depth),
+                "end ".repeat(depth)
+            ),
+            // Folded operands, down to the folded i32.const.
+            format!(
+                "{} (i32.const 0) {}",
+                "(i32.eqz ".repeat(depth),
+                ")".repeat(depth)
+            ),
+            // Alternating plain and folded blocks.
+            format!(
+                "{} i32.const 0 {}",
+                "loop (result i32) (block (result i32) ".repeat(depth / 2),
+                ") end ".repeat(depth / 2)
+            ),
+        ];
+        for body in &bodies {
+            let m = module(&func(body)).unwrap();
+            m.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn nesting_too_deep() {
+        let bodies = [
+            format!("{} {}", "(block ".repeat(513), ")".repeat(513)),
+            format!("{} {}", "block ".repeat(513), "end ".repeat(513)),
+            format!("{} {}", "(i32.eqz ".repeat(100_000), ")".repeat(100_000)),
+            "i32.const 0 if ".repeat(100_000),
+        ];
+        for body in &bodies {
+            let e = module(&func(body)).unwrap_err();
+            assert_eq!(e.message, "nesting too deep");
+        }
+    }
+
+    #[test]
+    fn misplaced_keywords() {
+        for body in [
+            "(nop) (local i32)",
+            "(block (result i32) (param i32))",
+            "(i32.const 0) (type 0)",
+        ] {
+            let e = module(&func(body)).unwrap_err();
+            assert_eq!(e.message, "unexpected token");
+        }
+        let e = module(&func("(i32.cosnt 0)")).unwrap_err();
+        assert_eq!(e.message, "unknown operator");
+    }
+}
@@ -0,0 +1,249 @@
+use crate::errors::ParseError;
+
+// Tokens: https://webassembly.github.io/spec/core/text/lexical.html#tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind<'a> {
+    LParen,
+    RParen,
+    // A keyword, number or reserved word: any run of identifier characters not starting
+    // with `$`. Which one it is depends on where it appears.
+    Atom(&'a str),
+    // An identifier, without the leading `$`.
+    Id(&'a str),
+    String(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    // The byte offset of the token in the source text.
+    pub offset: usize,
+}
+
+pub fn error(text: &str, offset: usize, message: &str) -> ParseError {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    ParseError {
+        line,
+        column,
+        message: message.to_string(),
+    }
+}
+
+// Splits the text into tokens, skipping white space and comments.
+pub fn tokenize(text: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let bytes = text.as_bytes();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let kind = match bytes[pos] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                pos += 1;
+                continue;
+            }
+            b'(' if bytes.get(pos + 1) == Some(&b';') => {
+                pos = block_comment(text, pos)?;
+                continue;
+            }
+            b';' if bytes.get(pos + 1) == Some(&b';') => {
+                pos = text[pos..].find('\n').map_or(bytes.len(), |n| pos + n);
+                continue;
+            }
+            b'(' => {
+                pos += 1;
+                TokenKind::LParen
+            }
+            b')' => {
+                pos += 1;
+                TokenKind::RParen
+            }
+            b'"' => {
+                let (end, s) = string(text, pos)?;
+                pos = end;
+                TokenKind::String(s)
+            }
+            c if is_idchar(c) => {
+                while pos < bytes.len() && is_idchar(bytes[pos]) {
+                    pos += 1;
+                }
+                match &text[start..pos] {
+                    "$" => return Err(error(text, start, "empty identifier")),
+                    s if s.starts_with('$') => TokenKind::Id(&s[1..]),
+                    s => TokenKind::Atom(s),
+                }
+            }
+            _ => return Err(error(text, start, "unexpected character")),
+        };
+
+        // Tokens other than parentheses must be separated by white space, comments or
+        // parentheses.
+        if !matches!(kind, TokenKind::LParen | TokenKind::RParen) {
+            match bytes.get(pos) {
+                None | Some(b' ' | b'\t' | b'\n' | b'\r' | b'(' | b')') => {}
+                Some(b';') if bytes.get(pos + 1) == Some(&b';') => {}
+                Some(_) => return Err(error(text, start, "unknown operator")),
+            }
+        }
+
+        tokens.push(Token {
+            kind,
+            offset: start,
+        });
+    }
+
+    Ok(tokens)
+}
+
+// Identifier characters: https://webassembly.github.io/spec/core/text/values.html#text-idchar
+fn is_idchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(
+            c,
+            b'!' | b'#'
+                | b'$'
+                | b'%'
+                | b'&'
+                | b'\''
+                | b'*'
+                | b'+'
+                | b'-'
+                | b'.'
+                | b'/'
+                | b':'
+                | b'<'
+                | b'='
+                | b'>'
+                | b'?'
+                | b'@'
+                | b'\\'
+                | b'^'
+                | b'_'
+                | b'`'
+                | b'|'
+                | b'~'
+        )
+}
+
+// Comments: https://webassembly.github.io/spec/core/text/lexical.html#comments
+// Block comments nest. Returns the offset after the comment.
+fn block_comment(text: &str, start: usize) -> Result<usize, ParseError> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut pos = start;
+    while pos + 1 < bytes.len() {
+        match (bytes[pos], bytes[pos + 1]) {
+            (b'(', b';') => {
+                depth += 1;
+                pos += 2;
+            }
+            (b';', b')') => {
+                depth -= 1;
+                pos += 2;
+                if depth == 0 {
+                    return Ok(pos);
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+    Err(error(text, start, "unclosed comment"))
+}
+
+// Strings: https://webassembly.github.io/spec/core/text/values.html#strings
+// Returns the offset after the closing quote and the bytes of the string.
+fn string(text: &str, start: usize) -> Result<(usize, Vec<u8>), ParseError> {
+    let mut bytes = vec![];
+    let mut chars = text[start + 1..].char_indices();
+    let at = |i: usize| start + 1 + i;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((at(i) + 1, bytes)),
+            '\\' => {
+                let (j, e) = chars
+                    .next()
+                    .ok_or_else(|| error(text, start, "unclosed string"))?;
+                match e {
+                    't' => bytes.push(b'\t'),
+                    'n' => bytes.push(b'\n'),
+                    'r' => bytes.push(b'\r'),
+                    '"' => bytes.push(b'"'),
+                    '\'' => bytes.push(b'\''),
+                    '\\' => bytes.push(b'\\'),
+                    'u' => {
+                        let rest = &text[at(j) + 1..];
+                        let end = rest.find('}').filter(|_| rest.starts_with('{'));
+                        let c = end
+                            .and_then(|end| hex_digits(&rest[1..end]))
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| error(text, at(i), "malformed unicode escape"))?;
+                        let mut buf = [0; 4];
+                        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        for _ in 0..end.unwrap_or(0) + 1 {
+                            chars.next();
+                        }
+                    }
+                    h if h.is_ascii_hexdigit() => {
+                        let l = chars
+                            .next()
+                            .and_then(|(_, l)| l.to_digit(16))
+                            .ok_or_else(|| error(text, at(i), "malformed escape"))?;
+                        let h = h.to_digit(16).expect("hex digit");
+                        bytes.push((h * 16 + l) as u8);
+                    }
+                    _ => return Err(error(text, at(i), "unknown escape")),
+                }
+            }
+            c if c < ' ' || c == '\u{7f}' => {
+                return Err(error(text, at(i), "control character in string"))
+            }
+            c => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    Err(error(text, start, "unclosed string"))
+}
+
+// The value of a non-empty run of hexadecimal digits with optional `_` separators.
+fn hex_digits(s: &str) -> Option<u32> {
+    let digits = s.bytes().all(|b| b.is_ascii_hexdigit() || b == b'_');
+    if !digits || s.is_empty() || s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+        return None;
+    }
+    u32::from_str_radix(&s.replace('_', ""), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let text = "(module ;; comment\n (; block (; nested ;) ;) $f \"a\\n\\u{1F600}\")";
+        let kinds: Vec<_> = tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::LParen,
+                TokenKind::Atom("module"),
+                TokenKind::Id("f"),
+                TokenKind::String("a\n😀".as_bytes().to_vec()),
+                TokenKind::RParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn error_location() {
+        let e = tokenize("(module\n  (func \"abc))").unwrap_err();
+        assert_eq!((e.line, e.column), (2, 9));
+        assert_eq!(e.message, "unclosed string");
+    }
+}
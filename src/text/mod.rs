@@ -0,0 +1,7 @@
+pub mod conventions;
+pub mod instructions;
+pub mod lexer;
+pub mod modules;
+pub mod parser;
+pub mod types;
+pub mod values;
@@ -0,0 +1,543 @@
+use crate::errors::ParseError;
+use crate::structure::instructions::{Expr, Instr};
+use crate::structure::modules::{
+    Data, DataMode, Elem, ElemMode, Export, ExportDesc, Func, FuncIdx, Global, GlobalIdx, Import,
+    ImportDesc, LocalIdx, Mem, MemIdx, Module, Start, Table, TableIdx,
+};
+use crate::structure::types::{Limits, MemType, RefType, TableType};
+use crate::structure::values::{Byte, Name};
+use crate::text::conventions::{Context, Ids};
+use crate::text::parser::Parser;
+
+const PAGE_SIZE: usize = 65536;
+
+// Modules: https://webassembly.github.io/spec/core/text/modules.html#modules
+// Parses a module in the text format. The fields may also be given without the
+// enclosing `(module ...)`.
+pub fn module(text: &str) -> Result<Module, ParseError> {
+    let mut p = Parser::new(text)?;
+    let mut module = Module::default();
+
+    let wrapped = p.peek_sexpr("module");
+    if wrapped {
+        p.lparen()?;
+        p.keyword("module")?;
+        module.names.module = p.id().map(|id| Name(id.to_string()));
+    }
+
+    let start = p.pos;
+    p.bind_fields()?;
+    p.pos = start;
+    while p.peek_lparen() {
+        p.field(&mut module)?;
+    }
+
+    if wrapped {
+        p.rparen()?;
+    }
+    if !p.is_empty() {
+        return Err(p.error("unexpected token"));
+    }
+
+    module.types = std::mem::take(&mut p.c.typedefs);
+    Ok(module)
+}
+
+impl<'a> Parser<'a> {
+    // Binds the identifiers of all fields up front, since fields can refer to later
+    // ones, and collects the type definitions, which type uses refer to.
+    fn bind_fields(&mut self) -> Result<(), ParseError> {
+        // Imports must come before any definition of a function, table, memory or global.
+        let mut defined = None;
+        while self.peek_lparen() {
+            self.lparen()?;
+            match self.any_keyword()? {
+                "type" => {
+                    let id = self.id();
+                    self.bind(|c| &mut c.types, "type", id)?;
+                    let functype = self.functype()?;
+                    self.c.typedefs.push(functype);
+                }
+                "import" => {
+                    self.name()?;
+                    self.name()?;
+                    self.lparen()?;
+                    let kind = self.any_keyword()?;
+                    let space = space(kind).ok_or_else(|| self.error("unexpected token"))?;
+                    let id = self.id();
+                    self.bind(space, kind, id)?;
+                    if let Some(kind) = defined {
+                        return Err(self.error(&format!("import after {}", kind)));
+                    }
+                    self.skip_sexpr()?;
+                }
+                kind @ ("func" | "table" | "memory" | "global") => {
+                    let id = self.id();
+                    self.bind(space(kind).expect("kind of definition"), kind, id)?;
+                    while self.peek_sexpr("export") {
+                        self.lparen()?;
+                        self.skip_sexpr()?;
+                    }
+                    if self.peek_sexpr("import") {
+                        if let Some(kind) = defined {
+                            return Err(self.error(&format!("import after {}", kind)));
+                        }
+                    } else {
+                        defined.get_or_insert(if kind == "func" { "function" } else { kind });
+
+                        // Inline element and data segments.
+                        if kind == "table" && self.peek_reftype() {
+                            self.pos += 1;
+                            if self.peek_sexpr("elem") {
+                                self.bind(|c| &mut c.elems, "elem", None)?;
+                            }
+                        }
+                        if kind == "memory" && self.peek_sexpr("data") {
+                            self.bind(|c| &mut c.datas, "data", None)?;
+                        }
+                    }
+                }
+                "elem" => {
+                    let id = self.id();
+                    self.bind(|c| &mut c.elems, "elem", id)?;
+                }
+                "data" => {
+                    let id = self.id();
+                    self.bind(|c| &mut c.datas, "data", id)?;
+                }
+                _ => {}
+            }
+            self.skip_sexpr()?;
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.lparen()?;
+        match self.any_keyword()? {
+            // Type definitions were collected when binding identifiers.
+            "type" => return self.skip_sexpr(),
+            "import" => self.import(module)?,
+            "func" => self.func(module)?,
+            "table" => self.table(module)?,
+            "memory" => self.memory(module)?,
+            "global" => self.global(module)?,
+            "export" => self.export(module)?,
+            "start" => self.start(module)?,
+            "elem" => self.elem(module)?,
+            "data" => self.data(module)?,
+            _ => return Err(self.error_at(self.pos - 1, "unknown field")),
+        }
+        self.rparen()
+    }
+
+    // Imports: https://webassembly.github.io/spec/core/text/modules.html#imports
+    fn import(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        let module_name = self.name()?;
+        let name = self.name()?;
+        self.lparen()?;
+        let desc = match self.any_keyword()? {
+            "func" => {
+                if let Some(id) = self.id() {
+                    let idx = count(module, "func");
+                    module
+                        .names
+                        .funcs
+                        .push((FuncIdx(idx), Name(id.to_string())));
+                }
+                ImportDesc::Func(self.typeuse()?.0)
+            }
+            kind => {
+                self.id();
+                match kind {
+                    "table" => ImportDesc::Table(self.tabletype()?),
+                    "memory" => ImportDesc::Mem(self.memtype()?),
+                    "global" => ImportDesc::Global(self.globaltype()?),
+                    _ => return Err(self.error_at(self.pos - 1, "unexpected token")),
+                }
+            }
+        };
+        self.rparen()?;
+        module.imports.push(Import {
+            module: module_name,
+            name,
+            desc,
+        });
+        Ok(())
+    }
+
+    // Parses the inline exports `(export "name")*` of a field, which export `desc`.
+    fn inline_exports(&mut self, module: &mut Module, desc: ExportDesc) -> Result<(), ParseError> {
+        while self.peek_sexpr("export") {
+            self.lparen()?;
+            self.keyword("export")?;
+            let name = self.name()?;
+            self.rparen()?;
+            module.exports.push(Export {
+                name,
+                desc: desc.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    // Parses the inline import `(import "module" "name")` of a field, if any.
+    fn inline_import(&mut self) -> Result<Option<(Name, Name)>, ParseError> {
+        if !self.peek_sexpr("import") {
+            return Ok(None);
+        }
+        self.lparen()?;
+        self.keyword("import")?;
+        let module = self.name()?;
+        let name = self.name()?;
+        self.rparen()?;
+        Ok(Some((module, name)))
+    }
+
+    // Functions: https://webassembly.github.io/spec/core/text/modules.html#functions
+    fn func(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        let idx = count(module, "func");
+        if let Some(id) = self.id() {
+            module
+                .names
+                .funcs
+                .push((FuncIdx(idx), Name(id.to_string())));
+        }
+        self.inline_exports(module, ExportDesc::Func(FuncIdx(idx)))?;
+        if let Some((module_name, name)) = self.inline_import()? {
+            let (x, _) = self.typeuse()?;
+            module.imports.push(Import {
+                module: module_name,
+                name,
+                desc: ImportDesc::Func(x),
+            });
+            return Ok(());
+        }
+
+        let (x, params) = self.typeuse()?;
+        self.c.locals = Default::default();
+        for id in params {
+            self.bind(|c| &mut c.locals, "local", id)?;
+        }
+        let mut locals = vec![];
+        while self.peek_sexpr("local") {
+            self.lparen()?;
+            self.keyword("local")?;
+            if let Some(id) = self.id() {
+                self.bind(|c| &mut c.locals, "local", Some(id))?;
+                locals.push(self.valtype()?);
+            } else {
+                while !self.peek_rparen() {
+                    self.bind(|c| &mut c.locals, "local", None)?;
+                    locals.push(self.valtype()?);
+                }
+            }
+            self.rparen()?;
+        }
+        let body = self.expr()?;
+
+        let names = self.c.locals.names();
+        if !names.is_empty() {
+            let names = names
+                .into_iter()
+                .map(|(i, id)| (LocalIdx(i), Name(id.to_string())))
+                .collect();
+            module.names.locals.push((FuncIdx(idx), names));
+        }
+        module.funcs.push(Func {
+            type_: x,
+            locals,
+            body,
+            offsets: vec![],
+        });
+        Ok(())
+    }
+
+    // Tables: https://webassembly.github.io/spec/core/text/modules.html#tables
+    fn table(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.id();
+        let idx = count(module, "table");
+        self.inline_exports(module, ExportDesc::Table(TableIdx(idx)))?;
+        if let Some((module_name, name)) = self.inline_import()? {
+            module.imports.push(Import {
+                module: module_name,
+                name,
+                desc: ImportDesc::Table(self.tabletype()?),
+            });
+        } else if self.peek_reftype() {
+            // An inline element segment, which also gives the size of the table.
+            let type_ = self.reftype()?;
+            self.lparen()?;
+            self.keyword("elem")?;
+            let init = if self.peek_lparen() {
+                self.elem_exprs()?
+            } else {
+                self.funcidxs()?
+            };
+            self.rparen()?;
+
+            let n = init.len() as u32;
+            let limits = Limits {
+                min: n,
+                max: Some(n),
+            };
+            module.tables.push(Table {
+                type_: TableType(limits, type_),
+            });
+            module.elems.push(Elem {
+                type_,
+                init,
+                mode: ElemMode::Active {
+                    table: TableIdx(idx),
+                    offset: Expr(vec![Instr::I32Const(0)]),
+                },
+            });
+        } else {
+            module.tables.push(Table {
+                type_: self.tabletype()?,
+            });
+        }
+        Ok(())
+    }
+
+    // Memories: https://webassembly.github.io/spec/core/text/modules.html#memories
+    fn memory(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.id();
+        let idx = count(module, "memory");
+        self.inline_exports(module, ExportDesc::Mem(MemIdx(idx)))?;
+        if let Some((module_name, name)) = self.inline_import()? {
+            module.imports.push(Import {
+                module: module_name,
+                name,
+                desc: ImportDesc::Mem(self.memtype()?),
+            });
+        } else if self.peek_sexpr("data") {
+            // An inline data segment, which also gives the size of the memory.
+            self.lparen()?;
+            self.keyword("data")?;
+            let init = self.datastring()?;
+            self.rparen()?;
+
+            let n = init.len().div_ceil(PAGE_SIZE) as u32;
+            let limits = Limits {
+                min: n,
+                max: Some(n),
+            };
+            module.mems.push(Mem {
+                type_: MemType(limits),
+            });
+            module.datas.push(Data {
+                init,
+                mode: DataMode::Active {
+                    memory: MemIdx(idx),
+                    offset: Expr(vec![Instr::I32Const(0)]),
+                },
+            });
+        } else {
+            module.mems.push(Mem {
+                type_: self.memtype()?,
+            });
+        }
+        Ok(())
+    }
+
+    // Globals: https://webassembly.github.io/spec/core/text/modules.html#globals
+    fn global(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.id();
+        let idx = count(module, "global");
+        self.inline_exports(module, ExportDesc::Global(GlobalIdx(idx)))?;
+        if let Some((module_name, name)) = self.inline_import()? {
+            module.imports.push(Import {
+                module: module_name,
+                name,
+                desc: ImportDesc::Global(self.globaltype()?),
+            });
+        } else {
+            let type_ = self.globaltype()?;
+            let init = self.expr()?;
+            module.globals.push(Global { type_, init });
+        }
+        Ok(())
+    }
+
+    // Exports: https://webassembly.github.io/spec/core/text/modules.html#exports
+    fn export(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        let name = self.name()?;
+        self.lparen()?;
+        let desc = match self.any_keyword()? {
+            "func" => ExportDesc::Func(FuncIdx(self.index(|c| &c.funcs)?)),
+            "table" => ExportDesc::Table(TableIdx(self.index(|c| &c.tables)?)),
+            "memory" => ExportDesc::Mem(MemIdx(self.index(|c| &c.mems)?)),
+            "global" => ExportDesc::Global(GlobalIdx(self.index(|c| &c.globals)?)),
+            _ => return Err(self.error_at(self.pos - 1, "unexpected token")),
+        };
+        self.rparen()?;
+        module.exports.push(Export { name, desc });
+        Ok(())
+    }
+
+    // Start Function: https://webassembly.github.io/spec/core/text/modules.html#start-function
+    fn start(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        if module.start.is_some() {
+            return Err(self.error("multiple start sections"));
+        }
+        let func = FuncIdx(self.index(|c| &c.funcs)?);
+        module.start = Some(Start { func });
+        Ok(())
+    }
+
+    // Element Segments: https://webassembly.github.io/spec/core/text/modules.html#element-segments
+    fn elem(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.id();
+        let mode = if self.eat_keyword("declare") {
+            ElemMode::Declarative
+        } else if self.peek_lparen() || self.peek_u32() {
+            // The table index may also be given without `(table ...)`.
+            let table = if self.peek_sexpr("table") {
+                self.lparen()?;
+                self.keyword("table")?;
+                let x = self.index(|c| &c.tables)?;
+                self.rparen()?;
+                x
+            } else if self.peek_u32() {
+                self.u32()?
+            } else {
+                0
+            };
+            ElemMode::Active {
+                table: TableIdx(table),
+                offset: self.offset()?,
+            }
+        } else {
+            ElemMode::Passive
+        };
+
+        // Active segments may list function indices without `func`.
+        let (type_, init) = if self.eat_keyword("func") {
+            (RefType::FuncRef, self.funcidxs()?)
+        } else if self.peek_reftype() {
+            let type_ = self.reftype()?;
+            (type_, self.elem_exprs()?)
+        } else if matches!(mode, ElemMode::Active { .. }) {
+            (RefType::FuncRef, self.funcidxs()?)
+        } else {
+            return Err(self.error("unexpected token"));
+        };
+
+        module.elems.push(Elem { type_, init, mode });
+        Ok(())
+    }
+
+    // Parses `(offset instr*)`, or a single folded instruction.
+    fn offset(&mut self) -> Result<Expr, ParseError> {
+        if !self.peek_sexpr("offset") {
+            return self.folded_instr();
+        }
+        self.lparen()?;
+        self.keyword("offset")?;
+        let offset = self.expr()?;
+        self.rparen()?;
+        Ok(offset)
+    }
+
+    // Parses element expressions `(item instr*)`, or single folded instructions.
+    fn elem_exprs(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut init = vec![];
+        while self.peek_lparen() {
+            if self.peek_sexpr("item") {
+                self.lparen()?;
+                self.keyword("item")?;
+                init.push(self.expr()?);
+                self.rparen()?;
+            } else {
+                init.push(self.folded_instr()?);
+            }
+        }
+        Ok(init)
+    }
+
+    // Parses function indices as element expressions referencing them.
+    fn funcidxs(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut init = vec![];
+        while self.peek_index() {
+            let x = FuncIdx(self.index(|c| &c.funcs)?);
+            init.push(Expr(vec![Instr::RefFunc(x)]));
+        }
+        Ok(init)
+    }
+
+    // Data Segments: https://webassembly.github.io/spec/core/text/modules.html#data-segments
+    fn data(&mut self, module: &mut Module) -> Result<(), ParseError> {
+        self.id();
+        let mode = if self.peek_lparen() || self.peek_u32() {
+            // The memory index may also be given without `(memory ...)`.
+            let memory = if self.peek_sexpr("memory") {
+                self.lparen()?;
+                self.keyword("memory")?;
+                let x = self.index(|c| &c.mems)?;
+                self.rparen()?;
+                x
+            } else if self.peek_u32() {
+                self.u32()?
+            } else {
+                0
+            };
+            DataMode::Active {
+                memory: MemIdx(memory),
+                offset: self.offset()?,
+            }
+        } else {
+            DataMode::Passive
+        };
+
+        let init = self.datastring()?;
+        module.datas.push(Data { init, mode });
+        Ok(())
+    }
+
+    // Parses a sequence of strings, whose bytes are concatenated.
+    fn datastring(&mut self) -> Result<Vec<Byte>, ParseError> {
+        let mut bytes = vec![];
+        while self.peek_string() {
+            bytes.extend(self.string()?.into_iter().map(Byte));
+        }
+        Ok(bytes)
+    }
+}
+
+// The index space of the functions, tables, memories or globals named by `kind`.
+fn space<'a>(kind: &str) -> Option<for<'c> fn(&'c mut Context<'a>) -> &'c mut Ids<'a>> {
+    let space: for<'c> fn(&'c mut Context<'a>) -> &'c mut Ids<'a> = match kind {
+        "func" => |c| &mut c.funcs,
+        "table" => |c| &mut c.tables,
+        "memory" => |c| &mut c.mems,
+        "global" => |c| &mut c.globals,
+        _ => return None,
+    };
+    Some(space)
+}
+
+// The number of functions, tables, memories or globals named by `kind` in the module so
+// far, imported or defined, which is the index of the next one.
+fn count(module: &Module, kind: &str) -> u32 {
+    let imports = module
+        .imports
+        .iter()
+        .filter(|import| {
+            let import_kind = match import.desc {
+                ImportDesc::Func(_) => "func",
+                ImportDesc::Table(_) => "table",
+                ImportDesc::Mem(_) => "memory",
+                ImportDesc::Global(_) => "global",
+            };
+            import_kind == kind
+        })
+        .count();
+    let defined = match kind {
+        "func" => module.funcs.len(),
+        "table" => module.tables.len(),
+        "memory" => module.mems.len(),
+        _ => module.globals.len(),
+    };
+    (imports + defined) as u32
+}
@@ -0,0 +1,162 @@
+use crate::errors::ParseError;
+use crate::text::conventions::{Context, Ids};
+use crate::text::lexer::{error, tokenize, Token, TokenKind};
+
+// Bounds the nesting of blocks and folded instructions, which are parsed recursively.
+const MAX_NESTING: usize = 512;
+
+// A cursor over the tokens of a text module, and the identifier context the indices in
+// it are resolved against.
+pub struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token<'a>>,
+    pub pos: usize,
+    pub c: Context<'a>,
+    // The number of blocks and folded instructions the current token is nested in.
+    pub depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(text: &'a str) -> Result<Parser<'a>, ParseError> {
+        Ok(Parser {
+            text,
+            tokens: tokenize(text)?,
+            pos: 0,
+            c: Default::default(),
+            depth: 0,
+        })
+    }
+
+    // An error at the current token.
+    pub fn error(&self, message: &str) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    // An error at the token at `pos`, or at the end of the text if there is none.
+    pub fn error_at(&self, pos: usize, message: &str) -> ParseError {
+        let offset = self.tokens.get(pos).map_or(self.text.len(), |t| t.offset);
+        error(self.text, offset, message)
+    }
+
+    pub fn peek(&self) -> Option<&TokenKind<'a>> {
+        self.peek_at(0)
+    }
+
+    pub fn peek_at(&self, n: usize) -> Option<&TokenKind<'a>> {
+        self.tokens.get(self.pos + n).map(|t| &t.kind)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    pub fn peek_lparen(&self) -> bool {
+        self.peek() == Some(&TokenKind::LParen)
+    }
+
+    pub fn peek_rparen(&self) -> bool {
+        self.peek() == Some(&TokenKind::RParen)
+    }
+
+    pub fn lparen(&mut self) -> Result<(), ParseError> {
+        self.expect(TokenKind::LParen)
+    }
+
+    pub fn rparen(&mut self) -> Result<(), ParseError> {
+        self.expect(TokenKind::RParen)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), ParseError> {
+        if self.peek() != Some(&kind) {
+            return Err(self.error("unexpected token"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    pub fn peek_keyword(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(TokenKind::Atom(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    // Whether the next tokens open an s-expression starting with the keyword `kw`.
+    pub fn peek_sexpr(&self, kw: &str) -> bool {
+        self.peek_lparen() && self.peek_at(1) == Some(&TokenKind::Atom(kw))
+    }
+
+    // Parses any keyword.
+    pub fn any_keyword(&mut self) -> Result<&'a str, ParseError> {
+        let kw = self
+            .peek_keyword()
+            .ok_or_else(|| self.error("unexpected token"))?;
+        self.pos += 1;
+        Ok(kw)
+    }
+
+    pub fn keyword(&mut self, kw: &str) -> Result<(), ParseError> {
+        if !self.eat_keyword(kw) {
+            return Err(self.error("unexpected token"));
+        }
+        Ok(())
+    }
+
+    pub fn eat_keyword(&mut self, kw: &str) -> bool {
+        let found = self.peek_keyword() == Some(kw);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // Parses an optional identifier.
+    pub fn id(&mut self) -> Option<&'a str> {
+        match self.peek() {
+            Some(TokenKind::Id(id)) => {
+                let id = *id;
+                self.pos += 1;
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+    // Binds the next index of `space` to `id`, failing if the identifier is taken by
+    // another `kind` of definition.
+    pub fn bind(
+        &mut self,
+        space: for<'c> fn(&'c mut Context<'a>) -> &'c mut Ids<'a>,
+        kind: &str,
+        id: Option<&'a str>,
+    ) -> Result<u32, ParseError> {
+        match space(&mut self.c).push(id) {
+            Some(idx) => Ok(idx),
+            None => Err(self.error(&format!("duplicate {} ${}", kind, id.unwrap_or("")))),
+        }
+    }
+
+    // Enters a block or folded instruction, which is left by decrementing `depth`.
+    pub fn nest(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error("nesting too deep"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Skips the rest of the current s-expression, including its closing parenthesis.
+    pub fn skip_sexpr(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0;
+        while let Some(kind) = self.peek() {
+            match kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen if depth == 0 => return self.rparen(),
+                TokenKind::RParen => depth -= 1,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        Err(self.error("unexpected end"))
+    }
+}
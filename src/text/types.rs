@@ -0,0 +1,186 @@
+use crate::errors::ParseError;
+use crate::structure::modules::TypeIdx;
+use crate::structure::types::{
+    FuncType, GlobalType, Limits, MemType, Mut, NumType, RefType, ResultType, TableType, ValType,
+};
+use crate::text::parser::Parser;
+
+impl<'a> Parser<'a> {
+    // Value Types: https://webassembly.github.io/spec/core/text/types.html#value-types
+    pub fn valtype(&mut self) -> Result<ValType, ParseError> {
+        let t = match self.peek_keyword() {
+            Some("i32") => ValType::NumType(NumType::I32),
+            Some("i64") => ValType::NumType(NumType::I64),
+            Some("f32") => ValType::NumType(NumType::F32),
+            Some("f64") => ValType::NumType(NumType::F64),
+            Some("funcref") => ValType::RefType(RefType::FuncRef),
+            Some("externref") => ValType::RefType(RefType::ExternRef),
+            _ => return Err(self.error("unexpected token")),
+        };
+        self.pos += 1;
+        Ok(t)
+    }
+
+    // Reference Types: https://webassembly.github.io/spec/core/text/types.html#reference-types
+    pub fn reftype(&mut self) -> Result<RefType, ParseError> {
+        let t = match self.peek_keyword() {
+            Some("funcref") => RefType::FuncRef,
+            Some("externref") => RefType::ExternRef,
+            _ => return Err(self.error("unexpected token")),
+        };
+        self.pos += 1;
+        Ok(t)
+    }
+
+    pub fn peek_reftype(&self) -> bool {
+        matches!(self.peek_keyword(), Some("funcref" | "externref"))
+    }
+
+    pub fn heaptype(&mut self) -> Result<RefType, ParseError> {
+        let t = match self.peek_keyword() {
+            Some("func") => RefType::FuncRef,
+            Some("extern") => RefType::ExternRef,
+            _ => return Err(self.error("unexpected token")),
+        };
+        self.pos += 1;
+        Ok(t)
+    }
+
+    // Function Types: https://webassembly.github.io/spec/core/text/types.html#function-types
+    pub fn functype(&mut self) -> Result<FuncType, ParseError> {
+        self.lparen()?;
+        self.keyword("func")?;
+        let params = self.params()?;
+        let results = self.results()?;
+        if self.peek_sexpr("param") {
+            return Err(self.error("result before parameter"));
+        }
+        self.rparen()?;
+        let params = params.into_iter().map(|(_, t)| t).collect();
+        Ok(FuncType(ResultType(params), ResultType(results)))
+    }
+
+    // Parses any number of `(param id? t)` and `(param t*)`, with the identifiers of
+    // the parameters.
+    pub fn params(&mut self) -> Result<Vec<(Option<&'a str>, ValType)>, ParseError> {
+        let mut params = vec![];
+        while self.peek_sexpr("param") {
+            self.lparen()?;
+            self.keyword("param")?;
+            if let Some(id) = self.id() {
+                params.push((Some(id), self.valtype()?));
+            } else {
+                while !self.peek_rparen() {
+                    params.push((None, self.valtype()?));
+                }
+            }
+            self.rparen()?;
+        }
+        Ok(params)
+    }
+
+    // Parses any number of `(result t*)`.
+    pub fn results(&mut self) -> Result<Vec<ValType>, ParseError> {
+        let mut results = vec![];
+        while self.peek_sexpr("result") {
+            self.lparen()?;
+            self.keyword("result")?;
+            while !self.peek_rparen() {
+                results.push(self.valtype()?);
+            }
+            self.rparen()?;
+        }
+        Ok(results)
+    }
+
+    // Type Uses: https://webassembly.github.io/spec/core/text/modules.html#type-uses
+    // Returns the type index and the identifiers of the parameters. Without a type
+    // index, the first type definition matching the inline parameters and results is
+    // used, or a new one is added.
+    pub fn typeuse(&mut self) -> Result<(TypeIdx, Vec<Option<&'a str>>), ParseError> {
+        let x = if self.peek_sexpr("type") {
+            self.lparen()?;
+            self.keyword("type")?;
+            let x = self.index(|c| &c.types)?;
+            self.rparen()?;
+            Some(x)
+        } else {
+            None
+        };
+        let params = self.params()?;
+        let results = self.results()?;
+        if self.peek_sexpr("param") {
+            return Err(self.error("unexpected token"));
+        }
+        let (ids, params): (Vec<_>, Vec<_>) = params.into_iter().unzip();
+        let functype = FuncType(ResultType(params), ResultType(results));
+
+        match x {
+            Some(x) => {
+                // Out of range indices are left to validation.
+                let typedef = match self.c.typedefs.get(x as usize) {
+                    Some(typedef) => typedef,
+                    None => return Ok((TypeIdx(x), ids)),
+                };
+                if functype.0 .0.is_empty() && functype.1 .0.is_empty() {
+                    let ids = vec![None; typedef.0 .0.len()];
+                    return Ok((TypeIdx(x), ids));
+                }
+                if *typedef != functype {
+                    return Err(self.error("inline function type"));
+                }
+                Ok((TypeIdx(x), ids))
+            }
+            None => Ok((self.type_index(functype), ids)),
+        }
+    }
+
+    // The index of the first type definition equal to `functype`, which is added if
+    // there is none.
+    pub fn type_index(&mut self, functype: FuncType) -> TypeIdx {
+        let typedefs = &mut self.c.typedefs;
+        let x = match typedefs.iter().position(|t| *t == functype) {
+            Some(x) => x,
+            None => {
+                typedefs.push(functype);
+                typedefs.len() - 1
+            }
+        };
+        TypeIdx(x as u32)
+    }
+
+    // Limits: https://webassembly.github.io/spec/core/text/types.html#limits
+    pub fn limits(&mut self) -> Result<Limits, ParseError> {
+        let min = self.u32()?;
+        let max = if self.peek_u32() {
+            Some(self.u32()?)
+        } else {
+            None
+        };
+        Ok(Limits { min, max })
+    }
+
+    // Memory Types: https://webassembly.github.io/spec/core/text/types.html#memory-types
+    pub fn memtype(&mut self) -> Result<MemType, ParseError> {
+        Ok(MemType(self.limits()?))
+    }
+
+    // Table Types: https://webassembly.github.io/spec/core/text/types.html#table-types
+    pub fn tabletype(&mut self) -> Result<TableType, ParseError> {
+        let limits = self.limits()?;
+        Ok(TableType(limits, self.reftype()?))
+    }
+
+    // Global Types: https://webassembly.github.io/spec/core/text/types.html#global-types
+    pub fn globaltype(&mut self) -> Result<GlobalType, ParseError> {
+        if self.peek_sexpr("mut") {
+            self.lparen()?;
+            self.keyword("mut")?;
+            let t = self.valtype()?;
+            self.rparen()?;
+            Ok(GlobalType(Mut::Var, t))
+        } else {
+            Ok(GlobalType(Mut::Const, self.valtype()?))
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use crate::errors::ParseError;
+use crate::structure::values::Name;
+use crate::text::conventions::{Context, Ids};
+use crate::text::lexer::TokenKind;
+use crate::text::parser::Parser;
+
+impl<'a> Parser<'a> {
+    // Integers: https://webassembly.github.io/spec/core/text/values.html#integers
+    pub fn u32(&mut self) -> Result<u32, ParseError> {
+        if !self.peek_u32() {
+            return Err(self.error("unexpected token"));
+        }
+        let s = self.peek_keyword().unwrap_or_default();
+        let n = match uint(s) {
+            Some(n) => n,
+            None if is_integer(s) => return Err(self.error("constant out of range")),
+            None => return Err(self.error("unknown operator")),
+        };
+        self.pos += 1;
+        Ok(n)
+    }
+
+    // Integer literals may be written signed or unsigned, and are reinterpreted as
+    // signed.
+    pub fn i32(&mut self) -> Result<i32, ParseError> {
+        let (neg, n) = self.integer()?;
+        let n = match neg {
+            false if n <= u32::MAX as u64 => n as u32 as i32,
+            true if n <= 1 << 31 => (n as i64).wrapping_neg() as i32,
+            _ => return Err(self.error("constant out of range")),
+        };
+        self.pos += 1;
+        Ok(n)
+    }
+
+    pub fn i64(&mut self) -> Result<i64, ParseError> {
+        let (neg, n) = self.integer()?;
+        let n = match neg {
+            false => n as i64,
+            true if n <= 1 << 63 => (n as i64).wrapping_neg(),
+            _ => return Err(self.error("constant out of range")),
+        };
+        self.pos += 1;
+        Ok(n)
+    }
+
+    fn integer(&self) -> Result<(bool, u64), ParseError> {
+        let s = self
+            .peek_keyword()
+            .ok_or_else(|| self.error("unexpected token"))?;
+        match integer(s) {
+            Some(n) => Ok(n),
+            None if is_integer(s) => Err(self.error("constant out of range")),
+            None => Err(self.error("unknown operator")),
+        }
+    }
+
+    // Floating-Point: https://webassembly.github.io/spec/core/text/values.html#floating-point
+    pub fn f32(&mut self) -> Result<f32, ParseError> {
+        let bits = self.float(23, 8, |s| s.parse::<f32>().ok().map(|f| f.to_bits() as u64))?;
+        Ok(f32::from_bits(bits as u32))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, ParseError> {
+        let bits = self.float(52, 11, |s| s.parse::<f64>().ok().map(f64::to_bits))?;
+        Ok(f64::from_bits(bits))
+    }
+
+    // Parses a float with `mant` significand bits and `exp` exponent bits into its bits.
+    // Decimal literals are converted by `decimal`, which rounds to nearest like the spec.
+    fn float(
+        &mut self,
+        mant: u32,
+        exp: u32,
+        decimal: fn(&str) -> Option<u64>,
+    ) -> Result<u64, ParseError> {
+        let s = self
+            .peek_keyword()
+            .ok_or_else(|| self.error("unexpected token"))?;
+        let (neg, s) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let inf = ((1 << exp) - 1) << mant;
+        let bits = if s == "inf" {
+            Some(inf)
+        } else if s == "nan" {
+            Some(inf | 1 << (mant - 1))
+        } else if let Some(payload) = s.strip_prefix("nan:0x").filter(|p| is_hexnum(p)) {
+            match digits(payload, 16).filter(|p| *p != 0 && *p < 1 << mant) {
+                Some(p) => Some(inf | p),
+                None => return Err(self.error("constant out of range")),
+            }
+        } else if let Some(hex) = s.strip_prefix("0x") {
+            hex_float(hex).map(|(m, sticky, e)| round(m, sticky, e, mant, exp))
+        } else if decimal_float(s) {
+            decimal(&s.replace('_', ""))
+        } else {
+            None
+        };
+
+        let bits = bits.ok_or_else(|| self.error("unknown operator"))?;
+        if bits == inf && s != "inf" {
+            return Err(self.error("constant out of range"));
+        }
+        self.pos += 1;
+        Ok(bits | (neg as u64) << (mant + exp))
+    }
+
+    // Strings: https://webassembly.github.io/spec/core/text/values.html#strings
+    pub fn string(&mut self) -> Result<Vec<u8>, ParseError> {
+        match self.peek() {
+            Some(TokenKind::String(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.error("unexpected token")),
+        }
+    }
+
+    pub fn peek_string(&self) -> bool {
+        matches!(self.peek(), Some(TokenKind::String(_)))
+    }
+
+    // Names: https://webassembly.github.io/spec/core/text/values.html#names
+    pub fn name(&mut self) -> Result<Name, ParseError> {
+        let error = self.error("malformed UTF-8 encoding");
+        let name = String::from_utf8(self.string()?).map_err(|_| error)?;
+        Ok(Name(name))
+    }
+
+    // Indices: https://webassembly.github.io/spec/core/text/modules.html#indices
+    // An index is a number or an identifier bound in `space`.
+    pub fn index(
+        &mut self,
+        space: for<'c> fn(&'c Context<'a>) -> &'c Ids<'a>,
+    ) -> Result<u32, ParseError> {
+        match self.peek() {
+            Some(TokenKind::Id(id)) => {
+                let idx = space(&self.c)
+                    .get(id)
+                    .ok_or_else(|| self.error(&format!("unknown identifier ${}", id)))?;
+                self.pos += 1;
+                Ok(idx)
+            }
+            _ => self.u32(),
+        }
+    }
+
+    pub fn peek_index(&self) -> bool {
+        self.peek_index_at(0)
+    }
+
+    pub fn peek_index_at(&self, n: usize) -> bool {
+        match self.peek_at(n) {
+            Some(TokenKind::Id(_)) => true,
+            Some(TokenKind::Atom(s)) => s.starts_with(|c: char| c.is_ascii_digit()),
+            _ => false,
+        }
+    }
+
+    pub fn peek_u32(&self) -> bool {
+        self.peek_index() && !matches!(self.peek(), Some(TokenKind::Id(_)))
+    }
+}
+
+// The value of an unsigned integer literal that fits in a u32.
+pub fn uint(s: &str) -> Option<u32> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => digits(hex, 16)?,
+        None => digits(s, 10)?,
+    };
+    use std::convert::TryFrom;
+    u32::try_from(n).ok()
+}
+
+// The sign and magnitude of an integer literal, or `None` if it isn't one or its
+// magnitude doesn't fit in a u64.
+fn integer(s: &str) -> Option<(bool, u64)> {
+    let (neg, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => digits(hex, 16)?,
+        None => digits(s, 10)?,
+    };
+    Some((neg, n))
+}
+
+// The value of a non-empty run of digits with single `_` separators between them.
+fn digits(s: &str, radix: u32) -> Option<u64> {
+    if s.is_empty() || s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+        return None;
+    }
+    let mut n: u64 = 0;
+    for c in s.chars().filter(|c| *c != '_') {
+        let d = c.to_digit(radix)?;
+        n = n.checked_mul(radix as u64)?.checked_add(d as u64)?;
+    }
+    Some(n)
+}
+
+// Whether `s` has the form `num(.num?)?([eE][+-]?num)?` of an unsigned decimal float.
+fn decimal_float(s: &str) -> bool {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], Some(&mantissa[i + 1..])),
+        None => (mantissa, None),
+    };
+    is_num(int)
+        && frac.is_none_or(|f| f.is_empty() || is_num(f))
+        && exponent.is_none_or(|e| is_num(e.strip_prefix(['+', '-']).unwrap_or(e)))
+}
+
+// Whether `s` is a run of decimal digits with single `_` separators, of any length.
+fn is_num(s: &str) -> bool {
+    is_separated(s, |c| c.is_ascii_digit())
+}
+
+fn is_hexnum(s: &str) -> bool {
+    is_separated(s, |c| c.is_ascii_hexdigit())
+}
+
+fn is_separated(s: &str, digit: fn(&char) -> bool) -> bool {
+    !s.is_empty()
+        && !s.starts_with('_')
+        && !s.ends_with('_')
+        && !s.contains("__")
+        && s.chars().all(|c| digit(&c) || c == '_')
+}
+
+// Whether `s` has the form of an integer literal, of any magnitude.
+pub fn is_integer(s: &str) -> bool {
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    match s.strip_prefix("0x") {
+        Some(hex) => is_hexnum(hex),
+        None => is_num(s),
+    }
+}
+
+// Splits a hexadecimal float `hexnum(.hexnum?)?([pP][+-]?num)?`, after the `0x`, into a
+// significand `m` and exponent `e` with the value m * 2^e. `sticky` is set if digits
+// that didn't fit into `m` were nonzero.
+fn hex_float(s: &str) -> Option<(u64, bool, i64)> {
+    let (mantissa, exponent) = match s.find(['p', 'P']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    let valid = |s: &str| !s.starts_with('_') && !s.ends_with('_') && !s.contains("__");
+    if int.is_empty() || !valid(int) || !valid(frac) {
+        return None;
+    }
+
+    let mut m: u64 = 0;
+    let mut sticky = false;
+    let mut e: i64 = 0;
+    for (c, is_frac) in int
+        .chars()
+        .map(|c| (c, false))
+        .chain(frac.chars().map(|c| (c, true)))
+    {
+        if c == '_' {
+            continue;
+        }
+        let d = c.to_digit(16)? as u64;
+        if m >> 60 == 0 {
+            m = m << 4 | d;
+            if is_frac {
+                e -= 4;
+            }
+        } else {
+            sticky |= d != 0;
+            if !is_frac {
+                e += 4;
+            }
+        }
+    }
+
+    if let Some(exponent) = exponent {
+        let (neg, num) = match exponent.as_bytes().first() {
+            Some(b'-') => (true, &exponent[1..]),
+            Some(b'+') => (false, &exponent[1..]),
+            _ => (false, exponent),
+        };
+        // Larger exponents overflow or underflow any float anyway.
+        let n = match digits(num, 10) {
+            Some(n) => n.min(1 << 20) as i64,
+            None if is_num(num) => 1 << 20,
+            None => return None,
+        };
+        e += if neg { -n } else { n };
+    }
+
+    Some((m, sticky, e))
+}
+
+// Rounds m * 2^e, plus a nonzero fraction below m if `sticky` is set, to the nearest
+// float with `mant` significand bits and `exp` exponent bits, ties to even, and returns
+// its bits. Values too large for the format round to infinity.
+fn round(m: u64, sticky: bool, e: i64, mant: u32, exp: u32) -> u64 {
+    if m == 0 {
+        return 0;
+    }
+    let bias = (1 << (exp - 1)) - 1;
+    let emin = 1 - bias;
+
+    // Normalize so that the leading bit of `m` is bit 63, with exponent `top`.
+    let lz = m.leading_zeros();
+    let m = m << lz;
+    let mut top = e - lz as i64 + 63;
+
+    // Subnormals keep fewer bits of the significand.
+    let keep = if top >= emin {
+        mant as i64 + 1
+    } else {
+        mant as i64 + 1 - (emin - top)
+    };
+    let shift = 64 - keep;
+    let (mut q, half, rest) = match shift {
+        s if s > 64 => (0, false, true),
+        64 => (0, m >> 63 == 1, m << 1 != 0 || sticky),
+        s => (
+            m >> s,
+            (m >> (s - 1)) & 1 == 1,
+            m & ((1 << (s - 1)) - 1) != 0 || sticky,
+        ),
+    };
+    if half && (rest || q & 1 == 1) {
+        q += 1;
+    }
+
+    if top < emin {
+        // Rounding up the largest subnormal gives the bits of the smallest normal.
+        return q;
+    }
+    if q >> (mant + 1) != 0 {
+        q >>= 1;
+        top += 1;
+    }
+    if top > bias {
+        return ((1 << exp) - 1) << mant;
+    }
+    ((top + bias) as u64) << mant | (q & ((1 << mant) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::parser::Parser;
+
+    fn f32_bits(s: &str) -> u32 {
+        Parser::new(s).unwrap().f32().unwrap().to_bits()
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(Parser::new("0xffff_ffff").unwrap().i32().unwrap(), -1);
+        assert_eq!(
+            Parser::new("-0x8000_0000").unwrap().i32().unwrap(),
+            i32::MIN
+        );
+        let e = Parser::new("0x1_0000_0000").unwrap().i32().unwrap_err();
+        assert_eq!(e.message, "constant out of range");
+        let e = Parser::new("0x1_0000_000g").unwrap().i32().unwrap_err();
+        assert_eq!(e.message, "unknown operator");
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(f32_bits("0x1p-149"), 1);
+        // Hex floats round to nearest, ties to even.
+        assert_eq!(f32_bits("0x1.000001p0"), 0x3f80_0000);
+        assert_eq!(f32_bits("0x1.000003p0"), 0x3f80_0002);
+        assert_eq!(f32_bits("-nan:0x200000"), 0xffa0_0000);
+        assert_eq!(f32_bits("1e-45"), 1);
+        let e = Parser::new("0x1p128").unwrap().f32().unwrap_err();
+        assert_eq!(e.message, "constant out of range");
+    }
+}
@@ -0,0 +1,16 @@
+use crate::structure::types::{FuncType, GlobalType, MemType, RefType, TableType};
+
+// Contexts: https://webassembly.github.io/spec/core/valid/conventions.html#contexts
+// Locals, labels and the return type are tracked per function by the validator.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Context {
+    pub types: Vec<FuncType>,
+    pub funcs: Vec<FuncType>,
+    pub tables: Vec<TableType>,
+    pub mems: Vec<MemType>,
+    pub globals: Vec<GlobalType>,
+    pub elems: Vec<RefType>,
+    pub datas: usize,
+    // The functions that may be referenced by ref.func inside function bodies.
+    pub refs: Vec<u32>,
+}
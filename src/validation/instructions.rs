@@ -0,0 +1,929 @@
+use crate::errors::ValidationErrorKind::*;
+use crate::errors::{ValidationError, ValidationErrorKind};
+use crate::structure::instructions::{BlockType, Expr, Instr, MemArg};
+use crate::structure::modules::{Func, LabelIdx};
+use crate::structure::types::{Mut, NumType, RefType, ValType};
+use crate::validation::conventions::Context;
+use crate::validation::types::func_type;
+
+// The type of an operand, where None is the unknown type of an operand that comes from
+// an unreachable part of the stack.
+type Opd = Option<ValType>;
+
+const I32: ValType = ValType::NumType(NumType::I32);
+const I64: ValType = ValType::NumType(NumType::I64);
+const F32: ValType = ValType::NumType(NumType::F32);
+const F64: ValType = ValType::NumType(NumType::F64);
+const FUNCREF: ValType = ValType::RefType(RefType::FuncRef);
+
+// Bounds the nesting of blocks, loops and ifs, which are validated recursively.
+const MAX_NESTING: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+struct CtrlFrame {
+    is_loop: bool,
+    start_types: Vec<ValType>,
+    end_types: Vec<ValType>,
+    height: usize,
+    unreachable: bool,
+}
+
+impl CtrlFrame {
+    // A branch to a loop restarts it, while a branch to any other block leaves it.
+    fn label_types(&self) -> &[ValType] {
+        if self.is_loop {
+            &self.start_types
+        } else {
+            &self.end_types
+        }
+    }
+}
+
+// Validation Algorithm: https://webassembly.github.io/spec/core/appendix/algorithm.html
+// Instructions are visited in order, and `count` counts them across nested bodies, so
+// that an error is located at the byte offset of the instruction it was found at.
+struct Validator<'a> {
+    c: &'a Context,
+    locals: Vec<ValType>,
+    return_: Vec<ValType>,
+    // Whether only constant instructions are allowed.
+    constant: bool,
+    opds: Vec<Opd>,
+    ctrls: Vec<CtrlFrame>,
+    func: Option<u32>,
+    offsets: &'a [usize],
+    count: usize,
+}
+
+// Validates the body of the function with index `funcidx`.
+pub fn func(c: &Context, funcidx: u32, func: &Func) -> Result<(), ValidationError> {
+    let functype = func_type(c, func.type_.0).map_err(|mut e| {
+        e.func = Some(funcidx);
+        e
+    })?;
+    let mut locals = functype.0 .0.clone();
+    locals.extend(func.locals.iter().cloned());
+
+    let mut v = Validator {
+        c,
+        locals,
+        return_: functype.1 .0.clone(),
+        constant: false,
+        opds: vec![],
+        ctrls: vec![],
+        func: Some(funcidx),
+        offsets: &func.offsets,
+        count: 0,
+    };
+    v.expr(&func.body, &functype.1 .0)
+}
+
+// Validates a constant expression producing a value of type `t`.
+pub fn const_expr(c: &Context, expr: &Expr, t: &ValType) -> Result<(), ValidationError> {
+    let mut v = Validator {
+        c,
+        locals: vec![],
+        return_: vec![],
+        constant: true,
+        opds: vec![],
+        ctrls: vec![],
+        func: None,
+        offsets: &[],
+        count: 0,
+    };
+    v.expr(expr, std::slice::from_ref(t))
+}
+
+impl<'a> Validator<'a> {
+    fn error(&self, kind: ValidationErrorKind) -> ValidationError {
+        ValidationError {
+            func: self.func,
+            name: None,
+            offset: self
+                .count
+                .checked_sub(1)
+                .and_then(|i| self.offsets.get(i))
+                .copied(),
+            kind,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, results: &[ValType]) -> Result<(), ValidationError> {
+        self.push_ctrl(false, vec![], results.to_vec());
+        self.instrs(&expr.0)?;
+        self.pop_ctrl()?;
+        Ok(())
+    }
+
+    fn push_val(&mut self, t: Opd) {
+        self.opds.push(t);
+    }
+
+    fn pop_val(&mut self) -> Result<Opd, ValidationError> {
+        self.pop(None)
+    }
+
+    fn pop_expect(&mut self, expect: &ValType) -> Result<Opd, ValidationError> {
+        match self.pop(Some(expect))? {
+            Some(actual) if actual != *expect => Err(self.error(TypeMismatch {
+                expected: Some(expect.clone()),
+                found: Some(actual),
+            })),
+            _ => Ok(Some(expect.clone())),
+        }
+    }
+
+    // Pops an operand, where `expect` is the type it should have, if any, for reporting
+    // a missing one.
+    fn pop(&mut self, expect: Option<&ValType>) -> Result<Opd, ValidationError> {
+        let ctrl = self
+            .ctrls
+            .last()
+            .ok_or_else(|| self.error(EmptyControlStack))?;
+        if self.opds.len() == ctrl.height {
+            if ctrl.unreachable {
+                return Ok(None);
+            }
+            return Err(self.error(TypeMismatch {
+                expected: expect.cloned(),
+                found: None,
+            }));
+        }
+        Ok(self.opds.pop().flatten())
+    }
+
+    fn push_vals(&mut self, types: &[ValType]) {
+        for t in types {
+            self.push_val(Some(t.clone()));
+        }
+    }
+
+    fn pop_vals(&mut self, types: &[ValType]) -> Result<Vec<Opd>, ValidationError> {
+        let mut popped = vec![];
+        for t in types.iter().rev() {
+            popped.push(self.pop_expect(t)?);
+        }
+        popped.reverse();
+        Ok(popped)
+    }
+
+    fn push_ctrl(&mut self, is_loop: bool, start_types: Vec<ValType>, end_types: Vec<ValType>) {
+        let height = self.opds.len();
+        self.push_vals(&start_types);
+        self.ctrls.push(CtrlFrame {
+            is_loop,
+            start_types,
+            end_types,
+            height,
+            unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> Result<CtrlFrame, ValidationError> {
+        let ctrl = self
+            .ctrls
+            .last()
+            .ok_or_else(|| self.error(EmptyControlStack))?;
+        let end_types = ctrl.end_types.clone();
+        let height = ctrl.height;
+        self.pop_vals(&end_types)?;
+        if self.opds.len() != height {
+            return Err(self.error(TypeMismatch {
+                expected: None,
+                found: self.opds.last().cloned().flatten(),
+            }));
+        }
+        Ok(self.ctrls.pop().unwrap())
+    }
+
+    fn label_types(&self, l: &LabelIdx) -> Result<Vec<ValType>, ValidationError> {
+        let ctrl = self
+            .ctrls
+            .iter()
+            .rev()
+            .nth(l.0 as usize)
+            .ok_or_else(|| self.error(UnknownLabel(l.0)))?;
+        Ok(ctrl.label_types().to_vec())
+    }
+
+    fn unreachable(&mut self) {
+        let ctrl = self.ctrls.last_mut().unwrap();
+        self.opds.truncate(ctrl.height);
+        ctrl.unreachable = true;
+    }
+
+    fn block_type(&self, bt: &BlockType) -> Result<(Vec<ValType>, Vec<ValType>), ValidationError> {
+        match bt {
+            BlockType::Empty => Ok((vec![], vec![])),
+            BlockType::ValType(t) => Ok((vec![], vec![t.clone()])),
+            BlockType::TypeIdx(x) => {
+                let functype = func_type(self.c, x.0).map_err(|e| self.error(e.kind))?;
+                Ok((functype.0 .0.clone(), functype.1 .0.clone()))
+            }
+        }
+    }
+
+    // Blocks are validated here rather than in `instr`, so that the frames of the
+    // recursion stay small.
+    fn instrs(&mut self, instrs: &[Instr]) -> Result<(), ValidationError> {
+        for instr in instrs {
+            self.count += 1;
+            if self.constant && !self.is_constant(instr) {
+                return Err(self.error(ConstantExprRequired));
+            }
+
+            match instr {
+                Instr::Block(bt, body) => self.block(false, bt, body)?,
+                Instr::Loop(bt, body) => self.block(true, bt, body)?,
+                Instr::If(bt, then, else_) => self.if_(bt, then, else_)?,
+                _ => self.instr(instr)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Validates a block body, leaving its results on the operand stack.
+    fn block(
+        &mut self,
+        is_loop: bool,
+        bt: &BlockType,
+        body: &[Instr],
+    ) -> Result<(), ValidationError> {
+        if self.ctrls.len() > MAX_NESTING {
+            return Err(self.error(NestingTooDeep));
+        }
+        let (params, results) = self.block_type(bt)?;
+        self.pop_vals(&params)?;
+        self.push_ctrl(is_loop, params, results);
+        self.instrs(body)?;
+        let ctrl = self.pop_ctrl()?;
+        self.push_vals(&ctrl.end_types);
+        Ok(())
+    }
+
+    fn if_(
+        &mut self,
+        bt: &BlockType,
+        then: &[Instr],
+        else_: &[Instr],
+    ) -> Result<(), ValidationError> {
+        if self.ctrls.len() > MAX_NESTING {
+            return Err(self.error(NestingTooDeep));
+        }
+        self.pop_expect(&I32)?;
+        let (params, results) = self.block_type(bt)?;
+        self.pop_vals(&params)?;
+        self.push_ctrl(false, params.clone(), results.clone());
+        self.instrs(then)?;
+        self.pop_ctrl()?;
+        self.push_ctrl(false, params, results);
+        self.instrs(else_)?;
+        let ctrl = self.pop_ctrl()?;
+        self.push_vals(&ctrl.end_types);
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), ValidationError> {
+        match instr {
+            // Control Instructions
+            Instr::Unreachable => self.unreachable(),
+            Instr::Nop => {}
+            Instr::Br(l) => {
+                let types = self.label_types(l)?;
+                self.pop_vals(&types)?;
+                self.unreachable();
+            }
+            Instr::BrIf(l) => {
+                self.pop_expect(&I32)?;
+                let types = self.label_types(l)?;
+                self.pop_vals(&types)?;
+                self.push_vals(&types);
+            }
+            Instr::BrTable(ls, default) => {
+                self.pop_expect(&I32)?;
+                let default_types = self.label_types(default)?;
+                for l in ls {
+                    let types = self.label_types(l)?;
+                    if types.len() != default_types.len() {
+                        return Err(self.error(ArityMismatch {
+                            expected: default_types.len(),
+                            found: types.len(),
+                        }));
+                    }
+                    let vals = self.pop_vals(&types)?;
+                    for v in vals {
+                        self.push_val(v);
+                    }
+                }
+                self.pop_vals(&default_types)?;
+                self.unreachable();
+            }
+            Instr::Return => {
+                let types = self.return_.clone();
+                self.pop_vals(&types)?;
+                self.unreachable();
+            }
+            Instr::Call(x) => {
+                let functype = self
+                    .c
+                    .funcs
+                    .get(x.0 as usize)
+                    .ok_or_else(|| self.error(UnknownFunc(x.0)))?;
+                self.pop_vals(&functype.0 .0)?;
+                self.push_vals(&functype.1 .0);
+            }
+            Instr::CallIndirect(y, x) => {
+                let table = self
+                    .c
+                    .tables
+                    .get(x.0 as usize)
+                    .ok_or_else(|| self.error(UnknownTable(x.0)))?;
+                if table.1 != RefType::FuncRef {
+                    return Err(self.error(TypeMismatch {
+                        expected: Some(FUNCREF),
+                        found: Some(ValType::RefType(table.1)),
+                    }));
+                }
+                let functype = func_type(self.c, y.0).map_err(|e| self.error(e.kind))?;
+                self.pop_expect(&I32)?;
+                self.pop_vals(&functype.0 .0)?;
+                self.push_vals(&functype.1 .0);
+            }
+
+            // Parametric Instructions
+            Instr::Drop => {
+                self.pop_val()?;
+            }
+            Instr::Select => {
+                self.pop_expect(&I32)?;
+                let t1 = self.pop_val()?;
+                let t2 = self.pop_val()?;
+                let t = match (t1, t2) {
+                    (Some(t @ ValType::RefType(_)), _) | (_, Some(t @ ValType::RefType(_))) => {
+                        return Err(self.error(TypeMismatch {
+                            expected: None,
+                            found: Some(t),
+                        }))
+                    }
+                    (Some(t1), Some(t2)) if t1 != t2 => {
+                        return Err(self.error(TypeMismatch {
+                            expected: Some(t1),
+                            found: Some(t2),
+                        }))
+                    }
+                    (None, t) | (t, None) => t,
+                    (t, _) => t,
+                };
+                self.push_val(t);
+            }
+            Instr::SelectT(types) => {
+                let t = match types.as_slice() {
+                    [t] => t.clone(),
+                    _ => return Err(self.error(InvalidResultArity)),
+                };
+                self.pop_expect(&I32)?;
+                self.pop_expect(&t)?;
+                self.pop_expect(&t)?;
+                self.push_val(Some(t));
+            }
+
+            // Variable Instructions
+            Instr::LocalGet(x) => {
+                let t = self.local(x.0)?;
+                self.push_val(Some(t));
+            }
+            Instr::LocalSet(x) => {
+                let t = self.local(x.0)?;
+                self.pop_expect(&t)?;
+            }
+            Instr::LocalTee(x) => {
+                let t = self.local(x.0)?;
+                self.pop_expect(&t)?;
+                self.push_val(Some(t));
+            }
+            Instr::GlobalGet(x) => {
+                let global = self
+                    .c
+                    .globals
+                    .get(x.0 as usize)
+                    .ok_or_else(|| self.error(UnknownGlobal(x.0)))?;
+                if self.constant && global.0 != Mut::Const {
+                    return Err(self.error(ConstantExprRequired));
+                }
+                self.push_val(Some(global.1.clone()));
+            }
+            Instr::GlobalSet(x) => {
+                let global = self
+                    .c
+                    .globals
+                    .get(x.0 as usize)
+                    .ok_or_else(|| self.error(UnknownGlobal(x.0)))?;
+                if global.0 != Mut::Var {
+                    return Err(self.error(ImmutableGlobal(x.0)));
+                }
+                let t = global.1.clone();
+                self.pop_expect(&t)?;
+            }
+
+            // Table Instructions
+            Instr::TableGet(x) => {
+                let t = self.table(x.0)?;
+                self.pop_expect(&I32)?;
+                self.push_val(Some(t));
+            }
+            Instr::TableSet(x) => {
+                let t = self.table(x.0)?;
+                self.pop_expect(&t)?;
+                self.pop_expect(&I32)?;
+            }
+            Instr::TableSize(x) => {
+                self.table(x.0)?;
+                self.push_val(Some(I32));
+            }
+            Instr::TableGrow(x) => {
+                let t = self.table(x.0)?;
+                self.pop_expect(&I32)?;
+                self.pop_expect(&t)?;
+                self.push_val(Some(I32));
+            }
+            Instr::TableFill(x) => {
+                let t = self.table(x.0)?;
+                self.pop_expect(&I32)?;
+                self.pop_expect(&t)?;
+                self.pop_expect(&I32)?;
+            }
+            Instr::TableCopy(x, y) => {
+                let t1 = self.table(x.0)?;
+                let t2 = self.table(y.0)?;
+                if t1 != t2 {
+                    return Err(self.error(TypeMismatch {
+                        expected: Some(t1),
+                        found: Some(t2),
+                    }));
+                }
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            Instr::TableInit(x, y) => {
+                let t1 = self.table(x.0)?;
+                let t2 = self
+                    .c
+                    .elems
+                    .get(y.0 as usize)
+                    .ok_or_else(|| self.error(UnknownElem(y.0)))?;
+                if t1 != ValType::RefType(*t2) {
+                    return Err(self.error(TypeMismatch {
+                        expected: Some(t1),
+                        found: Some(ValType::RefType(*t2)),
+                    }));
+                }
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            Instr::ElemDrop(x) => {
+                if x.0 as usize >= self.c.elems.len() {
+                    return Err(self.error(UnknownElem(x.0)));
+                }
+            }
+
+            // Memory Instructions
+            Instr::I32Load(m) => self.load(m, 4, I32)?,
+            Instr::I64Load(m) => self.load(m, 8, I64)?,
+            Instr::F32Load(m) => self.load(m, 4, F32)?,
+            Instr::F64Load(m) => self.load(m, 8, F64)?,
+            Instr::I32Load8S(m) | Instr::I32Load8U(m) => self.load(m, 1, I32)?,
+            Instr::I32Load16S(m) | Instr::I32Load16U(m) => self.load(m, 2, I32)?,
+            Instr::I64Load8S(m) | Instr::I64Load8U(m) => self.load(m, 1, I64)?,
+            Instr::I64Load16S(m) | Instr::I64Load16U(m) => self.load(m, 2, I64)?,
+            Instr::I64Load32S(m) | Instr::I64Load32U(m) => self.load(m, 4, I64)?,
+            Instr::I32Store(m) => self.store(m, 4, I32)?,
+            Instr::I64Store(m) => self.store(m, 8, I64)?,
+            Instr::F32Store(m) => self.store(m, 4, F32)?,
+            Instr::F64Store(m) => self.store(m, 8, F64)?,
+            Instr::I32Store8(m) => self.store(m, 1, I32)?,
+            Instr::I32Store16(m) => self.store(m, 2, I32)?,
+            Instr::I64Store8(m) => self.store(m, 1, I64)?,
+            Instr::I64Store16(m) => self.store(m, 2, I64)?,
+            Instr::I64Store32(m) => self.store(m, 4, I64)?,
+            Instr::MemorySize => {
+                self.mem()?;
+                self.push_val(Some(I32));
+            }
+            Instr::MemoryGrow => {
+                self.mem()?;
+                self.pop_expect(&I32)?;
+                self.push_val(Some(I32));
+            }
+            Instr::MemoryFill | Instr::MemoryCopy => {
+                self.mem()?;
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            Instr::MemoryInit(x) => {
+                self.mem()?;
+                self.data(x.0)?;
+                self.pop_vals(&[I32, I32, I32])?;
+            }
+            Instr::DataDrop(x) => self.data(x.0)?,
+
+            // Reference Instructions
+            Instr::RefNull(t) => self.push_val(Some(ValType::RefType(*t))),
+            Instr::RefIsNull => match self.pop_val()? {
+                Some(t @ ValType::NumType(_)) => {
+                    return Err(self.error(TypeMismatch {
+                        expected: None,
+                        found: Some(t),
+                    }))
+                }
+                _ => self.push_val(Some(I32)),
+            },
+            Instr::RefFunc(x) => {
+                if x.0 as usize >= self.c.funcs.len() {
+                    return Err(self.error(UnknownFunc(x.0)));
+                }
+                if !self.c.refs.contains(&x.0) {
+                    return Err(self.error(UndeclaredFuncRef(x.0)));
+                }
+                self.push_val(Some(FUNCREF));
+            }
+
+            // Numeric Instructions
+            _ => {
+                let (params, results) =
+                    numeric(instr).ok_or_else(|| self.error(UnknownInstruction))?;
+                self.pop_vals(params)?;
+                self.push_vals(results);
+            }
+        }
+        Ok(())
+    }
+
+    // Constant Expressions: https://webassembly.github.io/spec/core/valid/instructions.html#constant-expressions
+    fn is_constant(&self, instr: &Instr) -> bool {
+        matches!(
+            instr,
+            Instr::I32Const(_)
+                | Instr::I64Const(_)
+                | Instr::F32Const(_)
+                | Instr::F64Const(_)
+                | Instr::RefNull(_)
+                | Instr::RefFunc(_)
+                | Instr::GlobalGet(_)
+        )
+    }
+
+    fn local(&self, x: u32) -> Result<ValType, ValidationError> {
+        let t = self
+            .locals
+            .get(x as usize)
+            .ok_or_else(|| self.error(UnknownLocal(x)))?;
+        Ok(t.clone())
+    }
+
+    fn table(&self, x: u32) -> Result<ValType, ValidationError> {
+        let table = self
+            .c
+            .tables
+            .get(x as usize)
+            .ok_or_else(|| self.error(UnknownTable(x)))?;
+        Ok(ValType::RefType(table.1))
+    }
+
+    fn mem(&self) -> Result<(), ValidationError> {
+        if self.c.mems.is_empty() {
+            return Err(self.error(UnknownMem(0)));
+        }
+        Ok(())
+    }
+
+    fn data(&self, x: u32) -> Result<(), ValidationError> {
+        if x as usize >= self.c.datas {
+            return Err(self.error(UnknownData(x)));
+        }
+        Ok(())
+    }
+
+    // Checks a memarg against the `n` bytes accessed, whose alignment must not be
+    // larger than natural.
+    fn memarg(&self, m: &MemArg, n: u32) -> Result<(), ValidationError> {
+        self.mem()?;
+        if m.align > n.trailing_zeros() {
+            return Err(self.error(Alignment));
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, m: &MemArg, n: u32, t: ValType) -> Result<(), ValidationError> {
+        self.memarg(m, n)?;
+        self.pop_expect(&I32)?;
+        self.push_val(Some(t));
+        Ok(())
+    }
+
+    fn store(&mut self, m: &MemArg, n: u32, t: ValType) -> Result<(), ValidationError> {
+        self.memarg(m, n)?;
+        self.pop_expect(&t)?;
+        self.pop_expect(&I32)?;
+        Ok(())
+    }
+}
+
+type Signature = (&'static [ValType], &'static [ValType]);
+
+// Returns the parameter and result types of a numeric instruction.
+fn numeric(instr: &Instr) -> Option<Signature> {
+    let signature: Signature = match instr {
+        Instr::I32Const(_) => (&[], &[I32]),
+        Instr::I64Const(_) => (&[], &[I64]),
+        Instr::F32Const(_) => (&[], &[F32]),
+        Instr::F64Const(_) => (&[], &[F64]),
+
+        Instr::I32Eqz => (&[I32], &[I32]),
+        Instr::I32Eq
+        | Instr::I32Ne
+        | Instr::I32LtS
+        | Instr::I32LtU
+        | Instr::I32GtS
+        | Instr::I32GtU
+        | Instr::I32LeS
+        | Instr::I32LeU
+        | Instr::I32GeS
+        | Instr::I32GeU => (&[I32, I32], &[I32]),
+        Instr::I64Eqz => (&[I64], &[I32]),
+        Instr::I64Eq
+        | Instr::I64Ne
+        | Instr::I64LtS
+        | Instr::I64LtU
+        | Instr::I64GtS
+        | Instr::I64GtU
+        | Instr::I64LeS
+        | Instr::I64LeU
+        | Instr::I64GeS
+        | Instr::I64GeU => (&[I64, I64], &[I32]),
+        Instr::F32Eq | Instr::F32Ne | Instr::F32Lt | Instr::F32Gt | Instr::F32Le | Instr::F32Ge => {
+            (&[F32, F32], &[I32])
+        }
+        Instr::F64Eq | Instr::F64Ne | Instr::F64Lt | Instr::F64Gt | Instr::F64Le | Instr::F64Ge => {
+            (&[F64, F64], &[I32])
+        }
+
+        Instr::I32Clz | Instr::I32Ctz | Instr::I32Popcnt => (&[I32], &[I32]),
+        Instr::I32Add
+        | Instr::I32Sub
+        | Instr::I32Mul
+        | Instr::I32DivS
+        | Instr::I32DivU
+        | Instr::I32RemS
+        | Instr::I32RemU
+        | Instr::I32And
+        | Instr::I32Or
+        | Instr::I32Xor
+        | Instr::I32Shl
+        | Instr::I32ShrS
+        | Instr::I32ShrU
+        | Instr::I32Rotl
+        | Instr::I32Rotr => (&[I32, I32], &[I32]),
+        Instr::I64Clz | Instr::I64Ctz | Instr::I64Popcnt => (&[I64], &[I64]),
+        Instr::I64Add
+        | Instr::I64Sub
+        | Instr::I64Mul
+        | Instr::I64DivS
+        | Instr::I64DivU
+        | Instr::I64RemS
+        | Instr::I64RemU
+        | Instr::I64And
+        | Instr::I64Or
+        | Instr::I64Xor
+        | Instr::I64Shl
+        | Instr::I64ShrS
+        | Instr::I64ShrU
+        | Instr::I64Rotl
+        | Instr::I64Rotr => (&[I64, I64], &[I64]),
+        Instr::F32Abs
+        | Instr::F32Neg
+        | Instr::F32Ceil
+        | Instr::F32Floor
+        | Instr::F32Trunc
+        | Instr::F32Nearest
+        | Instr::F32Sqrt => (&[F32], &[F32]),
+        Instr::F32Add
+        | Instr::F32Sub
+        | Instr::F32Mul
+        | Instr::F32Div
+        | Instr::F32Min
+        | Instr::F32Max
+        | Instr::F32Copysign => (&[F32, F32], &[F32]),
+        Instr::F64Abs
+        | Instr::F64Neg
+        | Instr::F64Ceil
+        | Instr::F64Floor
+        | Instr::F64Trunc
+        | Instr::F64Nearest
+        | Instr::F64Sqrt => (&[F64], &[F64]),
+        Instr::F64Add
+        | Instr::F64Sub
+        | Instr::F64Mul
+        | Instr::F64Div
+        | Instr::F64Min
+        | Instr::F64Max
+        | Instr::F64Copysign => (&[F64, F64], &[F64]),
+
+        Instr::I32WrapI64 => (&[I64], &[I32]),
+        Instr::I32TruncF32S
+        | Instr::I32TruncF32U
+        | Instr::I32TruncSatF32S
+        | Instr::I32TruncSatF32U => (&[F32], &[I32]),
+        Instr::I32TruncF64S
+        | Instr::I32TruncF64U
+        | Instr::I32TruncSatF64S
+        | Instr::I32TruncSatF64U => (&[F64], &[I32]),
+        Instr::I64ExtendI32S | Instr::I64ExtendI32U => (&[I32], &[I64]),
+        Instr::I64TruncF32S
+        | Instr::I64TruncF32U
+        | Instr::I64TruncSatF32S
+        | Instr::I64TruncSatF32U => (&[F32], &[I64]),
+        Instr::I64TruncF64S
+        | Instr::I64TruncF64U
+        | Instr::I64TruncSatF64S
+        | Instr::I64TruncSatF64U => (&[F64], &[I64]),
+        Instr::F32ConvertI32S | Instr::F32ConvertI32U => (&[I32], &[F32]),
+        Instr::F32ConvertI64S | Instr::F32ConvertI64U => (&[I64], &[F32]),
+        Instr::F32DemoteF64 => (&[F64], &[F32]),
+        Instr::F64ConvertI32S | Instr::F64ConvertI32U => (&[I32], &[F64]),
+        Instr::F64ConvertI64S | Instr::F64ConvertI64U => (&[I64], &[F64]),
+        Instr::F64PromoteF32 => (&[F32], &[F64]),
+        Instr::I32ReinterpretF32 => (&[F32], &[I32]),
+        Instr::I64ReinterpretF64 => (&[F64], &[I64]),
+        Instr::F32ReinterpretI32 => (&[I32], &[F32]),
+        Instr::F64ReinterpretI64 => (&[I64], &[F64]),
+        Instr::I32Extend8S | Instr::I32Extend16S => (&[I32], &[I32]),
+        Instr::I64Extend8S | Instr::I64Extend16S | Instr::I64Extend32S => (&[I64], &[I64]),
+
+        _ => return None,
+    };
+    Some(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::instructions::Instr::*;
+    use crate::structure::modules::{
+        Export, ExportDesc, FuncIdx, Global, GlobalIdx, LocalIdx, Module, TypeIdx,
+    };
+    use crate::structure::types::{FuncType, GlobalType, ResultType};
+    use crate::structure::values::Name;
+
+    // Validates `module` with an added function of type [] -> `results`.
+    fn validate(
+        mut module: Module,
+        results: Vec<ValType>,
+        body: Vec<Instr>,
+    ) -> Result<(), ValidationError> {
+        module
+            .types
+            .push(FuncType(ResultType(vec![]), ResultType(results)));
+        module.funcs.push(Func {
+            type_: TypeIdx(module.types.len() as u32 - 1),
+            locals: vec![],
+            body: Expr(body),
+            offsets: vec![],
+        });
+        module.validate()
+    }
+
+    fn kind(results: Vec<ValType>, body: Vec<Instr>) -> ValidationErrorKind {
+        validate(Default::default(), results, body)
+            .unwrap_err()
+            .kind
+    }
+
+    #[test]
+    fn type_mismatch_location() {
+        let mut module = Module::default();
+        module
+            .types
+            .push(FuncType(ResultType(vec![]), ResultType(vec![I32])));
+        module.funcs.push(Func {
+            type_: TypeIdx(0),
+            locals: vec![],
+            body: Expr(vec![Nop, I64Const(0)]),
+            offsets: vec![0x20, 0x21],
+        });
+        // The results are checked at the end of the body, which is located at the last
+        // instruction, the i64.const.
+        let err = module.validate().unwrap_err();
+        assert_eq!(
+            err.kind,
+            TypeMismatch {
+                expected: Some(I32),
+                found: Some(I64)
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid module: type mismatch (func 0, at offset 0x21)"
+        );
+
+        module.names.funcs.push((FuncIdx(0), Name("f".to_string())));
+        let err = module.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid module: type mismatch (func 0 $f, at offset 0x21)"
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let nested = |depth| {
+            let mut body = vec![];
+            for _ in 0..depth {
+                body = vec![Block(BlockType::Empty, body)];
+            }
+            validate(Default::default(), vec![], body)
+        };
+        assert!(nested(MAX_NESTING).is_ok());
+        assert_eq!(nested(MAX_NESTING + 1).unwrap_err().kind, NestingTooDeep);
+    }
+
+    #[test]
+    fn operand_stack() {
+        assert_eq!(
+            kind(vec![I32], vec![I32Const(1), I32Add]),
+            TypeMismatch {
+                expected: Some(I32),
+                found: None
+            }
+        );
+        assert_eq!(
+            kind(vec![], vec![I32Const(1)]),
+            TypeMismatch {
+                expected: None,
+                found: Some(I32)
+            }
+        );
+        // The stack is polymorphic after an unconditional branch.
+        assert!(validate(Default::default(), vec![I32], vec![Unreachable, I32Add]).is_ok());
+        assert_eq!(
+            kind(vec![I32], vec![Unreachable, I64Const(0), I32Add]),
+            TypeMismatch {
+                expected: Some(I32),
+                found: Some(I64)
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_indices() {
+        assert_eq!(
+            kind(vec![], vec![LocalGet(LocalIdx(0)), Drop]),
+            UnknownLocal(0)
+        );
+        let br = vec![Block(BlockType::Empty, vec![Br(LabelIdx(2))])];
+        assert_eq!(kind(vec![], br), UnknownLabel(2));
+        assert_eq!(kind(vec![], vec![Call(FuncIdx(3))]), UnknownFunc(3));
+        assert_eq!(
+            kind(vec![], vec![GlobalGet(GlobalIdx(0)), Drop]),
+            UnknownGlobal(0)
+        );
+        let load = vec![
+            I32Const(0),
+            I32Load(MemArg {
+                offset: 0,
+                align: 2,
+            }),
+            Drop,
+        ];
+        assert_eq!(kind(vec![], load), UnknownMem(0));
+    }
+
+    #[test]
+    fn globals() {
+        let module = |init| Module {
+            globals: vec![Global {
+                type_: GlobalType(Mut::Const, I32),
+                init: Expr(init),
+            }],
+            ..Default::default()
+        };
+        let set = vec![I32Const(1), GlobalSet(GlobalIdx(0))];
+        let err = validate(module(vec![I32Const(0)]), vec![], set).unwrap_err();
+        assert_eq!(err.kind, ImmutableGlobal(0));
+
+        let init = vec![I32Const(0), I32Const(1), I32Add];
+        let err = validate(module(init), vec![], vec![]).unwrap_err();
+        assert_eq!(err.kind, ConstantExprRequired);
+    }
+
+    #[test]
+    fn undeclared_func_ref() {
+        let body = vec![RefFunc(FuncIdx(0)), Drop];
+        assert_eq!(kind(vec![], body.clone()), UndeclaredFuncRef(0));
+
+        let module = Module {
+            exports: vec![Export {
+                name: Name("f".to_string()),
+                desc: ExportDesc::Func(FuncIdx(0)),
+            }],
+            ..Default::default()
+        };
+        assert!(validate(module, vec![], body).is_ok());
+    }
+}
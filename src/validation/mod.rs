@@ -0,0 +1,4 @@
+pub mod conventions;
+pub mod instructions;
+pub mod modules;
+pub mod types;
@@ -0,0 +1,254 @@
+use crate::errors::ValidationErrorKind::*;
+use crate::errors::{ValidationError, ValidationErrorKind};
+use crate::structure::instructions::{Expr, Instr};
+use crate::structure::modules::{DataMode, ElemMode, ExportDesc, FuncIdx, ImportDesc, Module};
+use crate::structure::types::{FuncType, NumType, ResultType, ValType};
+use crate::validation::conventions::Context;
+use crate::validation::instructions::{const_expr, func};
+use crate::validation::types::{func_type, mem_type, table_type};
+use std::collections::HashSet;
+
+const I32: ValType = ValType::NumType(NumType::I32);
+
+pub fn invalid(kind: ValidationErrorKind) -> ValidationError {
+    ValidationError {
+        func: None,
+        name: None,
+        offset: None,
+        kind,
+    }
+}
+
+impl Module {
+    // Errors in functions are reported with the name the module gives the function, if
+    // any.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_module().map_err(|mut e| {
+            let name = e.func.and_then(|f| self.names.func(&FuncIdx(f)));
+            e.name = name.map(|name| name.0.clone());
+            e
+        })
+    }
+
+    // Modules: https://webassembly.github.io/spec/core/valid/modules.html#valid-module
+    fn validate_module(&self) -> Result<(), ValidationError> {
+        let mut c = Context {
+            types: self.types.clone(),
+            ..Default::default()
+        };
+
+        for import in &self.imports {
+            match &import.desc {
+                ImportDesc::Func(x) => c.funcs.push(func_type(&c, x.0)?.clone()),
+                ImportDesc::Table(t) => {
+                    table_type(t)?;
+                    c.tables.push(t.clone());
+                }
+                ImportDesc::Mem(t) => {
+                    mem_type(t)?;
+                    c.mems.push(t.clone());
+                }
+                ImportDesc::Global(t) => c.globals.push(t.clone()),
+            }
+        }
+        for (i, f) in self.funcs.iter().enumerate() {
+            let functype = func_type(&c, f.type_.0).map_err(|mut e| {
+                e.func = Some((c.funcs.len() + i) as u32);
+                e
+            });
+            c.funcs.push(functype?.clone());
+        }
+        for table in &self.tables {
+            table_type(&table.type_)?;
+            c.tables.push(table.type_.clone());
+        }
+        for mem in &self.mems {
+            mem_type(&mem.type_)?;
+            c.mems.push(mem.type_.clone());
+        }
+        if c.mems.len() > 1 {
+            return Err(invalid(MultipleMemories));
+        }
+        c.elems = self.elems.iter().map(|e| e.type_).collect();
+        c.datas = self.datas.len();
+        c.refs = self.refs();
+
+        // Constant expressions may only refer to imported globals.
+        let c_ = c.clone();
+        for global in &self.globals {
+            const_expr(&c_, &global.init, &global.type_.1)?;
+            c.globals.push(global.type_.clone());
+        }
+
+        for elem in &self.elems {
+            let t = ValType::RefType(elem.type_);
+            for init in &elem.init {
+                const_expr(&c_, init, &t)?;
+            }
+            if let ElemMode::Active { table, offset } = &elem.mode {
+                let table = c
+                    .tables
+                    .get(table.0 as usize)
+                    .ok_or_else(|| invalid(UnknownTable(table.0)))?;
+                if table.1 != elem.type_ {
+                    return Err(invalid(TypeMismatch {
+                        expected: Some(ValType::RefType(table.1)),
+                        found: Some(ValType::RefType(elem.type_)),
+                    }));
+                }
+                const_expr(&c_, offset, &I32)?;
+            }
+        }
+
+        for data in &self.datas {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                if memory.0 as usize >= c.mems.len() {
+                    return Err(invalid(UnknownMem(memory.0)));
+                }
+                const_expr(&c_, offset, &I32)?;
+            }
+        }
+
+        let imported_funcs = c.funcs.len() - self.funcs.len();
+        for (i, f) in self.funcs.iter().enumerate() {
+            func(&c, (imported_funcs + i) as u32, f)?;
+        }
+
+        if let Some(start) = &self.start {
+            let functype = c
+                .funcs
+                .get(start.func.0 as usize)
+                .ok_or_else(|| invalid(UnknownFunc(start.func.0)))?;
+            if *functype != FuncType(ResultType(vec![]), ResultType(vec![])) {
+                return Err(invalid(StartFunction));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for export in &self.exports {
+            let (x, len, unknown): (_, _, fn(u32) -> ValidationErrorKind) = match &export.desc {
+                ExportDesc::Func(x) => (x.0, c.funcs.len(), UnknownFunc),
+                ExportDesc::Table(x) => (x.0, c.tables.len(), UnknownTable),
+                ExportDesc::Mem(x) => (x.0, c.mems.len(), UnknownMem),
+                ExportDesc::Global(x) => (x.0, c.globals.len(), UnknownGlobal),
+            };
+            if x as usize >= len {
+                return Err(invalid(unknown(x)));
+            }
+            if !names.insert(&export.name.0) {
+                return Err(invalid(DuplicateExportName(export.name.0.clone())));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Collects the functions referenced outside of function bodies, which are the ones
+    // ref.func may refer to.
+    fn refs(&self) -> Vec<u32> {
+        let mut refs = vec![];
+        let exprs = self
+            .globals
+            .iter()
+            .map(|g| &g.init)
+            .chain(self.elems.iter().flat_map(|e| &e.init));
+        for expr in exprs {
+            refs.extend(expr_refs(expr));
+        }
+        for export in &self.exports {
+            if let ExportDesc::Func(x) = &export.desc {
+                refs.push(x.0);
+            }
+        }
+        refs
+    }
+}
+
+fn expr_refs(expr: &Expr) -> Vec<u32> {
+    expr.0
+        .iter()
+        .filter_map(|instr| match instr {
+            Instr::RefFunc(x) => Some(x.0),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::modules::{Elem, Export, FuncIdx, Mem, MemIdx, Start, Table, TableIdx};
+    use crate::structure::types::{Limits, MemType, RefType, TableType};
+    use crate::structure::values::Name;
+
+    fn mem(min: u32, max: Option<u32>) -> Mem {
+        Mem {
+            type_: MemType(Limits { min, max }),
+        }
+    }
+
+    fn kind(module: Module) -> ValidationErrorKind {
+        let err = module.validate().unwrap_err();
+        assert_eq!(err.func, None);
+        err.kind
+    }
+
+    #[test]
+    fn memories() {
+        let module = Module {
+            mems: vec![mem(1, None), mem(1, None)],
+            ..Default::default()
+        };
+        assert_eq!(kind(module), MultipleMemories);
+        let module = Module {
+            mems: vec![mem(2, Some(1))],
+            ..Default::default()
+        };
+        assert_eq!(kind(module), LimitsMinGreaterThanMax);
+    }
+
+    #[test]
+    fn exports_and_start() {
+        let export = |name: &str| Export {
+            name: Name(name.to_string()),
+            desc: ExportDesc::Mem(MemIdx(0)),
+        };
+        let module = Module {
+            mems: vec![mem(1, None)],
+            exports: vec![export("m"), export("m")],
+            ..Default::default()
+        };
+        assert_eq!(kind(module), DuplicateExportName("m".to_string()));
+
+        let module = Module {
+            start: Some(Start { func: FuncIdx(0) }),
+            ..Default::default()
+        };
+        assert_eq!(kind(module), UnknownFunc(0));
+    }
+
+    #[test]
+    fn elem_type() {
+        let module = Module {
+            tables: vec![Table {
+                type_: TableType(Limits { min: 1, max: None }, RefType::ExternRef),
+            }],
+            elems: vec![Elem {
+                type_: RefType::FuncRef,
+                init: vec![],
+                mode: ElemMode::Active {
+                    table: TableIdx(0),
+                    offset: Expr(vec![Instr::I32Const(0)]),
+                },
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            kind(module),
+            TypeMismatch {
+                expected: Some(ValType::RefType(RefType::ExternRef)),
+                found: Some(ValType::RefType(RefType::FuncRef))
+            }
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use crate::errors::ValidationErrorKind::*;
+use crate::errors::{ValidationError, ValidationErrorKind};
+use crate::structure::types::{FuncType, Limits, MemType, TableType};
+use crate::validation::conventions::Context;
+use crate::validation::modules::invalid;
+
+// The maximum number of pages addressable with 32-bit memory indices.
+const MAX_PAGES: u32 = 65536;
+
+// Limits: https://webassembly.github.io/spec/core/valid/types.html#limits
+fn limits(limits: &Limits, k: u32, too_large: ValidationErrorKind) -> Result<(), ValidationError> {
+    if limits.min > k || limits.max.is_some_and(|max| max > k) {
+        return Err(invalid(too_large));
+    }
+    if limits.max.is_some_and(|max| max < limits.min) {
+        return Err(invalid(LimitsMinGreaterThanMax));
+    }
+    Ok(())
+}
+
+// Table Types: https://webassembly.github.io/spec/core/valid/types.html#table-types
+pub fn table_type(table_type: &TableType) -> Result<(), ValidationError> {
+    limits(&table_type.0, u32::MAX, TableSizeTooLarge)
+}
+
+// Memory Types: https://webassembly.github.io/spec/core/valid/types.html#memory-types
+pub fn mem_type(mem_type: &MemType) -> Result<(), ValidationError> {
+    limits(&mem_type.0, MAX_PAGES, MemSizeTooLarge)
+}
+
+// Looks up a function type by index.
+pub fn func_type(c: &Context, x: u32) -> Result<&FuncType, ValidationError> {
+    c.types
+        .get(x as usize)
+        .ok_or_else(|| invalid(UnknownType(x)))
+}
@@ -0,0 +1,491 @@
+// Runs the WebAssembly testsuite in tests/testsuite and prints how many commands of each
+// script pass. The run fails if any command fails, and errors have to have the messages
+// the scripts expect.
+//
+// Pass script names to run only those scripts and print each failure, for example
+// `cargo test --test spec -- i32.wast`. tests/update-testsuite.sh updates the scripts.
+//
+// Scripts: https://github.com/WebAssembly/spec/tree/main/interpreter#scripts
+use rasm::errors::{DecodeError, Error, Trap};
+use rasm::structure::types::{FuncType, NumType, RefType, ResultType, ValType};
+use rasm::{Engine, Instance, Linker, Module, Ref, Store, Val};
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use wast::core::{AbstractHeapType, HeapType, ModuleKind, NanPattern, WastArgCore, WastRetCore};
+use wast::lexer::Lexer;
+use wast::parser::{self, ParseBuffer};
+use wast::token::Index;
+use wast::{
+    QuoteWat, QuoteWatTest, Wast, WastArg, WastDirective, WastExecute, WastInvoke, WastRet, Wat,
+};
+
+const TESTSUITE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/testsuite");
+
+// The module registered as "spectest", which scripts import from.
+const SPECTEST: &str = r#"
+(module
+  (global (export "global_i32") i32 (i32.const 666))
+  (global (export "global_i64") i64 (i64.const 666))
+  (global (export "global_f32") f32 (f32.const 666.6))
+  (global (export "global_f64") f64 (f64.const 666.6))
+  (table (export "table") 10 20 funcref)
+  (memory (export "memory") 1 2))
+"#;
+
+fn main() {
+    let filters: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(TESTSUITE)
+        .expect("testsuite directory")
+        .map(|entry| entry.expect("testsuite entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wast"))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            filters.is_empty() || filters.iter().any(|f| name.contains(f.as_str()))
+        })
+        .collect();
+    paths.sort();
+    let verbose = !filters.is_empty();
+
+    // Panics are counted as failures, so keep their messages out of the summary.
+    panic::set_hook(Box::new(|_| {}));
+
+    let summary = run_all(&paths, verbose);
+
+    println!("total: {}/{} passed", summary.passed, summary.total);
+    if summary.passed != summary.total {
+        std::process::exit(1);
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    total: usize,
+}
+
+fn run_all(paths: &[PathBuf], verbose: bool) -> Summary {
+    let mut summary = Summary::default();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy();
+        match run_file(path, verbose) {
+            Ok(s) => {
+                println!("{:<32} {:>5}/{:<5}", name, s.passed, s.total);
+                summary.passed += s.passed;
+                summary.total += s.total;
+            }
+            // A script that can't be parsed counts as one failed command.
+            Err(e) => {
+                println!("{:<32} error: {}", name, e);
+                summary.total += 1;
+            }
+        }
+    }
+    summary
+}
+
+fn run_file(path: &Path, verbose: bool) -> Result<Summary, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    // names.wast exports names made of such characters.
+    let mut lexer = Lexer::new(&text);
+    lexer.allow_confusing_unicode(true);
+    let buf = ParseBuffer::new_with_lexer(lexer).map_err(|e| e.to_string())?;
+    let wast = parser::parse::<Wast>(&buf).map_err(|e| e.to_string())?;
+
+    let mut runner = Runner::new(&text);
+    let mut summary = Summary::default();
+    for directive in wast.directives {
+        let (line, col) = directive.span().linecol_in(&text);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| runner.run(directive)))
+            .unwrap_or_else(|_| Err("panicked".to_string()));
+        summary.total += 1;
+        match result {
+            Ok(()) => summary.passed += 1,
+            Err(e) if verbose => println!("  {}:{}: {}", line + 1, col + 1, e),
+            Err(_) => {}
+        }
+    }
+    Ok(summary)
+}
+
+// The state of a script: the instances defined so far, and the most recent one, which
+// commands without a module name refer to.
+struct Runner<'a> {
+    text: &'a str,
+    engine: Engine,
+    store: Store,
+    linker: Linker,
+    instances: HashMap<String, Instance>,
+    current: Option<Instance>,
+}
+
+impl<'a> Runner<'a> {
+    fn new(text: &'a str) -> Runner<'a> {
+        let engine = Engine::new();
+        let mut store = Store::new(&engine);
+        let mut linker = Linker::new(&engine);
+
+        let module = Module::from_wat(&engine, SPECTEST).expect("spectest module");
+        let spectest = Instance::new(&mut store, &module).expect("spectest instance");
+        linker.instance(&store, "spectest", spectest);
+
+        let i32_ = ValType::NumType(NumType::I32);
+        let i64_ = ValType::NumType(NumType::I64);
+        let f32_ = ValType::NumType(NumType::F32);
+        let f64_ = ValType::NumType(NumType::F64);
+        let prints = [
+            ("print", vec![]),
+            ("print_i32", vec![i32_.clone()]),
+            ("print_i64", vec![i64_]),
+            ("print_f32", vec![f32_.clone()]),
+            ("print_f64", vec![f64_.clone()]),
+            ("print_i32_f32", vec![i32_, f32_]),
+            ("print_f64_f64", vec![f64_.clone(), f64_]),
+        ];
+        for (name, params) in prints {
+            let ty = FuncType(ResultType(params), ResultType(vec![]));
+            linker.func("spectest", name, ty, |_, _| Ok(vec![]));
+        }
+
+        Runner {
+            text,
+            engine,
+            store,
+            linker,
+            instances: HashMap::new(),
+            current: None,
+        }
+    }
+
+    fn run(&mut self, directive: WastDirective) -> Result<(), String> {
+        match directive {
+            WastDirective::Module(module) => {
+                let name = module.name().map(|id| id.name().to_string());
+                let module = self.compile(module)?.map_err(|e| e.to_string())?;
+                let inst = self.instantiate(&module).map_err(|e| e.to_string())?;
+                if let Some(name) = name {
+                    self.instances.insert(name, inst);
+                }
+                self.current = Some(inst);
+            }
+            WastDirective::Register { name, module, .. } => {
+                let inst = self.instance(module.map(|id| id.name()))?;
+                self.linker.instance(&self.store, name, inst);
+            }
+            WastDirective::Invoke(invoke) => {
+                self.invoke(invoke)?;
+            }
+            WastDirective::AssertReturn { exec, results, .. } => {
+                let actual = match exec {
+                    WastExecute::Invoke(invoke) => self.invoke(invoke)?,
+                    WastExecute::Get { module, global, .. } => {
+                        let inst = self.instance(module.map(|id| id.name()))?;
+                        let global = inst
+                            .get_export(&self.store, global)
+                            .and_then(|e| e.into_global())
+                            .ok_or_else(|| format!("unknown global {}", global))?;
+                        vec![global.get(&self.store)]
+                    }
+                    WastExecute::Wat(module) => {
+                        let module = self.compile(QuoteWat::Wat(module))?;
+                        module
+                            .and_then(|module| self.instantiate(&module))
+                            .map_err(|e| e.to_string())?;
+                        vec![]
+                    }
+                };
+                if actual.len() != results.len()
+                    || !actual
+                        .iter()
+                        .zip(&results)
+                        .all(|(v, r)| self.ret_matches(v, r))
+                {
+                    return Err(format!("expected {:?}, found {:?}", results, actual));
+                }
+            }
+            WastDirective::AssertTrap { exec, message, .. } => {
+                let result = match exec {
+                    WastExecute::Invoke(invoke) => self.try_invoke(invoke).map(|_| ()),
+                    WastExecute::Wat(module) => self
+                        .compile(QuoteWat::Wat(module))?
+                        .and_then(|module| self.instantiate(&module))
+                        .map(|_| ()),
+                    WastExecute::Get { .. } => return Err("expected a trap".to_string()),
+                };
+                match result {
+                    Err(Error::Trap(trap)) => check_message(&trap.to_string(), message)?,
+                    result => {
+                        return Err(format!(
+                            "expected trap {:?}, found {:?}",
+                            message,
+                            result.map(|_| ())
+                        ))
+                    }
+                }
+            }
+            WastDirective::AssertExhaustion { call, .. } => match self.try_invoke(call) {
+                Err(Error::Trap(Trap::CallStackExhausted)) => {}
+                result => return Err(format!("expected stack exhaustion, found {:?}", result)),
+            },
+            WastDirective::AssertMalformed {
+                module, message, ..
+            } => match self.compile(module)? {
+                Err(Error::Decode(e)) => check_message(&e.to_string(), message)?,
+                Err(Error::Parse(e)) => check_message(&e.message, message)?,
+                result => {
+                    return Err(format!(
+                        "expected malformed {:?}, found {:?}",
+                        message,
+                        result.map(|_| ())
+                    ))
+                }
+            },
+            WastDirective::AssertInvalid {
+                module, message, ..
+            } => match self.compile(module)? {
+                Err(Error::Validation(e)) => check_message(&e.kind.to_string(), message)?,
+                result => {
+                    return Err(format!(
+                        "expected invalid {:?}, found {:?}",
+                        message,
+                        result.map(|_| ())
+                    ))
+                }
+            },
+            WastDirective::AssertUnlinkable {
+                module, message, ..
+            } => {
+                let result = self
+                    .compile(QuoteWat::Wat(module))?
+                    .and_then(|module| self.instantiate(&module));
+                match result {
+                    Err(Error::Link(e)) => check_message(&e.to_string(), message)?,
+                    result => {
+                        return Err(format!(
+                            "expected unlinkable {:?}, found {:?}",
+                            message,
+                            result.map(|_| ())
+                        ))
+                    }
+                }
+            }
+            directive => return Err(format!("unsupported directive {:?}", directive.span())),
+        }
+        Ok(())
+    }
+
+    // Compiles a module of the script. Modules in text form are parsed from their source
+    // by rasm itself, and only modules given as binary are encoded by wast. The outer
+    // error is for modules wast can't encode.
+    fn compile(&self, module: QuoteWat) -> Result<Result<Module, Error>, String> {
+        let text = match module {
+            QuoteWat::Wat(Wat::Module(ref m)) if matches!(m.kind, ModuleKind::Text(_)) => {
+                sexpr(self.text, m.span.offset()).to_string()
+            }
+            mut module => match module.to_test().map_err(|e| e.to_string())? {
+                QuoteWatTest::Binary(bytes) => return Ok(Module::new(&self.engine, bytes)),
+                QuoteWatTest::Text(text) => match String::from_utf8(text) {
+                    Ok(text) => text,
+                    Err(_) => return Ok(Err(Error::Decode(DecodeError::InvalidUtf8))),
+                },
+            },
+        };
+        Ok(Module::from_wat(&self.engine, text))
+    }
+
+    fn instantiate(&mut self, module: &Module) -> Result<Instance, Error> {
+        self.linker.instantiate(&mut self.store, module)
+    }
+
+    fn instance(&self, name: Option<&str>) -> Result<Instance, String> {
+        match name {
+            Some(name) => self
+                .instances
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown module {}", name)),
+            None => self.current.ok_or_else(|| "no current module".to_string()),
+        }
+    }
+
+    fn invoke(&mut self, invoke: WastInvoke) -> Result<Vec<Val>, String> {
+        self.try_invoke(invoke).map_err(|e| e.to_string())
+    }
+
+    fn try_invoke(&mut self, invoke: WastInvoke) -> Result<Vec<Val>, Error> {
+        let inst = self
+            .instance(invoke.module.map(|id| id.name()))
+            .map_err(Error::InvalidParameters)?;
+        let func = inst
+            .get_func(&self.store, invoke.name)
+            .ok_or_else(|| Error::InvalidParameters(format!("unknown function {}", invoke.name)))?;
+        let args = invoke.args.iter().map(arg).collect::<Result<Vec<_>, _>>()?;
+        func.call(&mut self.store, &args)
+    }
+
+    fn ret_matches(&self, v: &Val, ret: &WastRet) -> bool {
+        match ret {
+            WastRet::Core(ret) => self.ret_core_matches(v, ret),
+            _ => false,
+        }
+    }
+
+    fn ret_core_matches(&self, v: &Val, ret: &WastRetCore) -> bool {
+        match (v, ret) {
+            (Val::I32(v), WastRetCore::I32(r)) => v == r,
+            (Val::I64(v), WastRetCore::I64(r)) => v == r,
+            (Val::F32(v), WastRetCore::F32(r)) => {
+                let bits = v.to_bits();
+                match r {
+                    NanPattern::CanonicalNan => bits & 0x7fff_ffff == 0x7fc0_0000,
+                    NanPattern::ArithmeticNan => bits & 0x7fc0_0000 == 0x7fc0_0000,
+                    NanPattern::Value(r) => bits == r.bits,
+                }
+            }
+            (Val::F64(v), WastRetCore::F64(r)) => {
+                let bits = v.to_bits();
+                match r {
+                    NanPattern::CanonicalNan => {
+                        bits & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000
+                    }
+                    NanPattern::ArithmeticNan => {
+                        bits & 0x7ff8_0000_0000_0000 == 0x7ff8_0000_0000_0000
+                    }
+                    NanPattern::Value(r) => bits == r.bits,
+                }
+            }
+            (Val::Ref(Ref::Null(t)), WastRetCore::RefNull(r)) => match r {
+                Some(r) => ref_type(r).is_ok_and(|r| *t == r),
+                None => true,
+            },
+            (Val::Ref(Ref::Extern(n)), WastRetCore::RefExtern(r)) => {
+                r.is_none_or(|r| *n == r as usize)
+            }
+            (Val::Ref(r), WastRetCore::RefFunc(idx)) => match idx {
+                None => matches!(r, Ref::Func(_)),
+                // An index refers to a function of the current instance.
+                Some(Index::Num(idx, _)) => self
+                    .current
+                    .and_then(|inst| inst.get_func_by_index(&self.store, *idx))
+                    .is_some_and(|func| Ref::from(func) == *r),
+                Some(Index::Id(_)) => false,
+            },
+            (v, WastRetCore::Either(rets)) => rets.iter().any(|r| self.ret_core_matches(v, r)),
+            _ => false,
+        }
+    }
+}
+
+// The source of the s-expression whose keyword is at `offset`, with its parentheses.
+// Without them, the script is an inline module and all of it is returned.
+fn sexpr(text: &str, offset: usize) -> &str {
+    let mut before = text[..offset].trim_end();
+    loop {
+        let line = before.rfind('\n').map_or(0, |i| i + 1);
+        if before.ends_with(";)") {
+            before = before[..before.rfind("(;").expect("block comment")].trim_end();
+        } else if let Some(i) = before[line..].find(";;") {
+            before = before[..line + i].trim_end();
+        } else {
+            break;
+        }
+    }
+    let start = match before.strip_suffix('(') {
+        Some(before) => before.len(),
+        None => return text,
+    };
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if text[i..].starts_with("(;") {
+            let mut nesting = 0;
+            while i < bytes.len() {
+                if text[i..].starts_with("(;") {
+                    nesting += 1;
+                    i += 2;
+                } else if text[i..].starts_with(";)") {
+                    nesting -= 1;
+                    i += 2;
+                    if nesting == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+            continue;
+        }
+        match bytes[i] {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &text[start..=i];
+                }
+            }
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                i += text[i..].find('\n').unwrap_or(text.len() - i);
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    &text[start..]
+}
+
+// The expected message only has to be a prefix of the actual one, as in the reference
+// interpreter.
+fn check_message(actual: &str, expected: &str) -> Result<(), String> {
+    if actual.starts_with(expected) {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, found {:?}", expected, actual))
+    }
+}
+
+fn arg(arg: &WastArg) -> Result<Val, Error> {
+    let v = match arg {
+        WastArg::Core(WastArgCore::I32(v)) => Val::I32(*v),
+        WastArg::Core(WastArgCore::I64(v)) => Val::I64(*v),
+        WastArg::Core(WastArgCore::F32(v)) => Val::F32(f32::from_bits(v.bits)),
+        WastArg::Core(WastArgCore::F64(v)) => Val::F64(f64::from_bits(v.bits)),
+        WastArg::Core(WastArgCore::RefNull(t)) => Val::Ref(Ref::Null(ref_type(t)?)),
+        WastArg::Core(WastArgCore::RefExtern(n)) => Val::Ref(Ref::Extern(*n as usize)),
+        arg => {
+            return Err(Error::InvalidParameters(format!(
+                "unsupported argument {:?}",
+                arg
+            )))
+        }
+    };
+    Ok(v)
+}
+
+fn ref_type(t: &HeapType) -> Result<RefType, Error> {
+    match t {
+        HeapType::Abstract {
+            ty: AbstractHeapType::Func,
+            ..
+        } => Ok(RefType::FuncRef),
+        HeapType::Abstract {
+            ty: AbstractHeapType::Extern,
+            ..
+        } => Ok(RefType::ExternRef),
+        t => Err(Error::InvalidParameters(format!(
+            "unsupported heap type {:?}",
+            t
+        ))),
+    }
+}
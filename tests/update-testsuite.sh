@@ -0,0 +1,17 @@
+#!/bin/sh
+# Replaces the scripts in tests/testsuite with the core scripts of the upstream
+# WebAssembly testsuite at the given revision, main by default, and records the revision
+# in tests/testsuite/REVISION. `cargo test --test spec` has to pass afterwards.
+set -e
+
+rev=${1:-main}
+dir=$(dirname "$0")/testsuite
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+git clone -q https://github.com/WebAssembly/testsuite.git "$tmp"
+git -C "$tmp" checkout -q "$rev"
+
+rm -f "$dir"/*.wast
+cp "$tmp"/*.wast "$tmp"/LICENSE "$tmp"/README.md "$dir"
+git -C "$tmp" rev-parse HEAD > "$dir"/REVISION